@@ -0,0 +1,821 @@
+#![forbid(unsafe_code)]
+
+use alloc::vec::{self, Vec};
+use core::mem;
+use crate::observer::{Event, NoObserver, Observer};
+use crate::traits::{Deque, Queue, Stack};
+
+// A doubly-linked list without `unsafe` or `RefCell`: the nodes live in a
+// `Vec` and point at each other by position. Freed slots are chained into a
+// free list and reused before the `Vec` grows.
+//
+// Pushing hands out an `Index`, a handle that stays valid for as long as its
+// element is in the list, so removing or inserting next to a known element is
+// O(1). Every slot counts how many times it has been emptied, and handles
+// carry that count, so a handle to an element that's gone is recognised as
+// stale even after its slot has been reused.
+
+// Marks the end of the list, and of the free list.
+const NIL: u32 = u32::MAX;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Index {
+  slot: u32,
+  generation: u32,
+}
+
+struct Slot<T> {
+  // `None` for free slots, whose `next` is the next free one.
+  elem: Option<T>,
+  generation: u32,
+  prev: u32,
+  next: u32,
+}
+
+pub struct List<T, O = NoObserver> {
+  slots: Vec<Slot<T>>,
+  head: u32,
+  tail: u32,
+  free: u32,
+  len: usize,
+  // Where new slots start counting, so that a handle into a slot `compact`
+  // got rid of doesn't come back to life when the slot is created again.
+  fresh_generation: u32,
+  observer: O,
+}
+
+impl<T> List<T> {
+  pub fn new() -> Self {
+    List::with_observer(NoObserver)
+  }
+
+  // Like in `unsafe_deque`, mutable access that bypasses the observer is only
+  // handed out when nobody is watching. That goes for everything working on
+  // handles too, since the events can't describe a change in the middle.
+  pub fn peek_front_mut(&mut self) -> Option<&mut T> {
+    self.elem_mut(self.head)
+  }
+
+  pub fn peek_back_mut(&mut self) -> Option<&mut T> {
+    self.elem_mut(self.tail)
+  }
+
+  // Handing out the elements in list order straight from the slots would
+  // take `unsafe`, so this allocates: it ranks the slots by following the
+  // links, then sorts a reference to every element into list order. That
+  // takes `len` entries, however many free slots there are.
+  pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+    let mut ranks = Vec::with_capacity(self.len);
+    let mut cur = self.head;
+    while cur != NIL {
+      ranks.push((cur, ranks.len()));
+      cur = self.slots[cur as usize].next;
+    }
+    ranks.sort_unstable();
+
+    // The occupied slots, in the same order as `ranks` now.
+    let elems = self.slots.iter_mut().filter_map(|slot| slot.elem.as_mut());
+    let mut elems: Vec<_> = ranks.into_iter().zip(elems)
+      .map(|((_, rank), elem)| (rank, elem))
+      .collect();
+    elems.sort_unstable_by_key(|&(rank, _)| rank);
+    IterMut(elems.into_iter())
+  }
+
+  pub fn get_mut(&mut self, index: Index) -> Option<&mut T> {
+    let slot = self.find(index)?;
+    self.elem_mut(slot)
+  }
+
+  // Takes out the element `index` refers to, if it's still there.
+  pub fn remove(&mut self, index: Index) -> Option<T> {
+    let slot = self.find(index)?;
+    Some(self.unlink(slot))
+  }
+
+  // Puts `elem` right after the element `index` refers to, or hands it back
+  // if that one is gone.
+  pub fn insert_after(&mut self, index: Index, elem: T) -> Result<Index, T> {
+    match self.find(index) {
+      Some(slot) => Ok(self.link(elem, slot, self.slots[slot as usize].next)),
+      None => Err(elem),
+    }
+  }
+
+  pub fn insert_before(&mut self, index: Index, elem: T) -> Result<Index, T> {
+    match self.find(index) {
+      Some(slot) => Ok(self.link(elem, self.slots[slot as usize].prev, slot)),
+      None => Err(elem),
+    }
+  }
+
+  // Moves the elements to the start of the `Vec`, in list order, and gives
+  // back the memory of the free slots. Every handle changes: `relocated` gets
+  // each old one with its replacement, and the old ones are stale from then
+  // on.
+  pub fn compact(&mut self, mut relocated: impl FnMut(Index, Index)) {
+    let mut old = mem::take(&mut self.slots);
+    let mut slots = Vec::with_capacity(self.len);
+
+    let mut cur = self.head;
+    while cur != NIL {
+      let new = slots.len() as u32;
+      // Newer than any handle into the slot this one replaces.
+      let generation = old.get(new as usize)
+        .map_or(self.fresh_generation, |slot| slot.generation.wrapping_add(1));
+      let slot = &mut old[cur as usize];
+
+      slots.push(Slot {
+        elem: slot.elem.take(),
+        generation,
+        prev: if new == 0 { NIL } else { new - 1 },
+        next: if slot.next == NIL { NIL } else { new + 1 },
+      });
+      relocated(Index { slot: cur, generation: slot.generation }, Index { slot: new, generation });
+      cur = slot.next;
+    }
+
+    // Slots past the new end go away; if they come back, they start after
+    // every handle they ever had.
+    for slot in &old[slots.len().min(old.len())..] {
+      self.fresh_generation = self.fresh_generation.max(slot.generation.wrapping_add(1));
+    }
+
+    self.slots = slots;
+    self.head = if self.len == 0 { NIL } else { 0 };
+    self.tail = if self.len == 0 { NIL } else { self.len as u32 - 1 };
+    self.free = NIL;
+  }
+}
+
+impl<T, O: Observer<T>> List<T, O> {
+  pub fn with_observer(observer: O) -> Self {
+    List {
+      slots: Vec::new(),
+      head: NIL,
+      tail: NIL,
+      free: NIL,
+      len: 0,
+      fresh_generation: 0,
+      observer,
+    }
+  }
+
+  pub fn observer(&self) -> &O {
+    &self.observer
+  }
+
+  pub fn observer_mut(&mut self) -> &mut O {
+    &mut self.observer
+  }
+
+  pub fn push_front(&mut self, elem: T) -> Index {
+    let index = self.link(elem, NIL, self.head);
+    if O::ENABLED {
+      self.observer.notify(Event::PushedFront(self.slots[index.slot as usize].elem.as_ref().unwrap()));
+    }
+    index
+  }
+
+  pub fn push_back(&mut self, elem: T) -> Index {
+    let index = self.link(elem, self.tail, NIL);
+    if O::ENABLED {
+      self.observer.notify(Event::PushedBack(self.slots[index.slot as usize].elem.as_ref().unwrap()));
+    }
+    index
+  }
+
+  pub fn pop_front(&mut self) -> Option<T> {
+    if self.head == NIL {
+      return None;
+    }
+
+    let elem = self.unlink(self.head);
+    self.observer.notify(Event::PoppedFront);
+    Some(elem)
+  }
+
+  pub fn pop_back(&mut self) -> Option<T> {
+    if self.tail == NIL {
+      return None;
+    }
+
+    let elem = self.unlink(self.tail);
+    self.observer.notify(Event::PoppedBack);
+    Some(elem)
+  }
+
+  // Keeps the slots' memory and generations, so existing handles all go
+  // stale rather than pointing at whatever gets pushed next.
+  pub fn clear(&mut self) {
+    while self.head != NIL {
+      self.unlink(self.head);
+    }
+    self.observer.notify(Event::Cleared);
+  }
+
+  // Runs `f` on the element at `index`, counting from the front, and reports
+  // the change. Returns `None` if there's no such element.
+  pub fn update<R, F: FnOnce(&mut T) -> R>(&mut self, index: usize, f: F) -> Option<R> {
+    let mut cur = self.head;
+    for _ in 0..index {
+      if cur == NIL {
+        return None;
+      }
+      cur = self.slots[cur as usize].next;
+    }
+    if cur == NIL {
+      return None;
+    }
+
+    let elem = self.slots[cur as usize].elem.as_mut().unwrap();
+    let result = f(elem);
+    self.observer.notify(Event::Mutated(index, elem));
+    Some(result)
+  }
+
+  #[allow(clippy::should_implement_trait)]
+  pub fn into_iter(self) -> IntoIter<T, O> {
+    IntoIter(self)
+  }
+
+  // Puts `elem` in a free slot, or a new one, between `prev` and `next`.
+  fn link(&mut self, elem: T, prev: u32, next: u32) -> Index {
+    let slot = if self.free != NIL {
+      let slot = self.free;
+      self.free = self.slots[slot as usize].next;
+      slot
+    } else {
+      assert!(self.slots.len() < NIL as usize, "an arena list can't hold more than {} elements", NIL);
+      self.slots.push(Slot { elem: None, generation: self.fresh_generation, prev: NIL, next: NIL });
+      self.slots.len() as u32 - 1
+    };
+
+    let node = &mut self.slots[slot as usize];
+    node.elem = Some(elem);
+    node.prev = prev;
+    node.next = next;
+    let generation = node.generation;
+
+    if prev == NIL {
+      self.head = slot;
+    } else {
+      self.slots[prev as usize].next = slot;
+    }
+    if next == NIL {
+      self.tail = slot;
+    } else {
+      self.slots[next as usize].prev = slot;
+    }
+
+    self.len += 1;
+    Index { slot, generation }
+  }
+
+  // Frees `slot`, which has to hold an element, and hands the element over.
+  fn unlink(&mut self, slot: u32) -> T {
+    let node = &mut self.slots[slot as usize];
+    let elem = node.elem.take().unwrap();
+    let (prev, next) = (node.prev, node.next);
+    node.generation = node.generation.wrapping_add(1);
+    node.next = self.free;
+    self.free = slot;
+
+    if prev == NIL {
+      self.head = next;
+    } else {
+      self.slots[prev as usize].next = next;
+    }
+    if next == NIL {
+      self.tail = prev;
+    } else {
+      self.slots[next as usize].prev = prev;
+    }
+
+    self.len -= 1;
+    elem
+  }
+}
+
+impl<T, O> List<T, O> {
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  pub fn peek_front(&self) -> Option<&T> {
+    self.elem(self.head)
+  }
+
+  pub fn peek_back(&self) -> Option<&T> {
+    self.elem(self.tail)
+  }
+
+  pub fn iter(&self) -> Iter<'_, T> {
+    Iter { slots: &self.slots, front: self.head, back: self.tail, len: self.len }
+  }
+
+  // Whether `index` still refers to an element.
+  pub fn contains(&self, index: Index) -> bool {
+    self.find(index).is_some()
+  }
+
+  pub fn get(&self, index: Index) -> Option<&T> {
+    self.elem(self.find(index)?)
+  }
+
+  // Handles for walking the list without holding on to it.
+  pub fn front_index(&self) -> Option<Index> {
+    self.index(self.head)
+  }
+
+  pub fn back_index(&self) -> Option<Index> {
+    self.index(self.tail)
+  }
+
+  pub fn next_index(&self, index: Index) -> Option<Index> {
+    self.index(self.slots[self.find(index)? as usize].next)
+  }
+
+  pub fn prev_index(&self, index: Index) -> Option<Index> {
+    self.index(self.slots[self.find(index)? as usize].prev)
+  }
+
+  // The slot `index` refers to, unless it's stale.
+  fn find(&self, index: Index) -> Option<u32> {
+    let slot = self.slots.get(index.slot as usize)?;
+    if slot.generation == index.generation && slot.elem.is_some() {
+      Some(index.slot)
+    } else {
+      None
+    }
+  }
+
+  fn index(&self, slot: u32) -> Option<Index> {
+    if slot == NIL {
+      None
+    } else {
+      Some(Index { slot, generation: self.slots[slot as usize].generation })
+    }
+  }
+
+  fn elem(&self, slot: u32) -> Option<&T> {
+    self.slots.get(slot as usize)?.elem.as_ref()
+  }
+
+  fn elem_mut(&mut self, slot: u32) -> Option<&mut T> {
+    self.slots.get_mut(slot as usize)?.elem.as_mut()
+  }
+}
+
+impl<T> Default for List<T> {
+  fn default() -> Self {
+    List::new()
+  }
+}
+
+pub struct IntoIter<T, O = NoObserver>(List<T, O>);
+
+impl<T, O: Observer<T>> Iterator for IntoIter<T, O> {
+  type Item = T;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.0.pop_front()
+  }
+}
+
+impl<T, O: Observer<T>> DoubleEndedIterator for IntoIter<T, O> {
+  fn next_back(&mut self) -> Option<Self::Item> {
+    self.0.pop_back()
+  }
+}
+
+pub struct Iter<'a, T> {
+  slots: &'a [Slot<T>],
+  front: u32,
+  back: u32,
+  len: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+  type Item = &'a T;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.len == 0 {
+      return None;
+    }
+
+    self.len -= 1;
+    let slot = &self.slots[self.front as usize];
+    self.front = slot.next;
+    slot.elem.as_ref()
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.len, Some(self.len))
+  }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+  fn next_back(&mut self) -> Option<Self::Item> {
+    if self.len == 0 {
+      return None;
+    }
+
+    self.len -= 1;
+    let slot = &self.slots[self.back as usize];
+    self.back = slot.prev;
+    slot.elem.as_ref()
+  }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+// Each element with its position in the list, in list order.
+pub struct IterMut<'a, T>(vec::IntoIter<(usize, &'a mut T)>);
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+  type Item = &'a mut T;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.0.next().map(|(_, elem)| elem)
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.0.size_hint()
+  }
+}
+
+impl<'a, T> DoubleEndedIterator for IterMut<'a, T> {
+  fn next_back(&mut self) -> Option<Self::Item> {
+    self.0.next_back().map(|(_, elem)| elem)
+  }
+}
+
+impl<'a, T> ExactSizeIterator for IterMut<'a, T> {}
+
+impl<T, O: Observer<T>> Stack<T> for List<T, O> {
+  fn push(&mut self, elem: T) {
+    self.push_front(elem);
+  }
+
+  fn pop(&mut self) -> Option<T> {
+    self.pop_front()
+  }
+}
+
+impl<T, O: Observer<T>> Queue<T> for List<T, O> {
+  fn enqueue(&mut self, elem: T) {
+    self.push_back(elem);
+  }
+
+  fn dequeue(&mut self) -> Option<T> {
+    self.pop_front()
+  }
+}
+
+impl<T, O: Observer<T>> Deque<T> for List<T, O> {
+  fn push_front(&mut self, elem: T) {
+    List::push_front(self, elem);
+  }
+
+  fn push_back(&mut self, elem: T) {
+    List::push_back(self, elem);
+  }
+
+  fn pop_front(&mut self) -> Option<T> {
+    List::pop_front(self)
+  }
+
+  fn pop_back(&mut self) -> Option<T> {
+    List::pop_back(self)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Index, List};
+  use crate::model::deque_harness;
+  use crate::observer::NoObserver;
+  use crate::rng::Rng;
+  use crate::test_support::{assert_panic_safe, measure, DropCounter, Profile};
+  use crate::traits::conformance;
+  use std::collections::HashMap;
+  use std::mem;
+
+  #[test]
+  fn basics() {
+    let mut list = List::new();
+    assert_eq!(list.pop_front(), None);
+    assert_eq!(list.pop_back(), None);
+
+    list.push_back(1);
+    list.push_front(2);
+    list.push_back(3);
+    list.push_front(4);
+    assert_eq!(list.len(), 4);
+
+    assert_eq!(list.pop_front(), Some(4));
+    assert_eq!(list.pop_back(), Some(3));
+    assert_eq!(list.pop_front(), Some(2));
+    assert_eq!(list.pop_back(), Some(1));
+    assert_eq!(list.pop_front(), None);
+    assert_eq!(list.pop_back(), None);
+    assert!(list.is_empty());
+
+    list.push_front(5);
+    assert_eq!(list.pop_back(), Some(5));
+    list.push_back(6);
+    assert_eq!(list.pop_front(), Some(6));
+  }
+
+  #[test]
+  fn peek() {
+    let mut list = List::new();
+    assert_eq!(list.peek_front(), None);
+    assert_eq!(list.peek_back_mut(), None);
+
+    list.push_back(1);
+    list.push_back(2);
+    assert_eq!(list.peek_front(), Some(&1));
+    assert_eq!(list.peek_back(), Some(&2));
+
+    *list.peek_front_mut().unwrap() = 10;
+    *list.peek_back_mut().unwrap() = 20;
+    assert_eq!(list.pop_front(), Some(10));
+    assert_eq!(list.peek_front(), Some(&20));
+    assert_eq!(list.peek_back(), Some(&20));
+  }
+
+  #[test]
+  fn iterators() {
+    let mut list = List::new();
+    for i in 0..5 {
+      list.push_back(i);
+    }
+    // Freed slots get reused out of order, which iteration has to follow.
+    list.pop_front();
+    list.pop_front();
+    list.push_front(1);
+    list.push_front(0);
+    list.push_back(5);
+
+    assert!(list.iter().copied().eq(0..6));
+    assert!(list.iter().rev().copied().eq((0..6).rev()));
+    assert_eq!(list.iter().len(), 6);
+
+    let mut iter = list.iter_mut();
+    assert_eq!(iter.next(), Some(&mut 0));
+    assert_eq!(iter.next_back(), Some(&mut 5));
+    for elem in iter {
+      *elem *= 10;
+    }
+    assert!(list.iter().copied().eq([0, 10, 20, 30, 40, 5]));
+
+    let mut iter = list.into_iter();
+    assert_eq!(iter.next_back(), Some(5));
+    assert_eq!(iter.next(), Some(0));
+    assert_eq!(iter.collect::<Vec<_>>(), [10, 20, 30, 40]);
+  }
+
+  #[test]
+  fn handles() {
+    let mut list = List::new();
+    let b = list.push_back('b');
+    let d = list.push_back('d');
+    let a = list.insert_before(b, 'a').unwrap();
+    let c = list.insert_after(b, 'c').unwrap();
+    let e = list.insert_after(d, 'e').unwrap();
+    assert_eq!(list.iter().collect::<String>(), "abcde");
+
+    assert_eq!(list.front_index(), Some(a));
+    assert_eq!(list.back_index(), Some(e));
+    assert_eq!(list.next_index(b), Some(c));
+    assert_eq!(list.prev_index(b), Some(a));
+    assert_eq!(list.prev_index(a), None);
+    assert_eq!(list.get(c), Some(&'c'));
+    *list.get_mut(c).unwrap() = 'C';
+
+    assert_eq!(list.remove(c), Some('C'));
+    assert_eq!(list.iter().collect::<String>(), "abde");
+
+    // `c`'s slot gets reused, but `c` stays stale.
+    let x = list.push_front('x');
+    assert_eq!((x.slot, x.generation), (c.slot, c.generation + 1));
+    assert!(!list.contains(c) && list.contains(x));
+    assert_eq!(list.get(c), None);
+    assert_eq!(list.get_mut(c), None);
+    assert_eq!(list.remove(c), None);
+    assert_eq!(list.insert_after(c, 'y'), Err('y'));
+    assert_eq!(list.insert_before(c, 'y'), Err('y'));
+    assert_eq!(list.next_index(c), None);
+    assert_eq!(list.iter().collect::<String>(), "xabde");
+
+    // Popping and clearing make handles stale too.
+    list.pop_front();
+    assert!(!list.contains(x));
+    list.clear();
+    assert!([a, b, d, e].iter().all(|&index| !list.contains(index)));
+
+    let other = List::<char>::new();
+    assert_eq!(other.get(a), None);
+  }
+
+  #[test]
+  fn compaction() {
+    let mut list = List::new();
+    let indices: Vec<Index> = (0..10).map(|i| list.push_back(i)).collect();
+    for &index in indices.iter().step_by(2) {
+      list.remove(index);
+    }
+    list.push_front(-1);
+    assert_eq!(list.slots.len(), 10);
+
+    let mut moved = HashMap::new();
+    list.compact(|old, new| {
+      moved.insert(old, new);
+    });
+    assert_eq!(list.slots.len(), 6);
+    assert!(list.iter().copied().eq([-1, 1, 3, 5, 7, 9]));
+
+    for (i, &index) in indices.iter().enumerate() {
+      assert!(!list.contains(index), "old handle {} still works", i);
+      if i % 2 == 1 {
+        assert_eq!(list.get(moved[&index]), Some(&(i as i32)));
+      }
+    }
+    assert_eq!(moved.len(), 6);
+    // In list order now.
+    let mut index = list.front_index();
+    for slot in 0..6 {
+      assert_eq!(index.unwrap().slot, slot);
+      index = list.next_index(index.unwrap());
+    }
+
+    // Slots that went away don't bring their handles back when they return.
+    for i in 0..10 {
+      list.push_back(10 + i);
+    }
+    assert!(indices.iter().all(|&index| !list.contains(index)));
+    assert_eq!(list.len(), 16);
+
+    let mut empty = List::<i32>::new();
+    let index = empty.push_back(1);
+    empty.pop_back();
+    empty.compact(|_, _| panic!("nothing to move"));
+    assert!(empty.is_empty() && empty.slots.is_empty());
+    let again = empty.push_back(2);
+    assert_ne!(again, index);
+    assert!(!empty.contains(index));
+  }
+
+  #[test]
+  fn observer() {
+    use crate::observer::Mirror;
+
+    type Op = fn(&mut List<i32, Mirror<i32>>);
+
+    let ops: Vec<Op> = vec![
+      |list| { list.push_back(1); },
+      |list| { list.push_back(2); },
+      |list| { list.push_front(3); },
+      |list| { list.pop_back(); },
+      |list| { list.push_back(4); },
+      |list| { list.pop_front(); },
+      |list| { list.update(1, |elem| *elem *= 10); },
+      |list| { list.update(3, |elem| *elem *= 10); },
+      |list| list.clear(),
+      |list| { list.pop_back(); },
+      |list| { list.push_front(6); },
+      |list| { list.update(0, |elem| *elem += 1); },
+    ];
+
+    let mut list = List::with_observer(Mirror::new());
+    for op in ops {
+      op(&mut list);
+      assert!(list.iter().eq(list.observer().elems.iter()));
+    }
+  }
+
+  #[test]
+  fn conformance() {
+    conformance::deque(List::new);
+    conformance::queue(List::new);
+    conformance::stack(List::new);
+    conformance::stack_drops(List::new);
+  }
+
+  #[test]
+  fn model() {
+    deque_harness!("arena_list", List::new, "let mut list = List::new();").assert(500, 100);
+  }
+
+  #[test]
+  fn against_brute_force() {
+    let mut rng = Rng::new(48);
+    let mut list = List::new();
+    // Values in list order, with their handles.
+    let mut model: Vec<(u32, Index)> = Vec::new();
+    let mut stale = Vec::new();
+
+    for step in 0..5_000 {
+      let value = rng.next_u64() as u32;
+      let at = rng.below(model.len() + 1);
+
+      match rng.below(6) {
+        0 => model.insert(0, (value, list.push_front(value))),
+        1 => model.push((value, list.push_back(value))),
+        2 if at < model.len() => {
+          let (expected, index) = model.remove(at);
+          assert_eq!(list.remove(index), Some(expected), "step {}", step);
+          stale.push(index);
+        }
+        3 if at < model.len() => {
+          let index = list.insert_after(model[at].1, value).unwrap();
+          model.insert(at + 1, (value, index));
+        }
+        4 if at < model.len() => {
+          let index = list.insert_before(model[at].1, value).unwrap();
+          model.insert(at, (value, index));
+        }
+        5 if rng.chance(1, 50) => {
+          let mut moved = HashMap::new();
+          list.compact(|old, new| {
+            moved.insert(old, new);
+          });
+          stale.extend(model.iter().map(|&(_, index)| index));
+          for (_, index) in &mut model {
+            *index = moved[index];
+          }
+        }
+        _ => {
+          if let Some(&index) = stale.get(rng.below(stale.len() + 1)) {
+            assert_eq!(list.get(index), None, "step {}", step);
+          }
+        }
+      }
+
+      assert_eq!(list.len(), model.len());
+    }
+
+    assert!(list.iter().copied().eq(model.iter().map(|&(value, _)| value)));
+    assert!(model.iter().all(|&(value, index)| list.get(index) == Some(&value)));
+    assert!(stale.iter().all(|&index| !list.contains(index)));
+  }
+
+  #[test]
+  fn allocations() {
+    let (mut list, profile) = measure(|| List::with_observer(NoObserver));
+    assert_eq!(profile, Profile::default());
+
+    for i in 0..8 {
+      list.push_back(i);
+    }
+    for _ in 0..8 {
+      list.pop_front();
+    }
+
+    // Freed slots get reused, and nothing else allocates.
+    let ((), profile) = measure(|| {
+      let middle = list.push_back(1);
+      list.push_front(0);
+      list.insert_after(middle, 2).unwrap();
+      assert_eq!(list.iter().count(), 3);
+      assert_eq!(list.remove(middle), Some(1));
+      list.update(0, |elem| *elem += 1);
+      list.pop_back();
+      list.clear();
+    });
+    assert_eq!(profile, Profile::default());
+
+    // Except `iter_mut`, which collects a reference per element, however
+    // many free slots there are.
+    list.push_back(1);
+    list.push_back(2);
+    let ((), profile) = measure(|| list.iter_mut().for_each(|elem| *elem += 1));
+    assert!(profile.allocations <= 2);
+    assert_eq!(profile.allocations, profile.deallocations);
+    assert!(profile.peak <= 2 * 2 * mem::size_of::<(usize, &mut i32)>());
+    assert!(list.iter().copied().eq([2, 3]));
+  }
+
+  #[test]
+  fn panic_safe_drops() {
+    let fill = |counter: &DropCounter| {
+      let mut list = List::new();
+      for _ in 0..5 {
+        list.push_back(counter.elem());
+      }
+      list
+    };
+
+    assert_panic_safe(5, fill, |mut list| list.clear());
+    assert_panic_safe(5, fill, |list| {
+      let mut iter = list.into_iter();
+      iter.next_back();
+    });
+  }
+}
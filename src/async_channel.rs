@@ -0,0 +1,546 @@
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+use crate::ch6_unsafe_singly_linked::List;
+use crate::channel::{SendError, TryRecvError};
+
+// Async, bounded take on `channel`: instead of blocking on a `Condvar`, a
+// task that can't make progress leaves its `Waker` in a list and returns
+// `Poll::Pending`. Both the messages and the waiting wakers are kept in
+// `ch6_unsafe_singly_linked::List`s.
+//
+// Every change wakes *all* the tasks waiting on the other side. They race
+// for the message or the free slot and the losers register again, which
+// keeps things simple and doesn't lose wake-ups when a waiting future is
+// dropped.
+//
+// Each waiting future, and each receiver polled through `poll_recv`, has an
+// id and leaves at most one waker under it, however often it's polled. A
+// future that goes away takes its waker with it.
+
+struct State<T> {
+  queue: List<T>,
+  len: usize,
+  senders: usize,
+  receivers: usize,
+  send_wakers: List<Waiter>,
+  recv_wakers: List<Waiter>,
+  next_waiter: usize,
+}
+
+struct Waiter {
+  id: usize,
+  waker: Waker,
+}
+
+impl<T> State<T> {
+  fn new_waiter(&mut self) -> usize {
+    self.next_waiter += 1;
+    self.next_waiter
+  }
+}
+
+struct Shared<T> {
+  state: Mutex<State<T>>,
+  bound: usize,
+}
+
+impl<T> Shared<T> {
+  fn lock(&self) -> MutexGuard<'_, State<T>> {
+    self.state.lock().unwrap()
+  }
+}
+
+// Takes the wakers out so they can be woken after releasing the lock.
+fn take_wakers(wakers: &mut List<Waiter>) -> List<Waiter> {
+  mem::replace(wakers, List::new())
+}
+
+fn wake_all(wakers: List<Waiter>) {
+  for waiter in wakers.into_iter() {
+    waiter.waker.wake();
+  }
+}
+
+// Leaves `waker` under `id`, keeping the one already there if it wakes the
+// same task anyway.
+fn register(wakers: &mut List<Waiter>, id: usize, waker: &Waker) {
+  match wakers.iter_mut().find(|waiter| waiter.id == id) {
+    Some(waiter) => {
+      if !waiter.waker.will_wake(waker) {
+        waiter.waker = waker.clone();
+      }
+    }
+    None => wakers.push(Waiter { id, waker: waker.clone() }),
+  }
+}
+
+fn unregister(wakers: &mut List<Waiter>, id: usize) {
+  if wakers.iter().any(|waiter| waiter.id == id) {
+    for waiter in take_wakers(wakers).into_iter() {
+      if waiter.id != id {
+        wakers.push(waiter);
+      }
+    }
+  }
+}
+
+pub struct Sender<T> {
+  shared: Arc<Shared<T>>,
+}
+
+pub struct Receiver<T> {
+  shared: Arc<Shared<T>>,
+  // What `poll_recv` registers under.
+  waiter: usize,
+}
+
+pub fn bounded<T>(bound: usize) -> (Sender<T>, Receiver<T>) {
+  assert!(bound > 0, "a bounded channel needs room for at least one message");
+
+  let shared = Arc::new(Shared {
+    state: Mutex::new(State {
+      queue: List::new(),
+      len: 0,
+      senders: 1,
+      receivers: 1,
+      send_wakers: List::new(),
+      recv_wakers: List::new(),
+      next_waiter: 0,
+    }),
+    bound,
+  });
+
+  let waiter = shared.lock().new_waiter();
+  (Sender { shared: shared.clone() }, Receiver { shared, waiter })
+}
+
+impl<T> Sender<T> {
+  // Resolves once the message is queued, or fails, handing it back, once
+  // every receiver is gone.
+  pub fn send(&self, elem: T) -> SendFuture<'_, T> {
+    SendFuture { sender: self, elem: Some(elem), waiter: None }
+  }
+}
+
+impl<T> Clone for Sender<T> {
+  fn clone(&self) -> Self {
+    self.shared.lock().senders += 1;
+    Sender { shared: self.shared.clone() }
+  }
+}
+
+impl<T> Drop for Sender<T> {
+  fn drop(&mut self) {
+    let mut state = self.shared.lock();
+    state.senders -= 1;
+    if state.senders == 0 {
+      let wakers = take_wakers(&mut state.recv_wakers);
+      drop(state);
+      wake_all(wakers);
+    }
+  }
+}
+
+impl<T> Receiver<T> {
+  // Resolves to the next message, or to `None` once the channel is empty and
+  // every sender is gone.
+  pub fn recv(&self) -> RecvFuture<'_, T> {
+    RecvFuture { receiver: self, waiter: None }
+  }
+
+  // Same shape as `Stream::poll_next`.
+  pub fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+    self.poll_recv_as(&mut Some(self.waiter), cx)
+  }
+
+  // Registers under `waiter` if it has to wait, picking an id first if
+  // there's none yet.
+  fn poll_recv_as(&self, waiter: &mut Option<usize>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+    let mut state = self.shared.lock();
+
+    match state.queue.pop() {
+      Some(elem) => {
+        state.len -= 1;
+
+        let wakers = take_wakers(&mut state.send_wakers);
+        drop(state);
+        wake_all(wakers);
+
+        Poll::Ready(Some(elem))
+      }
+      None if state.senders == 0 => Poll::Ready(None),
+      None => {
+        let state = &mut *state;
+        let id = *waiter.get_or_insert_with(|| state.new_waiter());
+        register(&mut state.recv_wakers, id, cx.waker());
+        Poll::Pending
+      }
+    }
+  }
+
+  pub fn try_recv(&self) -> Result<T, TryRecvError> {
+    let mut state = self.shared.lock();
+
+    match state.queue.pop() {
+      Some(elem) => {
+        state.len -= 1;
+
+        let wakers = take_wakers(&mut state.send_wakers);
+        drop(state);
+        wake_all(wakers);
+
+        Ok(elem)
+      }
+      None if state.senders == 0 => Err(TryRecvError::Disconnected),
+      None => Err(TryRecvError::Empty),
+    }
+  }
+}
+
+impl<T> Clone for Receiver<T> {
+  fn clone(&self) -> Self {
+    let mut state = self.shared.lock();
+    state.receivers += 1;
+    let waiter = state.new_waiter();
+    Receiver { shared: self.shared.clone(), waiter }
+  }
+}
+
+impl<T> Drop for Receiver<T> {
+  fn drop(&mut self) {
+    let mut state = self.shared.lock();
+    unregister(&mut state.recv_wakers, self.waiter);
+    state.receivers -= 1;
+    if state.receivers == 0 {
+      let wakers = take_wakers(&mut state.send_wakers);
+      drop(state);
+      wake_all(wakers);
+    }
+  }
+}
+
+pub struct SendFuture<'a, T> {
+  sender: &'a Sender<T>,
+  elem: Option<T>,
+  waiter: Option<usize>,
+}
+
+// The message is never pinned, it's just moved into the queue.
+impl<T> Unpin for SendFuture<'_, T> {}
+
+impl<T> Future for SendFuture<'_, T> {
+  type Output = Result<(), SendError<T>>;
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    let this = self.get_mut();
+    let shared = &this.sender.shared;
+    let mut state = shared.lock();
+
+    if state.receivers == 0 {
+      let elem = this.elem.take().expect("`SendFuture` polled after completion");
+      return Poll::Ready(Err(SendError(elem)));
+    }
+
+    if state.len < shared.bound {
+      let elem = this.elem.take().expect("`SendFuture` polled after completion");
+      state.queue.push(elem);
+      state.len += 1;
+
+      let wakers = take_wakers(&mut state.recv_wakers);
+      drop(state);
+      wake_all(wakers);
+
+      Poll::Ready(Ok(()))
+    } else {
+      let state = &mut *state;
+      let id = *this.waiter.get_or_insert_with(|| state.new_waiter());
+      register(&mut state.send_wakers, id, cx.waker());
+      Poll::Pending
+    }
+  }
+}
+
+impl<T> Drop for SendFuture<'_, T> {
+  fn drop(&mut self) {
+    if let Some(id) = self.waiter {
+      unregister(&mut self.sender.shared.lock().send_wakers, id);
+    }
+  }
+}
+
+pub struct RecvFuture<'a, T> {
+  receiver: &'a Receiver<T>,
+  waiter: Option<usize>,
+}
+
+impl<T> Future for RecvFuture<'_, T> {
+  type Output = Option<T>;
+
+  fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+    let this = self.get_mut();
+    this.receiver.poll_recv_as(&mut this.waiter, cx)
+  }
+}
+
+impl<T> Drop for RecvFuture<'_, T> {
+  fn drop(&mut self) {
+    if let Some(id) = self.waiter {
+      unregister(&mut self.receiver.shared.lock().recv_wakers, id);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{bounded, Receiver};
+  use crate::channel::{SendError, TryRecvError};
+  use std::cell::RefCell;
+  use std::collections::VecDeque;
+  use std::future::Future;
+  use std::pin::Pin;
+  use std::rc::Rc;
+  use std::sync::{Arc, Mutex};
+  use std::task::{Context, Poll, Wake, Waker};
+  use std::thread::{self, Thread};
+
+  // Single-threaded executor: a waker puts its task's id back in the run
+  // queue, and `run` polls whatever is in there until nothing is left.
+  struct Executor {
+    tasks: Vec<Option<Pin<Box<dyn Future<Output = ()>>>>>,
+    run_queue: Arc<Mutex<VecDeque<usize>>>,
+    polls: usize,
+  }
+
+  struct TaskWaker {
+    id: usize,
+    run_queue: Arc<Mutex<VecDeque<usize>>>,
+  }
+
+  impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+      self.run_queue.lock().unwrap().push_back(self.id);
+    }
+  }
+
+  impl Executor {
+    fn new() -> Self {
+      Executor { tasks: Vec::new(), run_queue: Arc::new(Mutex::new(VecDeque::new())), polls: 0 }
+    }
+
+    fn spawn(&mut self, future: impl Future<Output = ()> + 'static) {
+      self.run_queue.lock().unwrap().push_back(self.tasks.len());
+      self.tasks.push(Some(Box::pin(future)));
+    }
+
+    // Returns how many tasks are still stuck once nothing can make progress.
+    fn run(&mut self) -> usize {
+      loop {
+        let id = match self.run_queue.lock().unwrap().pop_front() {
+          Some(id) => id,
+          None => break,
+        };
+
+        if let Some(task) = self.tasks[id].as_mut() {
+          let waker = Waker::from(Arc::new(TaskWaker { id, run_queue: self.run_queue.clone() }));
+          self.polls += 1;
+          if task.as_mut().poll(&mut Context::from_waker(&waker)).is_ready() {
+            self.tasks[id] = None;
+          }
+        }
+      }
+
+      self.tasks.iter().filter(|task| task.is_some()).count()
+    }
+  }
+
+  struct ThreadWaker(Thread);
+
+  impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+      self.0.unpark();
+    }
+  }
+
+  // Runs a single future on the current thread, parking while it's pending.
+  fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+      if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+        return output;
+      }
+      thread::park();
+    }
+  }
+
+  // How many wakers each side has left in the channel.
+  fn wakers<T>(rx: &Receiver<T>) -> (usize, usize) {
+    let state = rx.shared.lock();
+    (state.send_wakers.iter().count(), state.recv_wakers.iter().count())
+  }
+
+  #[test]
+  fn basics() {
+    let (tx, rx) = bounded(2);
+
+    assert_eq!(block_on(tx.send(1)), Ok(()));
+    assert_eq!(block_on(tx.send(2)), Ok(()));
+    assert_eq!(block_on(rx.recv()), Some(1));
+    assert_eq!(rx.try_recv(), Ok(2));
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+
+    block_on(tx.clone().send(3)).unwrap();
+    drop(tx);
+
+    assert_eq!(block_on(rx.recv()), Some(3));
+    assert_eq!(block_on(rx.recv()), None);
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+  }
+
+  #[test]
+  fn one_waker_per_waiter() {
+    let (tx, rx) = bounded(1);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let other_waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    // Polled over and over, as in a `select!` loop, with a waker that
+    // changes every time.
+    for i in 0..100 {
+      let waker = if i % 2 == 0 { &waker } else { &other_waker };
+      assert_eq!(rx.poll_recv(&mut Context::from_waker(waker)), Poll::Pending);
+    }
+    assert_eq!(wakers(&rx), (0, 1));
+
+    let mut first = rx.recv();
+    let mut second = rx.recv();
+    for _ in 0..100 {
+      assert!(Pin::new(&mut first).poll(&mut cx).is_pending());
+      assert!(Pin::new(&mut second).poll(&mut cx).is_pending());
+    }
+    assert_eq!(wakers(&rx), (0, 3));
+    drop((first, second));
+    assert_eq!(wakers(&rx), (0, 1));
+
+    block_on(tx.send(1)).unwrap();
+    assert_eq!(wakers(&rx), (0, 0));
+
+    let mut send = tx.send(2);
+    for _ in 0..100 {
+      assert!(Pin::new(&mut send).poll(&mut cx).is_pending());
+    }
+    assert_eq!(wakers(&rx), (1, 0));
+    drop(send);
+    assert_eq!(wakers(&rx), (0, 0));
+  }
+
+  #[test]
+  fn send_waits_for_room() {
+    let (tx, rx) = bounded(2);
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let mut executor = Executor::new();
+
+    executor.spawn(async move {
+      for i in 0..10 {
+        tx.send(i).await.unwrap();
+      }
+    });
+
+    let log = received.clone();
+    executor.spawn(async move {
+      while let Some(i) = rx.recv().await {
+        log.borrow_mut().push(i);
+      }
+    });
+
+    assert_eq!(executor.run(), 0);
+    assert_eq!(*received.borrow(), (0..10).collect::<Vec<_>>());
+    // The sender had to wait for the receiver at least a few times.
+    assert!(executor.polls > 4);
+  }
+
+  #[test]
+  fn send_fails_without_receivers() {
+    let (tx, rx) = bounded(1);
+    let result = Rc::new(RefCell::new(None));
+    let mut executor = Executor::new();
+
+    let slot = result.clone();
+    executor.spawn(async move {
+      tx.send(1).await.unwrap();
+      *slot.borrow_mut() = Some(tx.send(2).await);
+    });
+
+    assert_eq!(executor.run(), 1);
+    drop(rx);
+    assert_eq!(executor.run(), 0);
+    assert_eq!(*result.borrow(), Some(Err(SendError(2))));
+  }
+
+  #[test]
+  fn many_tasks() {
+    let (tx, rx) = bounded(3);
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let mut executor = Executor::new();
+
+    for t in 0..4 {
+      let tx = tx.clone();
+      executor.spawn(async move {
+        for i in 0..100 {
+          tx.send(t * 100 + i).await.unwrap();
+        }
+      });
+    }
+    drop(tx);
+
+    for _ in 0..3 {
+      let (rx, log) = (rx.clone(), received.clone());
+      executor.spawn(async move {
+        while let Some(i) = rx.recv().await {
+          log.borrow_mut().push(i);
+        }
+      });
+    }
+    drop(rx);
+
+    assert_eq!(executor.run(), 0);
+
+    let mut received = received.borrow().clone();
+    received.sort();
+    assert_eq!(received, (0..400).collect::<Vec<_>>());
+  }
+
+  #[test]
+  fn across_threads() {
+    let (tx, rx) = bounded(4);
+
+    let producers: Vec<_> = (0..4).map(|t| {
+      let tx = tx.clone();
+      thread::spawn(move || {
+        for i in 0..1_000 {
+          block_on(tx.send((t, i))).unwrap();
+        }
+      })
+    }).collect();
+    drop(tx);
+
+    let mut last = [None; 4];
+    let mut count = 0;
+    while let Some((t, i)) = block_on(rx.recv()) {
+      assert!(last[t].is_none_or(|last| last < i));
+      last[t] = Some(i);
+      count += 1;
+    }
+
+    for producer in producers {
+      producer.join().unwrap();
+    }
+    assert_eq!(count, 4_000);
+  }
+}
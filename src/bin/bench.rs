@@ -0,0 +1,422 @@
+use std::collections::{HashMap, LinkedList, VecDeque};
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::hint::black_box;
+use std::process;
+use std::time::Instant;
+
+use lists::{arena_list, ch3_singly_linked, ch4_immutable, ch5_mutable_deque_without_refs, ch6_unsafe_singly_linked, unrolled, unsafe_deque};
+use lists::test_support::{self, CountingAlloc};
+
+// Compares the lists in this crate with each other and with `std`'s.
+//
+//   cargo run --release --bin bench -- [--sizes 1000,100000] [--rounds 5]
+//                                      [--csv FILE] [--compare FILE] [--threshold 10]
+//
+// Prints a table of nanoseconds and allocations per element for pushing n
+// elements, walking them, popping them all and dropping a full list. `--csv`
+// also saves the numbers, and `--compare` checks them against a file saved
+// earlier: anything more than `--threshold` percent slower, or allocating more
+// at all, is reported and makes the exit status 1.
+
+#[global_allocator]
+static ALLOCATOR: CountingAlloc = CountingAlloc::system();
+
+// How to drive one list type.
+struct Subject<L> {
+  name: &'static str,
+  new: fn() -> L,
+  push: fn(&mut L, u64),
+  pop: fn(&mut L) -> Option<u64>,
+  // `None` for lists that can't be walked without consuming them.
+  sum: Option<fn(&L) -> u64>,
+}
+
+// Nanoseconds per element for each phase, best of all rounds, and what the
+// pushes allocated per element.
+#[derive(Clone, Debug, PartialEq)]
+struct Row {
+  list: String,
+  size: usize,
+  push: f64,
+  iter: Option<f64>,
+  pop: f64,
+  drop: f64,
+  allocs: f64,
+  bytes: f64,
+}
+
+fn measure<L>(subject: &Subject<L>, size: usize, rounds: usize) -> Row {
+  let per_elem = |start: Instant| start.elapsed().as_nanos() as f64 / size.max(1) as f64;
+  let mut row = Row {
+    list: subject.name.to_string(),
+    size,
+    push: f64::INFINITY,
+    iter: subject.sum.map(|_| f64::INFINITY),
+    pop: f64::INFINITY,
+    drop: f64::INFINITY,
+    allocs: 0.0,
+    bytes: 0.0,
+  };
+
+  for _ in 0..rounds {
+    let mut list = (subject.new)();
+    let start = Instant::now();
+    let ((), profile) = test_support::measure(|| {
+      for i in 0..size as u64 {
+        (subject.push)(&mut list, black_box(i));
+      }
+    });
+    row.push = row.push.min(per_elem(start));
+    row.allocs = profile.allocations as f64 / size.max(1) as f64;
+    row.bytes = profile.bytes as f64 / size.max(1) as f64;
+
+    if let (Some(sum), Some(best)) = (subject.sum, row.iter.as_mut()) {
+      let start = Instant::now();
+      black_box(sum(&list));
+      *best = best.min(per_elem(start));
+    }
+
+    let start = Instant::now();
+    while let Some(elem) = (subject.pop)(&mut list) {
+      black_box(elem);
+    }
+    row.pop = row.pop.min(per_elem(start));
+
+    for i in 0..size as u64 {
+      (subject.push)(&mut list, i);
+    }
+    let start = Instant::now();
+    drop(list);
+    row.drop = row.drop.min(per_elem(start));
+  }
+
+  row
+}
+
+fn measure_all(size: usize, rounds: usize) -> Vec<Row> {
+  vec![
+    measure(&Subject {
+      name: "ch3_singly_linked",
+      new: ch3_singly_linked::List::new,
+      push: |list, elem| list.push(elem),
+      pop: |list| list.pop(),
+      sum: Some(|list| list.iter().sum()),
+    }, size, rounds),
+    measure(&Subject {
+      name: "ch4_immutable",
+      new: ch4_immutable::List::new,
+      push: |list, elem| *list = list.append(elem),
+      pop: |list| {
+        let head = list.head().copied();
+        *list = list.tail();
+        head
+      },
+      sum: Some(|list| list.iter().sum()),
+    }, size, rounds),
+    measure(&Subject {
+      name: "ch5_mutable_deque_without_refs",
+      new: ch5_mutable_deque_without_refs::List::new,
+      push: |list, elem| list.push_back(elem),
+      pop: |list| list.pop_front(),
+      sum: None,
+    }, size, rounds),
+    measure(&Subject {
+      name: "ch6_unsafe_singly_linked",
+      new: ch6_unsafe_singly_linked::List::new,
+      push: |list, elem| list.push(elem),
+      pop: |list| list.pop(),
+      sum: Some(|list| list.iter().sum()),
+    }, size, rounds),
+    measure(&Subject {
+      name: "unsafe_deque",
+      new: unsafe_deque::List::new,
+      push: |list, elem| list.push_back(elem),
+      pop: |list| list.pop_front(),
+      sum: Some(|list| list.iter().sum()),
+    }, size, rounds),
+    measure(&Subject {
+      name: "unrolled",
+      new: unrolled::List::<u64>::new,
+      push: |list, elem| list.push_back(elem),
+      pop: |list| list.pop_front(),
+      sum: Some(|list| list.iter().sum()),
+    }, size, rounds),
+    measure(&Subject {
+      name: "arena_list",
+      new: arena_list::List::new,
+      push: |list, elem| {
+        list.push_back(elem);
+      },
+      pop: |list| list.pop_front(),
+      sum: Some(|list| list.iter().sum()),
+    }, size, rounds),
+    measure(&Subject {
+      name: "std::LinkedList",
+      new: LinkedList::new,
+      push: |list, elem| list.push_back(elem),
+      pop: |list| list.pop_front(),
+      sum: Some(|list| list.iter().sum()),
+    }, size, rounds),
+    measure(&Subject {
+      name: "std::VecDeque",
+      new: VecDeque::new,
+      push: |list, elem| list.push_back(elem),
+      pop: |list| list.pop_front(),
+      sum: Some(|list| list.iter().sum()),
+    }, size, rounds),
+  ]
+}
+
+fn table(rows: &[Row]) -> String {
+  let mut out = String::new();
+  writeln!(out, "{:<32}{:>8}{:>10}{:>10}{:>10}{:>10}{:>10}{:>10}",
+    "list", "size", "push ns", "iter ns", "pop ns", "drop ns", "allocs", "bytes").unwrap();
+
+  for row in rows {
+    let iter = row.iter.map_or("-".to_string(), |iter| format!("{:.2}", iter));
+    writeln!(out, "{:<32}{:>8}{:>10.2}{:>10}{:>10.2}{:>10.2}{:>10.2}{:>10.2}",
+      row.list, row.size, row.push, iter, row.pop, row.drop, row.allocs, row.bytes).unwrap();
+  }
+  out
+}
+
+const HEADER: &str = "list,size,push_ns,iter_ns,pop_ns,drop_ns,allocs,bytes";
+
+fn to_csv(rows: &[Row]) -> String {
+  let mut out = format!("{}\n", HEADER);
+  for row in rows {
+    let iter = row.iter.map_or(String::new(), |iter| iter.to_string());
+    writeln!(out, "{},{},{},{},{},{},{},{}",
+      row.list, row.size, row.push, iter, row.pop, row.drop, row.allocs, row.bytes).unwrap();
+  }
+  out
+}
+
+fn from_csv(csv: &str) -> Result<Vec<Row>, String> {
+  let mut lines = csv.lines().enumerate();
+  match lines.next() {
+    Some((_, header)) if header == HEADER => {}
+    _ => return Err(format!("expected the header `{}`", HEADER)),
+  }
+
+  lines.filter(|(_, line)| !line.is_empty()).map(|(i, line)| {
+    let error = |what: &str| format!("line {}: {}", i + 1, what);
+    let fields: Vec<&str> = line.split(',').collect();
+    if fields.len() != 8 {
+      return Err(error("expected 8 fields"));
+    }
+
+    let number = |field: &str| field.parse::<f64>().map_err(|_| error(&format!("`{}` isn't a number", field)));
+    Ok(Row {
+      list: fields[0].to_string(),
+      size: fields[1].parse().map_err(|_| error(&format!("`{}` isn't a size", fields[1])))?,
+      push: number(fields[2])?,
+      iter: if fields[3].is_empty() { None } else { Some(number(fields[3])?) },
+      pop: number(fields[4])?,
+      drop: number(fields[5])?,
+      allocs: number(fields[6])?,
+      bytes: number(fields[7])?,
+    })
+  }).collect()
+}
+
+// One number that got worse than the baseline allows.
+#[derive(Debug, PartialEq)]
+struct Regression {
+  list: String,
+  size: usize,
+  metric: &'static str,
+  baseline: f64,
+  now: f64,
+}
+
+fn compare(baseline: &[Row], rows: &[Row], threshold: f64) -> Vec<Regression> {
+  let baseline: HashMap<_, _> = baseline.iter().map(|row| ((row.list.as_str(), row.size), row)).collect();
+  let mut regressions = Vec::new();
+
+  for row in rows {
+    let base = match baseline.get(&(row.list.as_str(), row.size)) {
+      Some(base) => base,
+      None => continue,
+    };
+
+    let timings = [
+      ("push", Some(base.push), Some(row.push)),
+      ("iter", base.iter, row.iter),
+      ("pop", Some(base.pop), Some(row.pop)),
+      ("drop", Some(base.drop), Some(row.drop)),
+    ];
+    for &(metric, baseline, now) in &timings {
+      if let (Some(baseline), Some(now)) = (baseline, now) {
+        if now > baseline * (1.0 + threshold / 100.0) {
+          regressions.push(Regression { list: row.list.clone(), size: row.size, metric, baseline, now });
+        }
+      }
+    }
+
+    // Allocations don't depend on the machine's mood, so any increase counts.
+    for &(metric, baseline, now) in &[("allocs", base.allocs, row.allocs), ("bytes", base.bytes, row.bytes)] {
+      if now > baseline {
+        regressions.push(Regression { list: row.list.clone(), size: row.size, metric, baseline, now });
+      }
+    }
+  }
+
+  regressions
+}
+
+struct Args {
+  sizes: Vec<usize>,
+  rounds: usize,
+  csv: Option<String>,
+  compare: Option<String>,
+  threshold: f64,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+  let mut parsed = Args {
+    sizes: vec![100, 10_000, 100_000],
+    rounds: 5,
+    csv: None,
+    compare: None,
+    threshold: 10.0,
+  };
+
+  while let Some(arg) = args.next() {
+    let mut value = || args.next().ok_or_else(|| format!("`{}` needs a value", arg));
+    match arg.as_str() {
+      "--sizes" => {
+        parsed.sizes = value()?.split(',')
+          .map(|size| size.parse().map_err(|_| format!("`{}` isn't a size", size)))
+          .collect::<Result<_, _>>()?;
+      }
+      "--rounds" => {
+        let rounds = value()?;
+        parsed.rounds = rounds.parse().ok().filter(|&rounds| rounds > 0)
+          .ok_or_else(|| format!("`{}` isn't a positive number of rounds", rounds))?;
+      }
+      "--csv" => parsed.csv = Some(value()?),
+      "--compare" => parsed.compare = Some(value()?),
+      "--threshold" => {
+        let threshold = value()?;
+        parsed.threshold = threshold.parse().map_err(|_| format!("`{}` isn't a percentage", threshold))?;
+      }
+      _ => return Err(format!("unknown argument `{}`", arg)),
+    }
+  }
+
+  Ok(parsed)
+}
+
+fn run() -> Result<bool, String> {
+  let args = parse_args(env::args().skip(1))?;
+
+  // Read it first so a typo doesn't cost a whole run.
+  let baseline = match &args.compare {
+    Some(path) => {
+      let csv = fs::read_to_string(path).map_err(|error| format!("can't read {}: {}", path, error))?;
+      Some(from_csv(&csv).map_err(|error| format!("{}: {}", path, error))?)
+    }
+    None => None,
+  };
+
+  let rows: Vec<Row> = args.sizes.iter().flat_map(|&size| measure_all(size, args.rounds)).collect();
+  print!("{}", table(&rows));
+
+  if let Some(path) = &args.csv {
+    fs::write(path, to_csv(&rows)).map_err(|error| format!("can't write {}: {}", path, error))?;
+  }
+
+  let baseline = match baseline {
+    Some(baseline) => baseline,
+    None => return Ok(true),
+  };
+
+  let regressions = compare(&baseline, &rows, args.threshold);
+  println!();
+  if regressions.is_empty() {
+    println!("no regressions against {}", args.compare.unwrap());
+    return Ok(true);
+  }
+
+  println!("{} regressions against {}:", regressions.len(), args.compare.unwrap());
+  for regression in &regressions {
+    println!("  {} {} {}: {:.2} -> {:.2} ({:+.0}%)",
+      regression.list, regression.size, regression.metric, regression.baseline, regression.now,
+      (regression.now / regression.baseline - 1.0) * 100.0);
+  }
+  Ok(false)
+}
+
+fn main() {
+  match run() {
+    Ok(true) => {}
+    Ok(false) => process::exit(1),
+    Err(error) => {
+      eprintln!("bench: {}", error);
+      process::exit(2);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{compare, from_csv, measure_all, parse_args, to_csv, Regression};
+
+  #[test]
+  fn measures_every_list() {
+    let rows = measure_all(50, 1);
+    assert_eq!(rows.len(), 9);
+    assert!(rows.iter().all(|row| row.size == 50));
+
+    // One node per element, except for `unrolled`, `arena_list` and
+    // `VecDeque`, and `ch5` can't be walked by reference.
+    assert_eq!(rows[0].allocs, 1.0);
+    assert_eq!(rows[2].iter, None);
+    assert!(rows[5].allocs < 1.0);
+    assert!(rows[6].allocs < 1.0);
+    assert!(rows[8].allocs < 1.0);
+  }
+
+  #[test]
+  fn csv_round_trip() {
+    let rows = measure_all(10, 1);
+    assert_eq!(from_csv(&to_csv(&rows)), Ok(rows));
+
+    assert!(from_csv("list,size\n").is_err());
+    assert!(from_csv(&format!("{}\nch3,10,1,2,3,4,5\n", super::HEADER)).unwrap_err().contains("line 2"));
+  }
+
+  #[test]
+  fn regressions() {
+    let baseline = measure_all(10, 1);
+    let mut rows = baseline.clone();
+    assert_eq!(compare(&baseline, &rows, 10.0), []);
+
+    rows[0].push = baseline[0].push * 1.05;
+    rows[1].pop = baseline[1].pop * 1.5;
+    rows[3].allocs += 0.01;
+    rows[4].iter = None;
+    assert_eq!(compare(&baseline, &rows, 10.0), [
+      Regression { list: rows[1].list.clone(), size: 10, metric: "pop", baseline: baseline[1].pop, now: rows[1].pop },
+      Regression { list: rows[3].list.clone(), size: 10, metric: "allocs", baseline: baseline[3].allocs, now: rows[3].allocs },
+    ]);
+  }
+
+  #[test]
+  fn args() {
+    let args = |args: &[&str]| parse_args(args.iter().map(|arg| arg.to_string()));
+
+    let parsed = args(&["--sizes", "1,20", "--compare", "base.csv", "--threshold", "5"]).unwrap();
+    assert_eq!(parsed.sizes, [1, 20]);
+    assert_eq!(parsed.compare.as_deref(), Some("base.csv"));
+    assert_eq!(parsed.threshold, 5.0);
+    assert_eq!(parsed.rounds, 5);
+
+    assert!(args(&["--rounds", "0"]).is_err());
+    assert!(args(&["--csv"]).is_err());
+    assert!(args(&["--fast"]).is_err());
+  }
+}
@@ -0,0 +1,384 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufWriter, Read, Write};
+use std::process;
+
+use lists::ch2_warmup::{List, Sum};
+
+// Reverse-Polish calculator on top of `ch2_warmup::List`.
+//
+//   cargo run --bin rpn -- [FILE...]
+//
+// Runs the files one after the other as a single program, or stdin if there
+// are none (`-` also means stdin). Words are separated by whitespace:
+//
+//   42 -7          push a number (64-bit signed)
+//   + - * / %      pop two numbers, push the result
+//   dup swap drop over
+//   min max sum    push the smallest, largest, or sum of the whole stack
+//   .              pop and print the top
+//   .s             print the whole stack, bottom first
+//   : name ... ;   define a word
+//   # ...          comment until the end of the line
+//
+// Whatever is left on the stack at the end gets printed like `.s` does. The
+// first error stops the program with its location and exit status 1.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Builtin {
+  Add,
+  Sub,
+  Mul,
+  Div,
+  Rem,
+  Dup,
+  Swap,
+  Drop,
+  Over,
+  Min,
+  Max,
+  Sum,
+  Print,
+  PrintStack,
+}
+
+const BUILTINS: &[(&str, Builtin)] = &[
+  ("+", Builtin::Add),
+  ("-", Builtin::Sub),
+  ("*", Builtin::Mul),
+  ("/", Builtin::Div),
+  ("%", Builtin::Rem),
+  ("dup", Builtin::Dup),
+  ("swap", Builtin::Swap),
+  ("drop", Builtin::Drop),
+  ("over", Builtin::Over),
+  ("min", Builtin::Min),
+  ("max", Builtin::Max),
+  ("sum", Builtin::Sum),
+  (".", Builtin::Print),
+  (".s", Builtin::PrintStack),
+];
+
+impl Builtin {
+  fn name(self) -> &'static str {
+    BUILTINS.iter().find(|&&(_, builtin)| builtin == self).unwrap().0
+  }
+
+  // How many values it needs on the stack.
+  fn arity(self) -> usize {
+    match self {
+      Builtin::Add | Builtin::Sub | Builtin::Mul | Builtin::Div | Builtin::Rem => 2,
+      Builtin::Swap | Builtin::Over => 2,
+      Builtin::Dup | Builtin::Drop | Builtin::Print => 1,
+      Builtin::Min | Builtin::Max | Builtin::Sum | Builtin::PrintStack => 0,
+    }
+  }
+}
+
+// User-defined words are inlined when they're defined, so redefining a word
+// later doesn't change the ones already using it, and nothing can recurse.
+#[derive(Clone, Copy, Debug)]
+enum Op {
+  Push(i64),
+  Builtin(Builtin),
+}
+
+#[derive(Debug, PartialEq)]
+enum Error {
+  Underflow { word: &'static str, needs: usize, has: usize },
+  DivisionByZero { word: &'static str },
+  Overflow { word: &'static str },
+  UnknownWord(String),
+  // `:` at the end of the input, or followed by a number or `;`.
+  MissingName,
+  Unterminated(String),
+  NestedDefinition(String),
+  UnexpectedSemicolon,
+  Io(String),
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Error::Underflow { word, needs: 1, has } => {
+        write!(f, "stack underflow: `{}` needs a value but the stack has {}", word, has)
+      }
+      Error::Underflow { word, needs, has } => {
+        write!(f, "stack underflow: `{}` needs {} values but the stack has {}", word, needs, has)
+      }
+      Error::DivisionByZero { word } => write!(f, "`{}` by zero", word),
+      Error::Overflow { word } => write!(f, "`{}` overflows", word),
+      Error::UnknownWord(word) => write!(f, "unknown word `{}`", word),
+      Error::MissingName => f.write_str("`:` needs a name for the new word"),
+      Error::Unterminated(name) => write!(f, "the definition of `{}` has no `;`", name),
+      Error::NestedDefinition(name) => write!(f, "`:` inside the definition of `{}`", name),
+      Error::UnexpectedSemicolon => f.write_str("`;` outside of a definition"),
+      Error::Io(error) => f.write_str(error),
+    }
+  }
+}
+
+// Where in the input something happened. Line 0 is the file as a whole.
+struct Located {
+  file: String,
+  line: usize,
+  error: Error,
+}
+
+impl fmt::Display for Located {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.line {
+      0 => write!(f, "{}: {}", self.file, self.error),
+      line => write!(f, "{}:{}: {}", self.file, line, self.error),
+    }
+  }
+}
+
+// A definition being read: its name, its body so far, and where it started.
+struct Definition {
+  name: String,
+  body: Vec<Op>,
+  line: usize,
+}
+
+struct Calculator<W: Write> {
+  stack: List<i64, Sum>,
+  len: usize,
+  words: HashMap<String, Vec<Op>>,
+  out: W,
+}
+
+impl<W: Write> Calculator<W> {
+  fn new(out: W) -> Self {
+    Calculator { stack: List::with_fold(Sum), len: 0, words: HashMap::new(), out }
+  }
+
+  // Runs one source file. Definitions can't span files.
+  fn run(&mut self, file: &str, source: &str) -> Result<(), Located> {
+    let locate = |line, error| Located { file: file.to_string(), line, error };
+    let mut definition: Option<Definition> = None;
+    // Set after `:`, until the name comes.
+    let mut naming = None;
+
+    for (i, line) in source.lines().enumerate() {
+      let line_number = i + 1;
+
+      for token in line.split_whitespace() {
+        if token.starts_with('#') {
+          break;
+        }
+
+        if let Some(start) = naming.take() {
+          if token == ";" || token == ":" || token.parse::<i64>().is_ok() {
+            return Err(locate(start, Error::MissingName));
+          }
+          definition = Some(Definition { name: token.to_string(), body: Vec::new(), line: start });
+          continue;
+        }
+
+        match (token, &mut definition) {
+          (":", Some(definition)) => {
+            return Err(locate(line_number, Error::NestedDefinition(definition.name.clone())));
+          }
+          (":", None) => naming = Some(line_number),
+          (";", Some(_)) => {
+            let Definition { name, body, .. } = definition.take().unwrap();
+            self.words.insert(name, body);
+          }
+          (";", None) => return Err(locate(line_number, Error::UnexpectedSemicolon)),
+          (word, Some(definition)) => {
+            let ops = self.compile(word).map_err(|error| locate(line_number, error))?;
+            definition.body.extend(ops);
+          }
+          (word, None) => {
+            let ops = self.compile(word).map_err(|error| locate(line_number, error))?;
+            for op in ops {
+              self.execute(op).map_err(|error| locate(line_number, error))?;
+            }
+          }
+        }
+      }
+    }
+
+    if let Some(start) = naming {
+      return Err(locate(start, Error::MissingName));
+    }
+    if let Some(definition) = definition {
+      return Err(locate(definition.line, Error::Unterminated(definition.name)));
+    }
+    Ok(())
+  }
+
+  fn compile(&self, word: &str) -> Result<Vec<Op>, Error> {
+    if let Some(body) = self.words.get(word) {
+      return Ok(body.clone());
+    }
+    if let Some(&(_, builtin)) = BUILTINS.iter().find(|&&(name, _)| name == word) {
+      return Ok(vec![Op::Builtin(builtin)]);
+    }
+    word.parse().map(|value| vec![Op::Push(value)]).map_err(|_| Error::UnknownWord(word.to_string()))
+  }
+
+  fn push(&mut self, value: i64) {
+    self.stack.push(value);
+    self.len += 1;
+  }
+
+  fn pop(&mut self) -> i64 {
+    self.len -= 1;
+    self.stack.pop().unwrap()
+  }
+
+  fn execute(&mut self, op: Op) -> Result<(), Error> {
+    let builtin = match op {
+      Op::Push(value) => {
+        self.push(value);
+        return Ok(());
+      }
+      Op::Builtin(builtin) => builtin,
+    };
+
+    let word = builtin.name();
+    if self.len < builtin.arity() {
+      return Err(Error::Underflow { word, needs: builtin.arity(), has: self.len });
+    }
+
+    match builtin {
+      Builtin::Add | Builtin::Sub | Builtin::Mul | Builtin::Div | Builtin::Rem => {
+        let b = self.pop();
+        let a = self.pop();
+        let result = match builtin {
+          Builtin::Add => a.checked_add(b),
+          Builtin::Sub => a.checked_sub(b),
+          Builtin::Mul => a.checked_mul(b),
+          _ if b == 0 => {
+            // Put them back, so the stack is as it was.
+            self.push(a);
+            self.push(b);
+            return Err(Error::DivisionByZero { word });
+          }
+          Builtin::Div => a.checked_div(b),
+          _ => a.checked_rem(b),
+        };
+
+        match result {
+          Some(result) => self.push(result),
+          None => {
+            self.push(a);
+            self.push(b);
+            return Err(Error::Overflow { word });
+          }
+        }
+      }
+      Builtin::Dup => {
+        let a = *self.stack.peek().unwrap();
+        self.push(a);
+      }
+      Builtin::Swap => {
+        let b = self.pop();
+        let a = self.pop();
+        self.push(b);
+        self.push(a);
+      }
+      Builtin::Drop => {
+        self.pop();
+      }
+      Builtin::Over => {
+        let b = self.pop();
+        let a = *self.stack.peek().unwrap();
+        self.push(b);
+        self.push(a);
+      }
+      Builtin::Min | Builtin::Max if self.len == 0 => {
+        return Err(Error::Underflow { word, needs: 1, has: 0 });
+      }
+      Builtin::Min => {
+        let min = *self.stack.min().unwrap();
+        self.push(min);
+      }
+      Builtin::Max => {
+        let max = *self.stack.max().unwrap();
+        self.push(max);
+      }
+      Builtin::Sum => {
+        let sum = self.stack.sum().ok_or(Error::Overflow { word })?;
+        self.push(sum);
+      }
+      Builtin::Print => {
+        let a = self.pop();
+        writeln!(self.out, "{}", a).map_err(|error| Error::Io(error.to_string()))?;
+      }
+      Builtin::PrintStack => self.print_stack().map_err(|error| Error::Io(error.to_string()))?,
+    }
+
+    Ok(())
+  }
+
+  // Bottom first. The stack can only be read from the top, so this empties
+  // it and builds it back up.
+  fn values(&mut self) -> Vec<i64> {
+    let mut values = Vec::with_capacity(self.len);
+    while let Some(value) = self.stack.pop() {
+      values.push(value);
+    }
+    values.reverse();
+
+    for &value in &values {
+      self.stack.push(value);
+    }
+    values
+  }
+
+  fn print_stack(&mut self) -> io::Result<()> {
+    let values = self.values();
+    write!(self.out, "<{}>", values.len())?;
+    for value in values {
+      write!(self.out, " {}", value)?;
+    }
+    writeln!(self.out)
+  }
+}
+
+fn read(file: &str) -> Result<String, Located> {
+  let result = if file == "-" {
+    let mut source = String::new();
+    io::stdin().read_to_string(&mut source).map(|_| source)
+  } else {
+    fs::read_to_string(file)
+  };
+  result.map_err(|error| Located { file: file.to_string(), line: 0, error: Error::Io(error.to_string()) })
+}
+
+fn main() {
+  let mut files: Vec<String> = env::args().skip(1).collect();
+  if files.is_empty() {
+    files.push("-".to_string());
+  }
+
+  let stdout = io::stdout();
+  let mut calculator = Calculator::new(BufWriter::new(stdout.lock()));
+
+  let result = files.iter().try_for_each(|file| {
+    let source = read(file)?;
+    let name = if file == "-" { "<stdin>" } else { file };
+    calculator.run(name, &source)
+  });
+
+  let status = match result {
+    Ok(()) if calculator.len > 0 => calculator.print_stack().map_or(1, |()| 0),
+    Ok(()) => 0,
+    Err(error) => {
+      // Whatever got printed before the error comes first.
+      let _ = calculator.out.flush();
+      eprintln!("rpn: {}", error);
+      1
+    }
+  };
+
+  if calculator.out.flush().is_err() {
+    process::exit(1);
+  }
+  process::exit(status);
+}
@@ -0,0 +1,304 @@
+use crate::ch6_unsafe_singly_linked::{self, List};
+
+// A `ch6_unsafe_singly_linked::List` used as a FIFO queue that never holds
+// more than `capacity` elements.
+
+// What `push` does when the queue is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overflow {
+  // Hand the new element back to the caller.
+  Reject,
+  // Drop the element at the front to make room for the new one.
+  DropOldest,
+  // Drop the new element.
+  DropNewest,
+  // Replace the element at the back with the new one.
+  Overwrite,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+  pub rejected: usize,
+  pub dropped_oldest: usize,
+  pub dropped_newest: usize,
+  pub overwritten: usize,
+}
+
+impl Stats {
+  // Elements the queue got rid of on its own, i.e. everything but rejections.
+  pub fn dropped(&self) -> usize {
+    self.dropped_oldest + self.dropped_newest + self.overwritten
+  }
+}
+
+pub struct BoundedQueue<T> {
+  list: List<T>,
+  len: usize,
+  capacity: usize,
+  overflow: Overflow,
+  stats: Stats,
+}
+
+impl<T> BoundedQueue<T> {
+  pub fn new(capacity: usize, overflow: Overflow) -> Self {
+    assert!(capacity > 0, "a bounded queue needs room for at least one element");
+
+    BoundedQueue {
+      list: List::new(),
+      len: 0,
+      capacity,
+      overflow,
+      stats: Stats::default(),
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  pub fn capacity(&self) -> usize {
+    self.capacity
+  }
+
+  pub fn is_full(&self) -> bool {
+    self.len == self.capacity
+  }
+
+  pub fn overflow(&self) -> Overflow {
+    self.overflow
+  }
+
+  pub fn stats(&self) -> Stats {
+    self.stats
+  }
+
+  // Pushes `elem` if there's room, whatever the overflow policy. A full
+  // queue hands it back untouched and without counting it.
+  pub fn try_push(&mut self, elem: T) -> Result<(), T> {
+    if self.is_full() {
+      return Err(elem);
+    }
+
+    self.list.push(elem);
+    self.len += 1;
+    Ok(())
+  }
+
+  // Pushes `elem`, applying the overflow policy if the queue is full. Only
+  // `Overflow::Reject` ever hands the element back.
+  pub fn push(&mut self, elem: T) -> Result<(), T> {
+    if !self.is_full() {
+      return self.try_push(elem);
+    }
+
+    match self.overflow {
+      Overflow::Reject => {
+        self.stats.rejected += 1;
+        return Err(elem);
+      }
+      Overflow::DropOldest => {
+        self.list.pop();
+        self.list.push(elem);
+        self.stats.dropped_oldest += 1;
+      }
+      Overflow::DropNewest => {
+        self.stats.dropped_newest += 1;
+      }
+      Overflow::Overwrite => {
+        *self.list.peek_back_mut().unwrap() = elem;
+        self.stats.overwritten += 1;
+      }
+    }
+
+    Ok(())
+  }
+
+  pub fn pop(&mut self) -> Option<T> {
+    let elem = self.list.pop();
+    if elem.is_some() {
+      self.len -= 1;
+    }
+    elem
+  }
+
+  pub fn peek(&self) -> Option<&T> {
+    self.list.peek()
+  }
+
+  pub fn peek_mut(&mut self) -> Option<&mut T> {
+    self.list.peek_mut()
+  }
+
+  pub fn iter(&self) -> ch6_unsafe_singly_linked::Iter<'_, T> {
+    self.list.iter()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{BoundedQueue, Overflow, Stats};
+  use crate::test_support::{measure, Profile};
+
+  fn filled(overflow: Overflow) -> BoundedQueue<i32> {
+    let mut queue = BoundedQueue::new(3, overflow);
+
+    assert!(queue.is_empty());
+    assert_eq!(queue.push(1), Ok(()));
+    assert_eq!(queue.push(2), Ok(()));
+    assert_eq!(queue.push(3), Ok(()));
+    assert!(queue.is_full());
+
+    queue
+  }
+
+  fn contents(queue: &BoundedQueue<i32>) -> Vec<i32> {
+    queue.iter().cloned().collect()
+  }
+
+  #[test]
+  fn basics() {
+    let mut queue = filled(Overflow::Reject);
+
+    assert_eq!(queue.len(), 3);
+    assert_eq!(queue.capacity(), 3);
+    assert_eq!(queue.peek(), Some(&1));
+
+    assert_eq!(queue.pop(), Some(1));
+    assert!(!queue.is_full());
+    assert_eq!(queue.push(4), Ok(()));
+
+    assert_eq!(queue.pop(), Some(2));
+    assert_eq!(queue.pop(), Some(3));
+    assert_eq!(queue.pop(), Some(4));
+    assert_eq!(queue.pop(), None);
+    assert_eq!(queue.len(), 0);
+    assert_eq!(queue.stats(), Stats::default());
+  }
+
+  #[test]
+  fn try_push_ignores_policy() {
+    for &overflow in &[Overflow::Reject, Overflow::DropOldest, Overflow::DropNewest, Overflow::Overwrite] {
+      let mut queue = filled(overflow);
+
+      assert_eq!(queue.try_push(4), Err(4));
+      assert_eq!(contents(&queue), [1, 2, 3]);
+      assert_eq!(queue.stats(), Stats::default());
+
+      queue.pop();
+      assert_eq!(queue.try_push(4), Ok(()));
+      assert_eq!(contents(&queue), [2, 3, 4]);
+    }
+  }
+
+  #[test]
+  fn reject() {
+    let mut queue = filled(Overflow::Reject);
+
+    assert_eq!(queue.push(4), Err(4));
+    assert_eq!(queue.push(5), Err(5));
+    assert_eq!(contents(&queue), [1, 2, 3]);
+    assert_eq!(queue.stats(), Stats { rejected: 2, ..Stats::default() });
+    assert_eq!(queue.stats().dropped(), 0);
+  }
+
+  #[test]
+  fn drop_oldest() {
+    let mut queue = filled(Overflow::DropOldest);
+
+    assert_eq!(queue.push(4), Ok(()));
+    assert_eq!(queue.push(5), Ok(()));
+    assert_eq!(contents(&queue), [3, 4, 5]);
+    assert_eq!(queue.len(), 3);
+    assert_eq!(queue.stats(), Stats { dropped_oldest: 2, ..Stats::default() });
+
+    assert_eq!(queue.pop(), Some(3));
+    assert_eq!(queue.push(6), Ok(()));
+    assert_eq!(contents(&queue), [4, 5, 6]);
+    assert_eq!(queue.stats().dropped(), 2);
+  }
+
+  #[test]
+  fn drop_newest() {
+    let mut queue = filled(Overflow::DropNewest);
+
+    assert_eq!(queue.push(4), Ok(()));
+    assert_eq!(queue.push(5), Ok(()));
+    assert_eq!(contents(&queue), [1, 2, 3]);
+    assert_eq!(queue.stats(), Stats { dropped_newest: 2, ..Stats::default() });
+    assert_eq!(queue.stats().dropped(), 2);
+  }
+
+  #[test]
+  fn overwrite() {
+    let mut queue = filled(Overflow::Overwrite);
+
+    assert_eq!(queue.push(4), Ok(()));
+    assert_eq!(queue.push(5), Ok(()));
+    assert_eq!(contents(&queue), [1, 2, 5]);
+    assert_eq!(queue.stats(), Stats { overwritten: 2, ..Stats::default() });
+
+    assert_eq!(queue.pop(), Some(1));
+    assert_eq!(queue.push(6), Ok(()));
+    assert_eq!(queue.push(7), Ok(()));
+    assert_eq!(contents(&queue), [2, 5, 7]);
+    assert_eq!(queue.stats().dropped(), 3);
+  }
+
+  #[test]
+  fn capacity_one() {
+    let mut queue = BoundedQueue::new(1, Overflow::Overwrite);
+
+    assert_eq!(queue.push(1), Ok(()));
+    assert_eq!(queue.push(2), Ok(()));
+    assert_eq!(queue.peek(), Some(&2));
+
+    let mut queue = BoundedQueue::new(1, Overflow::DropOldest);
+
+    assert_eq!(queue.push(1), Ok(()));
+    assert_eq!(queue.push(2), Ok(()));
+    assert_eq!(queue.pop(), Some(2));
+    assert_eq!(queue.pop(), None);
+  }
+
+  #[test]
+  #[should_panic]
+  fn zero_capacity() {
+    BoundedQueue::<i32>::new(0, Overflow::Reject);
+  }
+
+  #[test]
+  fn allocations() {
+    let (mut queue, profile) = measure(|| filled(Overflow::DropOldest));
+    assert_eq!((profile.allocations, profile.deallocations), (3, 0));
+
+    // Looking around and giving up on a push don't allocate.
+    let ((), profile) = measure(|| {
+      assert_eq!(queue.peek(), Some(&1));
+      *queue.peek_mut().unwrap() += 10;
+      assert_eq!(queue.iter().count(), 3);
+      assert_eq!(queue.try_push(4), Err(4));
+    });
+    assert_eq!(profile, Profile::default());
+
+    // Dropping the oldest element trades its node for a new one.
+    let (pushed, profile) = measure(|| queue.push(4));
+    assert_eq!(pushed, Ok(()));
+    assert_eq!((profile.allocations, profile.deallocations), (1, 1));
+
+    for &overflow in &[Overflow::Reject, Overflow::DropNewest, Overflow::Overwrite] {
+      let mut queue = filled(overflow);
+      let (_, profile) = measure(|| queue.push(4));
+      assert_eq!(profile, Profile::default());
+    }
+
+    let (elem, profile) = measure(|| queue.pop());
+    assert_eq!(elem, Some(2));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 1));
+    let ((), profile) = measure(|| drop(queue));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 2));
+  }
+}
@@ -1,34 +1,139 @@
-use std::mem;
+use alloc::boxed::Box;
+use core::mem;
+use crate::drop_guard;
+use crate::traits::Stack;
 
-pub struct List {
-  head: Link,
+// A stack that keeps its minimum, maximum and a fold of all its elements up
+// to date, so reading them is O(1). Every node remembers them for itself and
+// everything below it, so popping just uncovers the previous values.
+
+// An associative operation with an identity, folded over the stack from the
+// bottom up: the first element pushed is on the left.
+pub trait Monoid<T> {
+  type Value: Clone;
+
+  fn identity(&self) -> Self::Value;
+  fn lift(&self, elem: &T) -> Self::Value;
+  fn combine(&self, below: &Self::Value, above: &Self::Value) -> Self::Value;
+}
+
+// Folds nothing.
+pub struct NoFold;
+
+impl<T> Monoid<T> for NoFold {
+  type Value = ();
+
+  fn identity(&self) {}
+  fn lift(&self, _elem: &T) {}
+  fn combine(&self, _below: &(), _above: &()) {}
+}
+
+// Both folds side by side, e.g. `(Sum, M)` to keep the sum as well as `M`.
+impl<T, A: Monoid<T>, B: Monoid<T>> Monoid<T> for (A, B) {
+  type Value = (A::Value, B::Value);
+
+  fn identity(&self) -> Self::Value {
+    (self.0.identity(), self.1.identity())
+  }
+
+  fn lift(&self, elem: &T) -> Self::Value {
+    (self.0.lift(elem), self.1.lift(elem))
+  }
+
+  fn combine(&self, below: &Self::Value, above: &Self::Value) -> Self::Value {
+    (self.0.combine(&below.0, &above.0), self.1.combine(&below.1, &above.1))
+  }
+}
+
+// Addition that notices overflow.
+pub trait CheckedAdd: Sized {
+  fn checked_add(&self, other: &Self) -> Option<Self>;
+}
+
+macro_rules! checked_add {
+  ($($t:ty)*) => {
+    $(impl CheckedAdd for $t {
+      fn checked_add(&self, other: &Self) -> Option<Self> {
+        <$t>::checked_add(*self, *other)
+      }
+    })*
+  };
 }
 
-enum Link {
+checked_add!(i8 i16 i32 i64 i128 isize u8 u16 u32 u64 u128 usize);
+
+// `None` once the sum doesn't fit in `T`, until popping brings it back down.
+pub struct Sum;
+
+impl<T: Clone + Default + CheckedAdd> Monoid<T> for Sum {
+  type Value = Option<T>;
+
+  fn identity(&self) -> Option<T> {
+    Some(T::default())
+  }
+
+  fn lift(&self, elem: &T) -> Option<T> {
+    Some(elem.clone())
+  }
+
+  fn combine(&self, below: &Option<T>, above: &Option<T>) -> Option<T> {
+    below.as_ref()?.checked_add(above.as_ref()?)
+  }
+}
+
+pub struct List<T, M: Monoid<T> = NoFold> {
+  head: Link<T, M>,
+  monoid: M,
+}
+
+enum Link<T, M: Monoid<T>> {
   Empty,
-  More(Box<Node>),
+  More(Box<Node<T, M>>),
 }
 
-struct Node {
-  elem: i32,
-  next: Link,
+struct Node<T, M: Monoid<T>> {
+  elem: T,
+  // Of this node and the ones below it.
+  min: T,
+  max: T,
+  fold: M::Value,
+  next: Link<T, M>,
 }
 
-impl List {
+impl<T: Ord + Clone> List<T> {
   pub fn new() -> Self {
-    List { head: Link::Empty }
+    List::with_fold(NoFold)
+  }
+}
+
+impl<T: Ord + Clone, M: Monoid<T>> List<T, M> {
+  pub fn with_fold(monoid: M) -> Self {
+    List { head: Link::Empty, monoid }
   }
 
-  pub fn push(&mut self, elem: i32) {
+  pub fn push(&mut self, elem: T) {
+    let lifted = self.monoid.lift(&elem);
+    let (min, max, fold) = match &self.head {
+      Link::Empty => (elem.clone(), elem.clone(), lifted),
+      Link::More(node) => (
+        node.min.clone().min(elem.clone()),
+        node.max.clone().max(elem.clone()),
+        self.monoid.combine(&node.fold, &lifted),
+      ),
+    };
+
     let new_node = Box::new(Node {
       elem: elem,
+      min,
+      max,
+      fold,
       next: mem::replace(&mut self.head, Link::Empty),
     });
 
     self.head = Link::More(new_node);
   }
 
-  pub fn pop(&mut self) -> Option<i32> {
+  pub fn pop(&mut self) -> Option<T> {
     match mem::replace(&mut self.head, Link::Empty) {
       Link::Empty => None,
       Link::More(node) => {
@@ -39,19 +144,82 @@ impl List {
   }
 }
 
-impl Drop for List {
-  fn drop(&mut self) {
-    let mut cur_link = mem::replace(&mut self.head, Link::Empty);
+impl<T, M: Monoid<T>> List<T, M> {
+  pub fn peek(&self) -> Option<&T> {
+    self.top().map(|node| &node.elem)
+  }
 
-    while let Link::More(mut boxed_node) = cur_link {
-      cur_link = mem::replace(&mut boxed_node.next, Link::Empty);
+  pub fn min(&self) -> Option<&T> {
+    self.top().map(|node| &node.min)
+  }
+
+  pub fn max(&self) -> Option<&T> {
+    self.top().map(|node| &node.max)
+  }
+
+  // The identity for an empty stack.
+  pub fn fold(&self) -> M::Value {
+    match self.top() {
+      Some(node) => node.fold.clone(),
+      None => self.monoid.identity(),
     }
   }
+
+  pub fn monoid(&self) -> &M {
+    &self.monoid
+  }
+
+  fn top(&self) -> Option<&Node<T, M>> {
+    match &self.head {
+      Link::Empty => None,
+      Link::More(node) => Some(node),
+    }
+  }
+}
+
+impl<T> List<T, Sum> where Sum: Monoid<T, Value = Option<T>> {
+  pub fn sum(&self) -> Option<T> {
+    self.fold()
+  }
+}
+
+impl<T: Clone, M: Monoid<T>> List<T, (Sum, M)> where Sum: Monoid<T, Value = Option<T>> {
+  pub fn sum(&self) -> Option<T> {
+    match self.top() {
+      Some(node) => node.fold.0.clone(),
+      None => self.monoid.0.identity(),
+    }
+  }
+}
+
+impl<T, M: Monoid<T>> Drop for List<T, M> {
+  fn drop(&mut self) {
+    // Elements are generic now, so their destructors may panic.
+    drop_guard::drain(&mut self.head, |head| match mem::replace(head, Link::Empty) {
+      Link::Empty => false,
+      Link::More(mut boxed_node) => {
+        *head = mem::replace(&mut boxed_node.next, Link::Empty);
+        true
+      }
+    });
+  }
+}
+
+impl<T: Ord + Clone, M: Monoid<T>> Stack<T> for List<T, M> {
+  fn push(&mut self, elem: T) {
+    List::push(self, elem);
+  }
+
+  fn pop(&mut self) -> Option<T> {
+    List::pop(self)
+  }
 }
 
 #[cfg(test)]
 mod test {
-  use super::List;
+  use super::{List, Monoid, Sum};
+  use crate::rng::Rng;
+  use crate::traits::conformance;
 
   #[test]
   fn basics() {
@@ -75,5 +243,133 @@ mod test {
     assert_eq!(list.pop(), Some(1));
     assert_eq!(list.pop(), None);
   }
-}
 
+  #[test]
+  fn conformance() {
+    conformance::stack(List::new);
+    conformance::stack_allocations(List::new);
+  }
+
+  #[test]
+  fn aggregates() {
+    let mut list = List::with_fold(Sum);
+    assert_eq!((list.min(), list.max(), list.sum()), (None, None, Some(0)));
+
+    list.push(3);
+    list.push(1);
+    list.push(4);
+    assert_eq!((list.min(), list.max(), list.sum()), (Some(&1), Some(&4), Some(8)));
+
+    list.push(1);
+    list.push(5);
+    assert_eq!((list.min(), list.max(), list.sum()), (Some(&1), Some(&5), Some(14)));
+
+    // Popping uncovers what they were before.
+    list.pop();
+    list.pop();
+    assert_eq!((list.min(), list.max(), list.sum()), (Some(&1), Some(&4), Some(8)));
+    list.pop();
+    list.pop();
+    assert_eq!((list.min(), list.max(), list.sum()), (Some(&3), Some(&3), Some(3)));
+    list.pop();
+    assert_eq!((list.min(), list.max(), list.sum()), (None, None, Some(0)));
+  }
+
+  #[test]
+  fn sum_overflow() {
+    let mut list = List::with_fold(Sum);
+    list.push(i32::MAX);
+    list.push(1);
+    assert_eq!(list.sum(), None);
+    list.push(-1);
+    assert_eq!(list.sum(), None);
+
+    list.pop();
+    list.pop();
+    assert_eq!(list.sum(), Some(i32::MAX));
+  }
+
+  // Not commutative, so the order of the fold shows.
+  struct Concat;
+
+  impl Monoid<char> for Concat {
+    type Value = String;
+
+    fn identity(&self) -> String {
+      String::new()
+    }
+
+    fn lift(&self, elem: &char) -> String {
+      elem.to_string()
+    }
+
+    fn combine(&self, below: &String, above: &String) -> String {
+      format!("{}{}", below, above)
+    }
+  }
+
+  #[test]
+  fn fold() {
+    let mut list = List::with_fold(Concat);
+    assert_eq!(list.fold(), "");
+
+    for c in "stack".chars() {
+      list.push(c);
+    }
+    assert_eq!(list.fold(), "stack");
+
+    list.pop();
+    list.pop();
+    list.push('r');
+    assert_eq!(list.fold(), "star");
+  }
+
+  // Polynomial hash of the elements in push order, which is also order
+  // dependent, and cheap enough to keep for every node.
+  const BASE: u64 = 31;
+  const MODULUS: u64 = 1_000_000_007;
+
+  // The hash of a run of elements, with `BASE` to the power of its length.
+  struct Hash;
+
+  impl Monoid<i64> for Hash {
+    type Value = (u64, u64);
+
+    fn identity(&self) -> (u64, u64) {
+      (0, 1)
+    }
+
+    fn lift(&self, elem: &i64) -> (u64, u64) {
+      (elem.rem_euclid(MODULUS as i64) as u64, BASE)
+    }
+
+    fn combine(&self, below: &(u64, u64), above: &(u64, u64)) -> (u64, u64) {
+      ((below.0 * above.1 + above.0) % MODULUS, below.1 * above.1 % MODULUS)
+    }
+  }
+
+  #[test]
+  fn against_brute_force() {
+    let mut rng = Rng::new(44);
+    let mut list = List::with_fold((Sum, Hash));
+    let mut model: Vec<i64> = Vec::new();
+
+    for _ in 0..10_000 {
+      if model.is_empty() || rng.chance(3, 5) {
+        let elem = rng.below(1_000) as i64 - 500;
+        list.push(elem);
+        model.push(elem);
+      } else {
+        assert_eq!(list.pop(), model.pop());
+      }
+
+      assert_eq!(list.peek(), model.last());
+      assert_eq!(list.min(), model.iter().min());
+      assert_eq!(list.max(), model.iter().max());
+      assert_eq!(list.sum(), Some(model.iter().sum::<i64>()));
+
+      let hash = model.iter().fold(0, |hash, elem| (hash * BASE + Hash.lift(elem).0) % MODULUS);
+      assert_eq!(list.fold().1 .0, hash);
+    }
+  }
+}
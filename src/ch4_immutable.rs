@@ -1,4 +1,6 @@
-use std::rc::Rc;
+use alloc::rc::Rc;
+use crate::drop_guard;
+use crate::traits::PersistentStack;
 
 pub struct List<T> {
   head: Link<T>,
@@ -40,16 +42,15 @@ impl<T> List<T> {
 
 impl<T> Drop for List<T> {
   fn drop(&mut self) {
-    let mut head = self.head.take();
-
     // TODO open a PR with this naming `..._rc` to be clearer
-    while let Some(node_rc) = head {
-      if let Ok(mut node) = Rc::try_unwrap(node_rc) {
-        head = node.next.take();
-      } else {
-        break;
+    drop_guard::drain(&mut self.head, |head| match head.take().map(Rc::try_unwrap) {
+      Some(Ok(mut node)) => {
+        *head = node.next.take();
+        true
       }
-    }
+      // Shared with another list, which still needs the rest.
+      Some(Err(_)) | None => false,
+    });
   }
 }
 
@@ -68,9 +69,25 @@ impl<'a, T> Iterator for Iter<'a, T> {
   }
 }
 
+impl<T> PersistentStack<T> for List<T> {
+  fn push(&self, elem: T) -> Self {
+    self.append(elem)
+  }
+
+  fn peek(&self) -> Option<&T> {
+    self.head()
+  }
+
+  fn tail(&self) -> Self {
+    self.tail()
+  }
+}
+
 #[cfg(test)]
 mod test {
   use super::List;
+  use crate::test_support::{measure, Profile};
+  use crate::traits::conformance;
 
   #[test]
   fn basics() {
@@ -106,5 +123,34 @@ mod test {
     assert_eq!(iter.next(), Some(&1));
     assert_eq!(iter.next(), None);
   }
-}
 
+  #[test]
+  fn conformance() {
+    conformance::persistent_stack(List::new);
+    conformance::persistent_stack_drops(List::new);
+  }
+
+  #[test]
+  fn allocations() {
+    let (list, profile) = measure(List::new);
+    assert_eq!(profile, Profile::default());
+
+    // Each version adds one node and shares the rest.
+    let (one, profile) = measure(|| list.append(1));
+    assert_eq!((profile.allocations, profile.deallocations), (1, 0));
+    let two = one.append(2);
+
+    let ((), profile) = measure(|| {
+      assert_eq!(two.head(), Some(&2));
+      assert_eq!(two.iter().count(), 2);
+      assert_eq!(two.tail().head(), Some(&1));
+    });
+    assert_eq!(profile, Profile::default());
+
+    // Dropping a version only frees the nodes nobody else holds.
+    let ((), profile) = measure(|| drop(two));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 1));
+    let ((), profile) = measure(|| drop(one));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 1));
+  }
+}
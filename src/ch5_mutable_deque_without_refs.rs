@@ -1,9 +1,13 @@
-use std::rc::Rc;
-use std::cell::{Ref, RefMut, RefCell};
+use alloc::rc::Rc;
+use core::cell::{Ref, RefMut, RefCell};
+use crate::drop_guard;
+use crate::observer::{Event, NoObserver, Observer};
+use crate::traits::{Deque, Queue, Stack};
 
-pub struct List<T> {
+pub struct List<T, O = NoObserver> {
   head: Link<T>,
   tail: Link<T>,
+  observer: O,
 }
 
 type Link<T> = Option<Rc<RefCell<Node<T>>>>;
@@ -26,7 +30,35 @@ impl<T> Node<T> {
 
 impl<T> List<T> {
   pub fn new() -> Self {
-    List { head: None, tail: None }
+    List::with_observer(NoObserver)
+  }
+
+  // Changes made through these can't be reported, so they're only available
+  // on lists nobody is watching; observed lists go through `update`.
+  pub fn peek_front_mut(&self) -> Option<RefMut<T>> {
+    self.head.as_ref().map(|head| {
+      RefMut::map(head.borrow_mut(), |node| &mut node.elem)
+    })
+  }
+
+  pub fn peek_back_mut(&self) -> Option<RefMut<T>> {
+    self.tail.as_ref().map(|tail| {
+      RefMut::map(tail.borrow_mut(), |node| &mut node.elem)
+    })
+  }
+}
+
+impl<T, O: Observer<T>> List<T, O> {
+  pub fn with_observer(observer: O) -> Self {
+    List { head: None, tail: None, observer }
+  }
+
+  pub fn observer(&self) -> &O {
+    &self.observer
+  }
+
+  pub fn observer_mut(&mut self) -> &mut O {
+    &mut self.observer
   }
 
   pub fn push_front(&mut self, elem: T) {
@@ -43,6 +75,11 @@ impl<T> List<T> {
         self.head = Some(new_head);
       }
     }
+
+    if O::ENABLED {
+      let head = self.head.as_ref().unwrap().borrow();
+      self.observer.notify(Event::PushedFront(&head.elem));
+    }
   }
 
   pub fn push_back(&mut self, elem: T) {
@@ -59,9 +96,74 @@ impl<T> List<T> {
         self.tail = Some(new_tail);
       }
     }
+
+    if O::ENABLED {
+      let tail = self.tail.as_ref().unwrap().borrow();
+      self.observer.notify(Event::PushedBack(&tail.elem));
+    }
   }
 
   pub fn pop_front(&mut self) -> Option<T> {
+    let elem = self.unlink_front();
+    if elem.is_some() {
+      self.observer.notify(Event::PoppedFront);
+    }
+    elem
+  }
+
+  pub fn pop_back(&mut self) -> Option<T> {
+    let elem = self.unlink_back();
+    if elem.is_some() {
+      self.observer.notify(Event::PoppedBack);
+    }
+    elem
+  }
+
+  pub fn clear(&mut self) {
+    drop_guard::drain(self, |list| list.unlink_front().is_some());
+    self.observer.notify(Event::Cleared);
+  }
+
+  // Runs `f` on the element at `index`, counting from the front, and reports
+  // the change. Returns `None` if there's no such element.
+  pub fn update<R, F: FnOnce(&mut T) -> R>(&mut self, index: usize, f: F) -> Option<R> {
+    let mut cur_link = self.head.clone();
+    for _ in 0..index {
+      let node = cur_link?;
+      cur_link = node.borrow().next.clone();
+    }
+
+    let node = cur_link?;
+    let mut node = node.borrow_mut();
+    let result = f(&mut node.elem);
+    self.observer.notify(Event::Mutated(index, &node.elem));
+    Some(result)
+  }
+
+  pub fn into_iter(self) -> IntoIter<T, O> {
+    IntoIter(self)
+  }
+
+/* Kind of impossible to implement :\ */
+//  pub fn iter(&self) -> Iter<T> {
+//    Iter(self.head.as_ref().map(|head| head.borrow()))
+//  }
+}
+
+impl<T, O> List<T, O> {
+  pub fn peek_front(&self) -> Option<Ref<T>> {
+    self.head.as_ref().map(|head| {
+      Ref::map(head.borrow(), |node| &node.elem)
+    })
+  }
+
+  pub fn peek_back(&self) -> Option<Ref<T>> {
+    self.tail.as_ref().map(|tail| {
+      Ref::map(tail.borrow(), |node| &node.elem)
+    })
+  }
+
+  fn unlink_front(&mut self) -> Option<T> {
     self.head.take().map(|old_head| {
       match old_head.borrow_mut().next.take() {
         Some(new_head) => {
@@ -76,7 +178,7 @@ impl<T> List<T> {
     })
   }
 
-  pub fn pop_back(&mut self) -> Option<T> {
+  fn unlink_back(&mut self) -> Option<T> {
     self.tail.take().map(|old_tail| {
       match old_tail.borrow_mut().prev.take() {
         Some(new_tail) => {
@@ -90,57 +192,24 @@ impl<T> List<T> {
       Rc::try_unwrap(old_tail).ok().unwrap().into_inner().elem
     })
   }
-
-  pub fn peek_front(&self) -> Option<Ref<T>> {
-    self.head.as_ref().map(|head| {
-      Ref::map(head.borrow(), |node| &node.elem)
-    })
-  }
-
-  pub fn peek_back(&self) -> Option<Ref<T>> {
-    self.tail.as_ref().map(|tail| {
-      Ref::map(tail.borrow(), |node| &node.elem)
-    })
-  }
-
-  pub fn peek_front_mut(&self) -> Option<RefMut<T>> {
-    self.head.as_ref().map(|head| {
-      RefMut::map(head.borrow_mut(), |node| &mut node.elem)
-    })
-  }
-
-  pub fn peek_back_mut(&self) -> Option<RefMut<T>> {
-    self.tail.as_ref().map(|tail| {
-      RefMut::map(tail.borrow_mut(), |node| &mut node.elem)
-    })
-  }
-
-  pub fn into_iter(self) -> IntoIter<T> {
-    IntoIter(self)
-  }
-
-/* Kind of impossible to implement :\ */
-//  pub fn iter(&self) -> Iter<T> {
-//    Iter(self.head.as_ref().map(|head| head.borrow()))
-//  }
 }
 
-impl<T> Drop for List<T> {
+impl<T, O> Drop for List<T, O> {
   fn drop(&mut self) {
-    while self.pop_front().is_some() {}
+    drop_guard::drain(self, |list| list.unlink_front().is_some());
   }
 }
 
-pub struct IntoIter<T>(List<T>);
+pub struct IntoIter<T, O = NoObserver>(List<T, O>);
 
-impl<T> Iterator for IntoIter<T> {
+impl<T, O: Observer<T>> Iterator for IntoIter<T, O> {
   type Item = T;
   fn next(&mut self) -> Option<T> {
     self.0.pop_front()
   }
 }
 
-impl<T> DoubleEndedIterator for IntoIter<T> {
+impl<T, O: Observer<T>> DoubleEndedIterator for IntoIter<T, O> {
   fn next_back(&mut self) -> Option<T> {
     self.0.pop_back()
   }
@@ -182,9 +251,52 @@ impl<T> DoubleEndedIterator for IntoIter<T> {
 //  }
 //}
 
+impl<T, O: Observer<T>> Stack<T> for List<T, O> {
+  fn push(&mut self, elem: T) {
+    self.push_front(elem);
+  }
+
+  fn pop(&mut self) -> Option<T> {
+    self.pop_front()
+  }
+}
+
+impl<T, O: Observer<T>> Queue<T> for List<T, O> {
+  fn enqueue(&mut self, elem: T) {
+    self.push_back(elem);
+  }
+
+  fn dequeue(&mut self) -> Option<T> {
+    self.pop_front()
+  }
+}
+
+impl<T, O: Observer<T>> Deque<T> for List<T, O> {
+  fn push_front(&mut self, elem: T) {
+    List::push_front(self, elem);
+  }
+
+  fn push_back(&mut self, elem: T) {
+    List::push_back(self, elem);
+  }
+
+  fn pop_front(&mut self) -> Option<T> {
+    List::pop_front(self)
+  }
+
+  fn pop_back(&mut self) -> Option<T> {
+    List::pop_back(self)
+  }
+}
+
 #[cfg(test)]
 mod test {
   use super::List;
+  use crate::model::{deque_harness, peeked};
+  use crate::observer::NoObserver;
+  use crate::test_support::{assert_panic_safe, measure, DropCounter, Profile};
+  use crate::traits::conformance;
+  use std::panic::{self, AssertUnwindSafe};
 
   #[test]
   fn basics() {
@@ -263,4 +375,135 @@ mod test {
     assert_eq!(iter.next_back(), None);
     assert_eq!(iter.next(), None);
   }
+
+  #[test]
+  fn observer() {
+    use crate::observer::Mirror;
+
+    type Op = fn(&mut List<i32, Mirror<i32>>);
+
+    let ops: Vec<Op> = vec![
+      |list| list.push_back(1),
+      |list| list.push_back(2),
+      |list| list.push_front(3),
+      |list| { list.pop_back(); },
+      |list| list.push_back(4),
+      |list| list.push_front(5),
+      |list| { list.pop_front(); },
+      |list| { list.update(1, |elem| *elem *= 10); },
+      |list| { list.update(3, |elem| *elem *= 10); },
+      |list| list.clear(),
+      |list| { list.pop_back(); },
+      |list| { list.pop_front(); },
+      |list| list.push_front(6),
+      |list| { list.update(0, |elem| *elem += 1); },
+      |list| { list.pop_front(); },
+      |list| list.push_back(7),
+      |list| list.push_front(8),
+    ];
+
+    let mut list = List::with_observer(Mirror::new());
+
+    for op in ops {
+      op(&mut list);
+
+      let elems = &list.observer().elems;
+      assert_eq!(list.peek_front().map(|elem| *elem), elems.front().cloned());
+      assert_eq!(list.peek_back().map(|elem| *elem), elems.back().cloned());
+    }
+
+    let mirror = list.observer().elems.clone();
+    let mut iter = list.into_iter();
+    for elem in mirror {
+      assert_eq!(iter.next(), Some(elem));
+    }
+    assert_eq!(iter.next(), None);
+  }
+
+  #[test]
+  fn conformance() {
+    conformance::deque(List::new);
+    conformance::queue(List::new);
+    conformance::stack(List::new);
+    conformance::queue_allocations(List::new);
+    conformance::stack_allocations(List::new);
+    conformance::stack_drops(List::new);
+  }
+
+  // There's no `iter`, so each step is checked through both ends.
+  #[test]
+  fn model() {
+    deque_harness!(
+      "ch5_mutable_deque_without_refs", List::new, "let mut list = List::new();",
+      observe: |list| (peeked(list.peek_front()), peeked(list.peek_back())),
+      expect: |model| (model.front().copied(), model.back().copied()),
+      observe_code: "(list.peek_front().map(|value| *value), list.peek_back().map(|value| *value))",
+    ).assert(500, 100);
+  }
+
+  #[test]
+  fn allocations() {
+    let mut list = List::with_observer(NoObserver);
+    list.push_front(2);
+    list.push_back(3);
+    list.push_front(1);
+    list.push_back(4);
+
+    let ((), profile) = measure(|| {
+      assert_eq!(*list.peek_front().unwrap(), 1);
+      assert_eq!(*list.peek_back().unwrap(), 4);
+      *list.peek_front_mut().unwrap() += 10;
+      *list.peek_back_mut().unwrap() += 10;
+      assert_eq!(list.update(1, |elem| *elem *= 10), Some(()));
+      let _ = list.observer();
+      let _ = list.observer_mut();
+    });
+    assert_eq!(profile, Profile::default());
+
+    assert_eq!(list.pop_front(), Some(11));
+    let (elem, profile) = measure(|| list.pop_back());
+    assert_eq!(elem, Some(14));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 1));
+
+    let (mut iter, profile) = measure(|| list.into_iter());
+    assert_eq!(profile, Profile::default());
+    let (elem, profile) = measure(|| iter.next_back());
+    assert_eq!(elem, Some(3));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 1));
+    let ((), profile) = measure(|| drop(iter));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 1));
+
+    let mut list = List::new();
+    for i in 0..10 {
+      list.push_back(i);
+    }
+    let ((), profile) = measure(|| list.clear());
+    assert_eq!((profile.allocations, profile.deallocations), (0, 10));
+  }
+
+  #[test]
+  fn panic_safe_drops() {
+    let fill = |counter: &DropCounter| {
+      let mut list = List::new();
+      for _ in 0..5 {
+        list.push_back(counter.elem());
+      }
+      list
+    };
+
+    assert_panic_safe(5, fill, |mut list| list.clear());
+    assert_panic_safe(5, fill, |list| {
+      let mut iter = list.into_iter();
+      iter.next_back();
+    });
+
+    // A panicking `clear` still leaves an empty, usable list behind.
+    let counter = DropCounter::panicking_on(2);
+    let mut list = fill(&counter);
+    assert!(panic::catch_unwind(AssertUnwindSafe(|| list.clear())).is_err());
+    assert!(list.peek_front().is_none() && list.peek_back().is_none());
+    assert_eq!(counter.live(), 0);
+    list.push_back(counter.elem());
+    assert!(list.peek_front().is_some());
+  }
 }
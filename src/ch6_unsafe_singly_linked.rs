@@ -1,4 +1,9 @@
-use std::ptr;
+use alloc::boxed::Box;
+use core::ptr;
+#[cfg(any(debug_assertions, feature = "invariants"))]
+use crate::invariants::{self, InvariantError};
+use crate::drop_guard;
+use crate::traits::Queue;
 
 pub struct List<T> {
   head: Link<T>,
@@ -12,6 +17,10 @@ struct Node<T> {
   next: Link<T>,
 }
 
+// `tail` only ever points into nodes the list owns.
+unsafe impl<T: Send> Send for List<T> {}
+unsafe impl<T: Sync> Sync for List<T> {}
+
 impl<T> List<T> {
   pub fn new() -> Self {
     List { head: None, tail: ptr::null_mut() }
@@ -34,10 +43,11 @@ impl<T> List<T> {
     }
 
     self.tail = raw_tail;
+    self.after_mutation();
   }
 
   pub fn pop(&mut self) -> Option<T> {
-    self.head.take().map(|old_head| {
+    let elem = self.head.take().map(|old_head| {
       self.head = old_head.next;
 
       if self.head.is_none() {
@@ -45,7 +55,9 @@ impl<T> List<T> {
       }
 
       old_head.elem
-    })
+    });
+    self.after_mutation();
+    elem
   }
 
   pub fn peek(&self) -> Option<&T> {
@@ -60,6 +72,61 @@ impl<T> List<T> {
     })
   }
 
+  pub fn peek_back(&self) -> Option<&T> {
+    if self.tail.is_null() {
+      None
+    } else {
+      unsafe {
+        Some(&(*self.tail).elem)
+      }
+    }
+  }
+
+  pub fn peek_back_mut(&mut self) -> Option<&mut T> {
+    if self.tail.is_null() {
+      None
+    } else {
+      unsafe {
+        Some(&mut (*self.tail).elem)
+      }
+    }
+  }
+
+  // Checks that `tail` is the last node and that following `next` ends.
+  #[cfg(any(debug_assertions, feature = "invariants"))]
+  pub fn check_invariants(&self) -> Result<(), InvariantError> {
+    let head = match &self.head {
+      None if self.tail.is_null() => return Ok(()),
+      None => return Err(InvariantError::EmptyMismatch { head_is_none: true }),
+      Some(_) if self.tail.is_null() => return Err(InvariantError::EmptyMismatch { head_is_none: false }),
+      Some(head) => head,
+    };
+
+    if let Some(index) = invariants::find_cycle(&**head, |node| node.next.as_deref()) {
+      return Err(InvariantError::Cycle { index });
+    }
+
+    let mut cur: &Node<T> = head;
+    let mut len = 1;
+    while let Some(next) = &cur.next {
+      cur = next;
+      len += 1;
+    }
+
+    if !ptr::eq(cur, self.tail) {
+      return Err(InvariantError::TailNotLast { len });
+    }
+    Ok(())
+  }
+
+  // Called at the end of every mutating method.
+  fn after_mutation(&self) {
+    #[cfg(feature = "strict-invariants")]
+    if let Err(error) = self.check_invariants() {
+      panic!("broken `ch6_unsafe_singly_linked::List`: {}", error);
+    }
+  }
+
   pub fn into_iter(self) -> IntoIter<T> {
     IntoIter(self)
   }
@@ -75,10 +142,14 @@ impl<T> List<T> {
 
 impl<T> Drop for List<T> {
   fn drop(&mut self) {
-    let mut cur_link = self.head.take();
-    while let Some(mut boxed_node) = cur_link {
-      cur_link = boxed_node.next.take();
-    }
+    self.tail = ptr::null_mut();
+    drop_guard::drain(&mut self.head, |head| match head.take() {
+      Some(mut node) => {
+        *head = node.next.take();
+        true
+      }
+      None => false,
+    });
   }
 }
 
@@ -122,9 +193,22 @@ impl<'a, T> Iterator for IterMut<'a, T> {
   }
 }
 
+impl<T> Queue<T> for List<T> {
+  fn enqueue(&mut self, elem: T) {
+    self.push(elem);
+  }
+
+  fn dequeue(&mut self) -> Option<T> {
+    self.pop()
+  }
+}
+
 #[cfg(test)]
 mod test {
   use super::List;
+  use crate::model::queue_harness;
+  use crate::test_support::{assert_panic_safe, measure, DropCounter, Profile};
+  use crate::traits::conformance;
 
   #[test]
   fn basics() {
@@ -178,6 +262,29 @@ mod test {
     assert_eq!(list.peek(), None);
     assert_eq!(list.peek_mut(), None);
   }
+
+  #[test]
+  fn peek_back() {
+    let mut list = List::new();
+
+    assert!(list.peek_back().is_none());
+    assert!(list.peek_back_mut().is_none());
+
+    list.push(1);
+    assert_eq!(list.peek_back(), Some(&1));
+
+    list.push(2);
+    assert_eq!(list.peek_back(), Some(&2));
+
+    if let Some(elem) = list.peek_back_mut() {
+      *elem = 3;
+    }
+
+    assert_eq!(list.pop(), Some(1));
+    assert_eq!(list.peek_back(), Some(&3));
+    assert_eq!(list.pop(), Some(3));
+    assert_eq!(list.peek_back(), None);
+  }
 //
 //  #[test]
 //  fn into_iter() {
@@ -193,4 +300,96 @@ mod test {
 //    assert_eq!(iter.next(), Some(2));
 //    assert_eq!(iter.next(), Some(3));
 //  }
+
+  #[test]
+  fn conformance() {
+    conformance::queue(List::new);
+    conformance::queue_allocations(List::new);
+    conformance::queue_drops(List::new);
+  }
+
+  #[cfg(any(debug_assertions, feature = "invariants"))]
+  #[test]
+  fn invariants() {
+    use crate::invariants::InvariantError;
+    use std::ptr;
+
+    let mut list = List::new();
+    assert_eq!(list.check_invariants(), Ok(()));
+
+    for i in 0..5 {
+      list.push(i);
+    }
+    list.pop();
+    assert_eq!(list.check_invariants(), Ok(()));
+
+    let tail = list.tail;
+    let head: *mut _ = &mut **list.head.as_mut().unwrap();
+
+    list.tail = ptr::null_mut();
+    assert_eq!(list.check_invariants(), Err(InvariantError::EmptyMismatch { head_is_none: false }));
+    list.tail = head;
+    assert_eq!(list.check_invariants(), Err(InvariantError::TailNotLast { len: 4 }));
+    list.tail = tail;
+    // Closing a cycle of `Box`es takes two of them owning one node, so
+    // `invariants::test::finds_cycles` covers that on plain indices.
+    assert_eq!(list.check_invariants(), Ok(()));
+
+    let mut empty = List::<i32>::new();
+    empty.tail = tail;
+    assert_eq!(empty.check_invariants(), Err(InvariantError::EmptyMismatch { head_is_none: true }));
+    empty.tail = ptr::null_mut();
+  }
+
+  #[test]
+  fn model() {
+    queue_harness!("ch6_unsafe_singly_linked", List::new, "let mut list = List::new();").assert(500, 100);
+  }
+
+  #[test]
+  fn allocations() {
+    let mut list = List::new();
+    list.push(1);
+    list.push(2);
+    list.push(3);
+
+    let ((), profile) = measure(|| {
+      assert_eq!(list.peek(), Some(&1));
+      assert_eq!(list.peek_back(), Some(&3));
+      *list.peek_mut().unwrap() += 1;
+      *list.peek_back_mut().unwrap() += 1;
+      assert_eq!(list.iter().count(), 3);
+      list.iter_mut().for_each(|elem| *elem *= 10);
+    });
+    assert_eq!(profile, Profile::default());
+
+    #[cfg(any(debug_assertions, feature = "invariants"))]
+    assert_eq!(measure(|| list.check_invariants()), (Ok(()), Profile::default()));
+
+    assert_eq!(list.pop(), Some(20));
+
+    let (mut iter, profile) = measure(|| list.into_iter());
+    assert_eq!(profile, Profile::default());
+    let (elem, profile) = measure(|| iter.next());
+    assert_eq!(elem, Some(20));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 1));
+    let ((), profile) = measure(|| drop(iter));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 1));
+  }
+
+  #[test]
+  fn panic_safe_drops() {
+    let fill = |counter: &DropCounter| {
+      let mut list = List::new();
+      for _ in 0..5 {
+        list.push(counter.elem());
+      }
+      list
+    };
+
+    assert_panic_safe(5, fill, |list| {
+      let mut iter = list.into_iter();
+      iter.next();
+    });
+  }
 }
@@ -0,0 +1,493 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::ch6_unsafe_singly_linked::List;
+
+// Multi-producer, multi-consumer channel: a `ch6_unsafe_singly_linked::List`
+// behind a `Mutex`, with one `Condvar` for receivers waiting on an empty
+// queue and another for senders waiting on a full one.
+
+struct State<T> {
+  queue: List<T>,
+  len: usize,
+  senders: usize,
+  receivers: usize,
+}
+
+struct Shared<T> {
+  state: Mutex<State<T>>,
+  bound: Option<usize>,
+  not_empty: Condvar,
+  not_full: Condvar,
+}
+
+impl<T> Shared<T> {
+  fn lock(&self) -> MutexGuard<'_, State<T>> {
+    self.state.lock().unwrap()
+  }
+
+  fn is_full(&self, state: &State<T>) -> bool {
+    self.bound.is_some_and(|bound| state.len >= bound)
+  }
+}
+
+impl<T> State<T> {
+  fn pop(&mut self) -> Option<T> {
+    let elem = self.queue.pop();
+    if elem.is_some() {
+      self.len -= 1;
+    }
+    elem
+  }
+}
+
+pub struct Sender<T> {
+  shared: Arc<Shared<T>>,
+}
+
+pub struct Receiver<T> {
+  shared: Arc<Shared<T>>,
+}
+
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+  with_bound(None)
+}
+
+// `send` blocks while `bound` messages are waiting to be received.
+pub fn bounded<T>(bound: usize) -> (Sender<T>, Receiver<T>) {
+  assert!(bound > 0, "a bounded channel needs room for at least one message");
+  with_bound(Some(bound))
+}
+
+fn with_bound<T>(bound: Option<usize>) -> (Sender<T>, Receiver<T>) {
+  let shared = Arc::new(Shared {
+    state: Mutex::new(State {
+      queue: List::new(),
+      len: 0,
+      senders: 1,
+      receivers: 1,
+    }),
+    bound,
+    not_empty: Condvar::new(),
+    not_full: Condvar::new(),
+  });
+
+  (Sender { shared: shared.clone() }, Receiver { shared })
+}
+
+impl<T> Sender<T> {
+  // Fails, handing the message back, once every receiver is gone.
+  pub fn send(&self, elem: T) -> Result<(), SendError<T>> {
+    let mut state = self.shared.lock();
+
+    loop {
+      if state.receivers == 0 {
+        return Err(SendError(elem));
+      }
+      if !self.shared.is_full(&state) {
+        break;
+      }
+      state = self.shared.not_full.wait(state).unwrap();
+    }
+
+    state.queue.push(elem);
+    state.len += 1;
+    drop(state);
+
+    self.shared.not_empty.notify_one();
+    Ok(())
+  }
+}
+
+impl<T> Clone for Sender<T> {
+  fn clone(&self) -> Self {
+    self.shared.lock().senders += 1;
+    Sender { shared: self.shared.clone() }
+  }
+}
+
+impl<T> Drop for Sender<T> {
+  fn drop(&mut self) {
+    let mut state = self.shared.lock();
+    state.senders -= 1;
+    if state.senders == 0 {
+      drop(state);
+      self.shared.not_empty.notify_all();
+    }
+  }
+}
+
+impl<T> Receiver<T> {
+  // Blocks until a message arrives. Fails once the channel is empty and every
+  // sender is gone.
+  pub fn recv(&self) -> Result<T, RecvError> {
+    let mut state = self.shared.lock();
+
+    loop {
+      if let Some(elem) = state.pop() {
+        drop(state);
+        self.shared.not_full.notify_one();
+        return Ok(elem);
+      }
+      if state.senders == 0 {
+        return Err(RecvError);
+      }
+      state = self.shared.not_empty.wait(state).unwrap();
+    }
+  }
+
+  pub fn try_recv(&self) -> Result<T, TryRecvError> {
+    let mut state = self.shared.lock();
+
+    match state.pop() {
+      Some(elem) => {
+        drop(state);
+        self.shared.not_full.notify_one();
+        Ok(elem)
+      }
+      None if state.senders == 0 => Err(TryRecvError::Disconnected),
+      None => Err(TryRecvError::Empty),
+    }
+  }
+
+  pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+    let deadline = Instant::now() + timeout;
+    let mut state = self.shared.lock();
+
+    loop {
+      if let Some(elem) = state.pop() {
+        drop(state);
+        self.shared.not_full.notify_one();
+        return Ok(elem);
+      }
+      if state.senders == 0 {
+        return Err(RecvTimeoutError::Disconnected);
+      }
+
+      let now = Instant::now();
+      if now >= deadline {
+        return Err(RecvTimeoutError::Timeout);
+      }
+      state = self.shared.not_empty.wait_timeout(state, deadline - now).unwrap().0;
+    }
+  }
+
+  // Blocks for each message; ends once every sender is gone.
+  pub fn iter(&self) -> Iter<'_, T> {
+    Iter { receiver: self }
+  }
+
+  // Only yields the messages that are already waiting.
+  pub fn try_iter(&self) -> TryIter<'_, T> {
+    TryIter { receiver: self }
+  }
+}
+
+impl<T> Clone for Receiver<T> {
+  fn clone(&self) -> Self {
+    self.shared.lock().receivers += 1;
+    Receiver { shared: self.shared.clone() }
+  }
+}
+
+impl<T> Drop for Receiver<T> {
+  fn drop(&mut self) {
+    let mut state = self.shared.lock();
+    state.receivers -= 1;
+    if state.receivers == 0 {
+      drop(state);
+      self.shared.not_full.notify_all();
+    }
+  }
+}
+
+pub struct Iter<'a, T> {
+  receiver: &'a Receiver<T>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+  type Item = T;
+
+  fn next(&mut self) -> Option<T> {
+    self.receiver.recv().ok()
+  }
+}
+
+pub struct TryIter<'a, T> {
+  receiver: &'a Receiver<T>,
+}
+
+impl<'a, T> Iterator for TryIter<'a, T> {
+  type Item = T;
+
+  fn next(&mut self) -> Option<T> {
+    self.receiver.try_recv().ok()
+  }
+}
+
+pub struct IntoIter<T> {
+  receiver: Receiver<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+  type Item = T;
+
+  fn next(&mut self) -> Option<T> {
+    self.receiver.recv().ok()
+  }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+  type Item = T;
+  type IntoIter = IntoIter<T>;
+
+  fn into_iter(self) -> IntoIter<T> {
+    IntoIter { receiver: self }
+  }
+}
+
+impl<'a, T> IntoIterator for &'a Receiver<T> {
+  type Item = T;
+  type IntoIter = Iter<'a, T>;
+
+  fn into_iter(self) -> Iter<'a, T> {
+    self.iter()
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Debug for SendError<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("SendError { .. }")
+  }
+}
+
+impl<T> fmt::Display for SendError<T> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("sending on a channel with no receivers")
+  }
+}
+
+impl<T> Error for SendError<T> {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecvError;
+
+impl fmt::Display for RecvError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("receiving on an empty channel with no senders")
+  }
+}
+
+impl Error for RecvError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TryRecvError {
+  Empty,
+  Disconnected,
+}
+
+impl fmt::Display for TryRecvError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      TryRecvError::Empty => f.write_str("receiving on an empty channel"),
+      TryRecvError::Disconnected => fmt::Display::fmt(&RecvError, f),
+    }
+  }
+}
+
+impl Error for TryRecvError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecvTimeoutError {
+  Timeout,
+  Disconnected,
+}
+
+impl fmt::Display for RecvTimeoutError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      RecvTimeoutError::Timeout => f.write_str("timed out waiting on a channel"),
+      RecvTimeoutError::Disconnected => fmt::Display::fmt(&RecvError, f),
+    }
+  }
+}
+
+impl Error for RecvTimeoutError {}
+
+#[cfg(test)]
+mod test {
+  use super::{bounded, channel, RecvError, RecvTimeoutError, SendError, TryRecvError};
+  use std::thread;
+  use std::time::Duration;
+
+  #[test]
+  fn basics() {
+    let (tx, rx) = channel();
+
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+
+    tx.send(1).unwrap();
+    tx.send(2).unwrap();
+    tx.clone().send(3).unwrap();
+
+    assert_eq!(rx.recv(), Ok(1));
+    assert_eq!(rx.try_recv(), Ok(2));
+    assert_eq!(rx.recv_timeout(Duration::from_millis(10)), Ok(3));
+    assert_eq!(rx.recv_timeout(Duration::from_millis(10)), Err(RecvTimeoutError::Timeout));
+
+    tx.send(4).unwrap();
+    drop(tx);
+
+    assert_eq!(rx.recv(), Ok(4));
+    assert_eq!(rx.recv(), Err(RecvError));
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    assert_eq!(rx.recv_timeout(Duration::from_millis(10)), Err(RecvTimeoutError::Disconnected));
+  }
+
+  #[test]
+  fn send_without_receivers() {
+    let (tx, rx) = channel();
+    let rx2 = rx.clone();
+
+    drop(rx);
+    assert_eq!(tx.send(1), Ok(()));
+
+    drop(rx2);
+    assert_eq!(tx.send(2), Err(SendError(2)));
+  }
+
+  #[test]
+  fn iterators() {
+    let (tx, rx) = channel();
+
+    for i in 0..5 {
+      tx.send(i).unwrap();
+    }
+
+    assert_eq!(rx.try_iter().take(2).collect::<Vec<_>>(), [0, 1]);
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), [2, 3, 4]);
+
+    let producer = thread::spawn(move || {
+      for i in 5..10 {
+        tx.send(i).unwrap();
+      }
+    });
+
+    assert_eq!(rx.into_iter().collect::<Vec<_>>(), [5, 6, 7, 8, 9]);
+    producer.join().unwrap();
+  }
+
+  #[test]
+  fn bounded_send_blocks() {
+    let (tx, rx) = bounded(2);
+    let (sent, progress) = channel();
+
+    let producer = thread::spawn(move || {
+      for i in 0..100 {
+        tx.send(i).unwrap();
+        sent.send(i).unwrap();
+      }
+    });
+
+    // Two fit, and the third waits for room however long it takes.
+    assert_eq!(progress.recv(), Ok(0));
+    assert_eq!(progress.recv(), Ok(1));
+    assert_eq!(progress.recv_timeout(Duration::from_millis(20)), Err(RecvTimeoutError::Timeout));
+
+    // Each message received makes room for exactly one more.
+    assert_eq!(rx.recv(), Ok(0));
+    assert_eq!(progress.recv(), Ok(2));
+    assert_eq!(progress.recv_timeout(Duration::from_millis(20)), Err(RecvTimeoutError::Timeout));
+
+    let received: Vec<_> = rx.iter().collect();
+    producer.join().unwrap();
+
+    assert_eq!(received, (1..100).collect::<Vec<_>>());
+  }
+
+  #[test]
+  fn bounded_send_wakes_up_on_disconnect() {
+    let (tx, rx) = bounded(1);
+
+    tx.send(1).unwrap();
+    let (ready, started) = channel();
+    let producer = thread::spawn(move || {
+      ready.send(()).unwrap();
+      tx.send(2)
+    });
+
+    // Whether or not the send is waiting by now, it has to fail.
+    started.recv().unwrap();
+    drop(rx);
+
+    assert_eq!(producer.join().unwrap(), Err(SendError(2)));
+  }
+
+  const SENDERS: usize = 4;
+  const RECEIVERS: usize = 3;
+  // The queue walks itself on every send under strict invariant checking.
+  const PER_SENDER: usize = if cfg!(feature = "strict-invariants") { 500 } else { 5_000 };
+
+  fn check_per_sender_order(received: &[(usize, usize)]) {
+    let mut last = [None; SENDERS];
+    for &(sender, i) in received {
+      assert!(last[sender].is_none_or(|last| last < i));
+      last[sender] = Some(i);
+    }
+  }
+
+  fn mpmc(bound: Option<usize>) {
+    let (tx, rx) = match bound {
+      Some(bound) => bounded(bound),
+      None => channel(),
+    };
+
+    let producers: Vec<_> = (0..SENDERS).map(|sender| {
+      let tx = tx.clone();
+      thread::spawn(move || {
+        for i in 0..PER_SENDER {
+          tx.send((sender, i)).unwrap();
+        }
+      })
+    }).collect();
+    drop(tx);
+
+    let consumers: Vec<_> = (0..RECEIVERS).map(|_| {
+      let rx = rx.clone();
+      thread::spawn(move || rx.iter().collect::<Vec<_>>())
+    }).collect();
+    drop(rx);
+
+    for producer in producers {
+      producer.join().unwrap();
+    }
+
+    let mut all = Vec::new();
+    for consumer in consumers {
+      let received = consumer.join().unwrap();
+      check_per_sender_order(&received);
+      all.extend(received);
+    }
+
+    all.sort();
+    let expected: Vec<_> = (0..SENDERS)
+      .flat_map(|sender| (0..PER_SENDER).map(move |i| (sender, i)))
+      .collect();
+    assert_eq!(all, expected);
+  }
+
+  #[test]
+  fn mpmc_unbounded() {
+    mpmc(None);
+  }
+
+  #[test]
+  fn mpmc_bounded() {
+    mpmc(Some(16));
+  }
+}
@@ -0,0 +1,392 @@
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{AtomicIsize, AtomicPtr, Ordering};
+use std::sync::Arc;
+
+use crate::hazard::{self, HazardPointer};
+
+// Chase–Lev work-stealing deque (as revised by Lê et al., 2013).
+//
+// The owning `Worker` pushes and pops at the bottom like a stack, while any
+// number of `Stealer`s take from the top. Elements live in a circular buffer
+// indexed by ever-growing `top`/`bottom` counters. When it fills up, the
+// worker copies everything into a buffer twice the size and retires the old
+// one through `hazard`, since a thief may still be reading from it.
+//
+// Thieves read their element before claiming it with a CAS on `top`, so a
+// losing thief just forgets the bits it copied.
+
+const MIN_CAPACITY: usize = 16;
+
+struct Buffer<T> {
+  slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+}
+
+impl<T> Buffer<T> {
+  fn alloc(capacity: usize) -> *mut Buffer<T> {
+    let slots = (0..capacity).map(|_| UnsafeCell::new(MaybeUninit::uninit())).collect();
+    Box::into_raw(Box::new(Buffer { slots }))
+  }
+
+  fn capacity(&self) -> usize {
+    self.slots.len()
+  }
+
+  fn slot(&self, index: isize) -> *mut MaybeUninit<T> {
+    // The capacity is always a power of two.
+    self.slots[index as usize & (self.capacity() - 1)].get()
+  }
+
+  unsafe fn write(&self, index: isize, elem: T) {
+    ptr::write_volatile(self.slot(index), MaybeUninit::new(elem));
+  }
+
+  // The result is only initialized if nobody else has taken the element.
+  unsafe fn read(&self, index: isize) -> MaybeUninit<T> {
+    ptr::read_volatile(self.slot(index))
+  }
+}
+
+struct Inner<T> {
+  top: AtomicIsize,
+  bottom: AtomicIsize,
+  buffer: AtomicPtr<Buffer<T>>,
+  _elems: PhantomData<*mut T>,
+}
+
+unsafe impl<T: Send> Send for Inner<T> {}
+unsafe impl<T: Send> Sync for Inner<T> {}
+
+impl<T> Drop for Inner<T> {
+  fn drop(&mut self) {
+    let top = *self.top.get_mut();
+    let bottom = *self.bottom.get_mut();
+
+    unsafe {
+      let buffer = Box::from_raw(*self.buffer.get_mut());
+      for i in top..bottom {
+        (*buffer.slot(i)).assume_init_drop();
+      }
+    }
+  }
+}
+
+pub struct Worker<T> {
+  inner: Arc<Inner<T>>,
+}
+
+pub struct Stealer<T> {
+  inner: Arc<Inner<T>>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Steal<T> {
+  Empty,
+  Success(T),
+  // Lost a race with another thread; the deque may still have elements.
+  Retry,
+}
+
+impl<T> Steal<T> {
+  pub fn success(self) -> Option<T> {
+    match self {
+      Steal::Success(elem) => Some(elem),
+      _ => None,
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    matches!(self, Steal::Empty)
+  }
+
+  pub fn is_retry(&self) -> bool {
+    matches!(self, Steal::Retry)
+  }
+}
+
+impl<T> Worker<T> {
+  pub fn new() -> Self {
+    Worker {
+      inner: Arc::new(Inner {
+        top: AtomicIsize::new(0),
+        bottom: AtomicIsize::new(0),
+        buffer: AtomicPtr::new(Buffer::alloc(MIN_CAPACITY)),
+        _elems: PhantomData,
+      }),
+    }
+  }
+
+  pub fn stealer(&self) -> Stealer<T> {
+    Stealer { inner: self.inner.clone() }
+  }
+
+  // Only the worker ever swaps the buffer, so it can use it unprotected.
+  fn buffer(&self) -> &Buffer<T> {
+    unsafe { &*self.inner.buffer.load(Ordering::Relaxed) }
+  }
+
+  pub fn len(&self) -> usize {
+    let bottom = self.inner.bottom.load(Ordering::SeqCst);
+    let top = self.inner.top.load(Ordering::SeqCst);
+    (bottom - top).max(0) as usize
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn push(&mut self, elem: T) {
+    let bottom = self.inner.bottom.load(Ordering::Relaxed);
+    let top = self.inner.top.load(Ordering::Acquire);
+
+    if bottom - top >= self.buffer().capacity() as isize {
+      self.grow(top, bottom);
+    }
+
+    unsafe {
+      self.buffer().write(bottom, elem);
+    }
+    self.inner.bottom.store(bottom + 1, Ordering::SeqCst);
+  }
+
+  fn grow(&mut self, top: isize, bottom: isize) {
+    let old = self.inner.buffer.load(Ordering::Relaxed);
+    let new = Buffer::alloc(unsafe { (*old).capacity() } * 2);
+
+    unsafe {
+      for i in top..bottom {
+        ptr::copy_nonoverlapping((*old).slot(i), (*new).slot(i), 1);
+      }
+    }
+
+    self.inner.buffer.store(new, Ordering::SeqCst);
+    // Thieves may still be reading from the old one.
+    unsafe {
+      hazard::retire(old);
+    }
+  }
+
+  pub fn pop(&mut self) -> Option<T> {
+    let bottom = self.inner.bottom.load(Ordering::Relaxed) - 1;
+    self.inner.bottom.store(bottom, Ordering::SeqCst);
+    let top = self.inner.top.load(Ordering::SeqCst);
+
+    if top > bottom {
+      // Empty: undo the reservation.
+      self.inner.bottom.store(bottom + 1, Ordering::SeqCst);
+      return None;
+    }
+
+    let elem = unsafe { self.buffer().read(bottom) };
+
+    if top == bottom {
+      // Last element: race the thieves for it.
+      let won = self.inner.top
+        .compare_exchange(top, top + 1, Ordering::SeqCst, Ordering::Relaxed)
+        .is_ok();
+      self.inner.bottom.store(bottom + 1, Ordering::SeqCst);
+
+      if !won {
+        return None;
+      }
+    }
+
+    Some(unsafe { elem.assume_init() })
+  }
+}
+
+impl<T> Default for Worker<T> {
+  fn default() -> Self {
+    Worker::new()
+  }
+}
+
+impl<T> Stealer<T> {
+  pub fn steal(&self) -> Steal<T> {
+    let top = self.inner.top.load(Ordering::SeqCst);
+    let bottom = self.inner.bottom.load(Ordering::SeqCst);
+
+    if top >= bottom {
+      return Steal::Empty;
+    }
+
+    let hazard = HazardPointer::new();
+    let buffer = hazard.protect(&self.inner.buffer);
+    let elem = unsafe { (*buffer).read(top) };
+
+    if self.inner.top.compare_exchange(top, top + 1, Ordering::SeqCst, Ordering::Relaxed).is_ok() {
+      Steal::Success(unsafe { elem.assume_init() })
+    } else {
+      Steal::Retry
+    }
+  }
+
+  // Only a snapshot: other threads may change it right away.
+  pub fn is_empty(&self) -> bool {
+    let top = self.inner.top.load(Ordering::SeqCst);
+    let bottom = self.inner.bottom.load(Ordering::SeqCst);
+    top >= bottom
+  }
+}
+
+impl<T> Clone for Stealer<T> {
+  fn clone(&self) -> Self {
+    Stealer { inner: self.inner.clone() }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Steal, Worker};
+  use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+  use std::sync::Arc;
+  use std::thread;
+
+  #[test]
+  fn basics() {
+    let mut worker = Worker::new();
+    let stealer = worker.stealer();
+
+    assert_eq!(worker.pop(), None);
+    assert_eq!(stealer.steal(), Steal::Empty);
+
+    worker.push(1);
+    worker.push(2);
+    worker.push(3);
+    worker.push(4);
+    assert_eq!(worker.len(), 4);
+
+    // The worker works like a stack, thieves take the oldest elements.
+    assert_eq!(worker.pop(), Some(4));
+    assert_eq!(stealer.steal(), Steal::Success(1));
+    assert_eq!(stealer.clone().steal().success(), Some(2));
+    assert_eq!(worker.pop(), Some(3));
+
+    assert_eq!(worker.pop(), None);
+    assert!(stealer.steal().is_empty());
+    assert!(worker.is_empty());
+  }
+
+  #[test]
+  fn grows() {
+    let mut worker = Worker::new();
+    let stealer = worker.stealer();
+
+    for i in 0..1_000 {
+      worker.push(i);
+    }
+    for i in 0..100 {
+      assert_eq!(stealer.steal(), Steal::Success(i));
+    }
+    // Wraps around the bigger buffer.
+    for i in 1_000..1_050 {
+      worker.push(i);
+    }
+
+    assert_eq!(worker.len(), 950);
+    for i in (100..1_050).rev() {
+      assert_eq!(worker.pop(), Some(i));
+    }
+    assert_eq!(worker.pop(), None);
+  }
+
+  #[test]
+  fn drops_remaining_elements() {
+    let elem = Arc::new(());
+
+    let mut worker = Worker::new();
+    let stealer = worker.stealer();
+    for _ in 0..100 {
+      worker.push(elem.clone());
+    }
+    worker.pop();
+    stealer.steal();
+
+    drop(worker);
+    assert_eq!(Arc::strong_count(&elem), 99);
+    drop(stealer);
+    assert_eq!(Arc::strong_count(&elem), 1);
+  }
+
+  #[test]
+  fn every_task_runs_once() {
+    const TASKS: usize = 50_000;
+    const THIEVES: usize = 4;
+
+    let runs: Vec<AtomicUsize> = (0..TASKS).map(|_| AtomicUsize::new(0)).collect();
+    let done = AtomicBool::new(false);
+    let mut worker = Worker::<usize>::new();
+
+    thread::scope(|s| {
+      for _ in 0..THIEVES {
+        let stealer = worker.stealer();
+        let (runs, done) = (&runs, &done);
+        s.spawn(move || {
+          loop {
+            match stealer.steal() {
+              Steal::Success(task) => {
+                runs[task].fetch_add(1, Ordering::SeqCst);
+              }
+              Steal::Retry => {}
+              Steal::Empty if done.load(Ordering::SeqCst) => break,
+              Steal::Empty => thread::yield_now(),
+            }
+          }
+        });
+      }
+
+      // Push in bursts so the worker and the thieves keep fighting over the
+      // last few elements.
+      for chunk in (0..TASKS).collect::<Vec<_>>().chunks(100) {
+        for &task in chunk {
+          worker.push(task);
+        }
+        for _ in 0..30 {
+          if let Some(task) = worker.pop() {
+            runs[task].fetch_add(1, Ordering::SeqCst);
+          }
+        }
+      }
+      while let Some(task) = worker.pop() {
+        runs[task].fetch_add(1, Ordering::SeqCst);
+      }
+
+      done.store(true, Ordering::SeqCst);
+    });
+
+    for (task, count) in runs.iter().enumerate() {
+      assert_eq!(count.load(Ordering::SeqCst), 1, "task {} ran {} times", task, count.load(Ordering::SeqCst));
+    }
+  }
+
+  #[test]
+  fn stealing_while_growing() {
+    // Thieves hold on to the old buffers while the worker keeps growing.
+    let elem = Arc::new(());
+    let stolen = AtomicUsize::new(0);
+    let mut worker = Worker::new();
+
+    thread::scope(|s| {
+      for _ in 0..2 {
+        let stealer = worker.stealer();
+        let stolen = &stolen;
+        s.spawn(move || {
+          for _ in 0..2_000 {
+            if let Steal::Success(_) = stealer.steal() {
+              stolen.fetch_add(1, Ordering::SeqCst);
+            }
+          }
+        });
+      }
+
+      for _ in 0..20_000 {
+        worker.push(elem.clone());
+      }
+    });
+
+    assert_eq!(worker.len() + stolen.load(Ordering::SeqCst), 20_000);
+    assert_eq!(Arc::strong_count(&elem), worker.len() + 1);
+  }
+}
@@ -0,0 +1,501 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use crate::drop_guard;
+use crate::traits::{Deque, Queue, Stack};
+
+// Thread-safe take on `ch5_mutable_deque_without_refs::List`: `Rc<RefCell<_>>`
+// becomes `Arc<Mutex<_>>` and the list itself gets one lock per end, so
+// operations on the front and on the back of a long list don't contend.
+//
+// Lock ordering:
+//  - `head` is always locked before `tail`;
+//  - front operations hold `head`, back operations hold `tail`, and only
+//    then lock the nodes they touch;
+//  - nodes are locked front to back. The one place that needs to go back to
+//    front (`pop_back` reaching for the new tail) only `try_lock`s and falls
+//    back to locking both ends on failure;
+//  - going from empty to one element and back changes both ends, so it's
+//    done holding `head` and `tail`. Nobody can touch a node without holding
+//    an end, so that's also exclusive access to the whole list.
+pub struct List<T> {
+  head: Mutex<Link<T>>,
+  tail: Mutex<Link<T>>,
+  len: AtomicUsize,
+}
+
+type Link<T> = Option<Arc<Mutex<Node<T>>>>;
+
+struct Node<T> {
+  // Another thread may hold a clone of a node's `Arc` for a moment after it
+  // was unlinked, so the element is taken out instead of unwrapping the node.
+  elem: Option<T>,
+  next: Link<T>,
+  prev: Link<T>,
+}
+
+impl<T> Node<T> {
+  fn new(elem: T, next: Link<T>, prev: Link<T>) -> Arc<Mutex<Self>> {
+    Arc::new(Mutex::new(Node {
+      elem: Some(elem),
+      next,
+      prev,
+    }))
+  }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+  mutex.lock().unwrap()
+}
+
+impl<T> List<T> {
+  pub fn new() -> Self {
+    List {
+      head: Mutex::new(None),
+      tail: Mutex::new(None),
+      len: AtomicUsize::new(0),
+    }
+  }
+
+  // Only a snapshot when other threads are pushing or popping.
+  pub fn len(&self) -> usize {
+    self.len.load(Ordering::SeqCst)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    lock(&self.head).is_none()
+  }
+
+  pub fn push_front(&self, elem: T) {
+    let mut head = lock(&self.head);
+
+    match head.take() {
+      Some(old_head) => {
+        let new_head = Node::new(elem, Some(old_head.clone()), None);
+        lock(&old_head).prev = Some(new_head.clone());
+        *head = Some(new_head);
+      }
+      None => {
+        let new_head = Node::new(elem, None, None);
+        *lock(&self.tail) = Some(new_head.clone());
+        *head = Some(new_head);
+      }
+    }
+
+    self.len.fetch_add(1, Ordering::SeqCst);
+  }
+
+  pub fn push_back(&self, elem: T) {
+    {
+      let mut tail = lock(&self.tail);
+
+      if let Some(old_tail) = tail.take() {
+        let new_tail = Node::new(elem, None, Some(old_tail.clone()));
+        lock(&old_tail).next = Some(new_tail.clone());
+        *tail = Some(new_tail);
+
+        self.len.fetch_add(1, Ordering::SeqCst);
+        return;
+      }
+    }
+
+    // Empty list: `head` must be set too, and it can't be locked while
+    // holding `tail`.
+    let (mut head, mut tail) = self.lock_ends();
+    let new_tail = Node::new(elem, None, tail.clone());
+
+    match tail.take() {
+      Some(old_tail) => {
+        lock(&old_tail).next = Some(new_tail.clone());
+      }
+      None => {
+        *head = Some(new_tail.clone());
+      }
+    }
+
+    *tail = Some(new_tail);
+    self.len.fetch_add(1, Ordering::SeqCst);
+  }
+
+  pub fn pop_front(&self) -> Option<T> {
+    let mut head = lock(&self.head);
+
+    let old_head = head.clone()?;
+    {
+      let mut old = lock(&old_head);
+
+      if let Some(new_head) = old.next.take() {
+        lock(&new_head).prev = None;
+        *head = Some(new_head);
+
+        self.len.fetch_sub(1, Ordering::SeqCst);
+        return old.elem.take();
+      }
+    }
+
+    // Last element: `tail` points at it as well. We already hold `head`, so
+    // locking `tail` now keeps the order.
+    let mut tail = lock(&self.tail);
+    let elem = Self::pop_front_exclusive(&mut head, &mut tail);
+    if elem.is_some() {
+      self.len.fetch_sub(1, Ordering::SeqCst);
+    }
+    elem
+  }
+
+  pub fn pop_back(&self) -> Option<T> {
+    {
+      let mut tail = lock(&self.tail);
+
+      let old_tail = tail.clone()?;
+      let mut old = lock(&old_tail);
+
+      if let Some(new_tail) = old.prev.clone() {
+        // Back to front: a `pop_front` holding `new_tail` may be waiting on
+        // `old_tail` (two-element list), so don't block on it.
+        let unlinked = match new_tail.try_lock() {
+          Ok(mut new) => {
+            new.next = None;
+            true
+          }
+          Err(_) => false,
+        };
+
+        if unlinked {
+          old.prev = None;
+          *tail = Some(new_tail);
+
+          self.len.fetch_sub(1, Ordering::SeqCst);
+          return old.elem.take();
+        }
+      }
+    }
+
+    let (mut head, mut tail) = self.lock_ends();
+    let elem = Self::pop_back_exclusive(&mut head, &mut tail);
+    if elem.is_some() {
+      self.len.fetch_sub(1, Ordering::SeqCst);
+    }
+    elem
+  }
+
+  fn lock_ends(&self) -> (MutexGuard<'_, Link<T>>, MutexGuard<'_, Link<T>>) {
+    let head = lock(&self.head);
+    let tail = lock(&self.tail);
+    (head, tail)
+  }
+
+  fn pop_front_exclusive(head: &mut Link<T>, tail: &mut Link<T>) -> Option<T> {
+    head.take().and_then(|old_head| {
+      let mut old = lock(&old_head);
+      match old.next.take() {
+        Some(new_head) => {
+          lock(&new_head).prev = None;
+          *head = Some(new_head);
+        }
+        None => {
+          tail.take();
+        }
+      }
+      old.elem.take()
+    })
+  }
+
+  fn pop_back_exclusive(head: &mut Link<T>, tail: &mut Link<T>) -> Option<T> {
+    tail.take().and_then(|old_tail| {
+      let mut old = lock(&old_tail);
+      match old.prev.take() {
+        Some(new_tail) => {
+          lock(&new_tail).next = None;
+          *tail = Some(new_tail);
+        }
+        None => {
+          head.take();
+        }
+      }
+      old.elem.take()
+    })
+  }
+}
+
+impl<T> Default for List<T> {
+  fn default() -> Self {
+    List::new()
+  }
+}
+
+impl<T> Drop for List<T> {
+  fn drop(&mut self) {
+    // Nodes point at each other through `Arc`s in both directions, so they
+    // have to be unlinked one by one.
+    drop_guard::drain(self, |list| list.pop_front().is_some());
+  }
+}
+
+impl<T> IntoIterator for List<T> {
+  type Item = T;
+  type IntoIter = IntoIter<T>;
+
+  fn into_iter(self) -> IntoIter<T> {
+    IntoIter(self)
+  }
+}
+
+pub struct IntoIter<T>(List<T>);
+
+impl<T> Iterator for IntoIter<T> {
+  type Item = T;
+
+  fn next(&mut self) -> Option<T> {
+    self.0.pop_front()
+  }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+  fn next_back(&mut self) -> Option<T> {
+    self.0.pop_back()
+  }
+}
+
+impl<T> Stack<T> for List<T> {
+  fn push(&mut self, elem: T) {
+    self.push_front(elem);
+  }
+
+  fn pop(&mut self) -> Option<T> {
+    self.pop_front()
+  }
+}
+
+impl<T> Queue<T> for List<T> {
+  fn enqueue(&mut self, elem: T) {
+    self.push_back(elem);
+  }
+
+  fn dequeue(&mut self) -> Option<T> {
+    self.pop_front()
+  }
+}
+
+impl<T> Deque<T> for List<T> {
+  fn push_front(&mut self, elem: T) {
+    List::push_front(self, elem);
+  }
+
+  fn push_back(&mut self, elem: T) {
+    List::push_back(self, elem);
+  }
+
+  fn pop_front(&mut self) -> Option<T> {
+    List::pop_front(self)
+  }
+
+  fn pop_back(&mut self) -> Option<T> {
+    List::pop_back(self)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::List;
+  use crate::test_support::{assert_panic_safe, measure, DropCounter, Profile};
+  use crate::traits::conformance;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::thread;
+
+  #[test]
+  fn basics() {
+    let list = List::new();
+
+    assert!(list.is_empty());
+    assert_eq!(list.pop_front(), None);
+    assert_eq!(list.pop_back(), None);
+
+    list.push_front(1);
+    list.push_front(2);
+    list.push_back(3);
+    list.push_back(4);
+    assert_eq!(list.len(), 4);
+
+    assert_eq!(list.pop_front(), Some(2));
+    assert_eq!(list.pop_back(), Some(4));
+    assert_eq!(list.pop_back(), Some(3));
+    assert_eq!(list.pop_back(), Some(1));
+    assert_eq!(list.pop_back(), None);
+    assert_eq!(list.pop_front(), None);
+    assert!(list.is_empty());
+
+    list.push_back(5);
+    assert_eq!(list.pop_front(), Some(5));
+    list.push_front(6);
+    assert_eq!(list.pop_back(), Some(6));
+    assert_eq!(list.len(), 0);
+  }
+
+  #[test]
+  fn into_iter() {
+    let list = List::new();
+    list.push_back(1); list.push_back(2); list.push_back(3);
+
+    let mut iter = list.into_iter();
+
+    assert_eq!(iter.next(), Some(1));
+    assert_eq!(iter.next_back(), Some(3));
+    assert_eq!(iter.next(), Some(2));
+    assert_eq!(iter.next_back(), None);
+    assert_eq!(iter.next(), None);
+  }
+
+  const THREADS: usize = 4;
+  const PER_THREAD: usize = 5_000;
+
+  #[test]
+  fn concurrent_pushes() {
+    let list = List::new();
+
+    thread::scope(|s| {
+      for t in 0..THREADS {
+        let list = &list;
+        s.spawn(move || {
+          for i in 0..PER_THREAD {
+            if t % 2 == 0 {
+              list.push_front(t * PER_THREAD + i);
+            } else {
+              list.push_back(t * PER_THREAD + i);
+            }
+          }
+        });
+      }
+    });
+
+    assert_eq!(list.len(), THREADS * PER_THREAD);
+
+    let mut elems: Vec<_> = list.into_iter().collect();
+    elems.sort();
+    assert_eq!(elems, (0..THREADS * PER_THREAD).collect::<Vec<_>>());
+  }
+
+  #[test]
+  fn concurrent_pushes_and_pops() {
+    let list = List::new();
+    let popped = AtomicUsize::new(0);
+    let total = THREADS * PER_THREAD;
+
+    let mut elems: Vec<_> = thread::scope(|s| {
+      for t in 0..THREADS {
+        let list = &list;
+        s.spawn(move || {
+          for i in 0..PER_THREAD {
+            if i % 2 == 0 {
+              list.push_front(t * PER_THREAD + i);
+            } else {
+              list.push_back(t * PER_THREAD + i);
+            }
+          }
+        });
+      }
+
+      let consumers: Vec<_> = (0..THREADS).map(|t| {
+        let (list, popped) = (&list, &popped);
+        s.spawn(move || {
+          let mut mine = Vec::new();
+          while popped.load(Ordering::SeqCst) < total {
+            let elem = if t % 2 == 0 { list.pop_front() } else { list.pop_back() };
+            if let Some(elem) = elem {
+              popped.fetch_add(1, Ordering::SeqCst);
+              mine.push(elem);
+            }
+          }
+          mine
+        })
+      }).collect();
+
+      consumers.into_iter().flat_map(|c| c.join().unwrap()).collect()
+    });
+
+    assert!(list.is_empty());
+    elems.sort();
+    assert_eq!(elems, (0..total).collect::<Vec<_>>());
+  }
+
+  #[test]
+  fn pops_on_one_and_two_elements_do_not_deadlock() {
+    let list = List::new();
+    let rounds = 20_000;
+
+    let mut elems: Vec<_> = thread::scope(|s| {
+      let workers: Vec<_> = (0..THREADS).map(|t| {
+        let list = &list;
+        s.spawn(move || {
+          let mut mine = Vec::new();
+          for i in 0..rounds {
+            list.push_back(t * rounds + i);
+            let elem = if (t + i) % 2 == 0 { list.pop_front() } else { list.pop_back() };
+            mine.extend(elem);
+          }
+          mine
+        })
+      }).collect();
+
+      workers.into_iter().flat_map(|w| w.join().unwrap()).collect()
+    });
+
+    elems.extend(list);
+    elems.sort();
+    assert_eq!(elems, (0..THREADS * rounds).collect::<Vec<_>>());
+  }
+
+  #[test]
+  fn conformance() {
+    conformance::deque(List::new);
+    conformance::queue(List::new);
+    conformance::stack(List::new);
+    conformance::queue_allocations(List::new);
+    conformance::stack_allocations(List::new);
+    conformance::stack_drops(List::new);
+  }
+
+  #[test]
+  fn allocations() {
+    let list = List::new();
+    list.push_front(2);
+    list.push_back(3);
+    list.push_front(1);
+    list.push_back(4);
+
+    // Locks don't allocate.
+    let ((), profile) = measure(|| {
+      assert_eq!(list.len(), 4);
+      assert!(!list.is_empty());
+    });
+    assert_eq!(profile, Profile::default());
+
+    assert_eq!(list.pop_front(), Some(1));
+    let (elem, profile) = measure(|| list.pop_back());
+    assert_eq!(elem, Some(4));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 1));
+
+    let (mut iter, profile) = measure(|| list.into_iter());
+    assert_eq!(profile, Profile::default());
+    let (elem, profile) = measure(|| iter.next());
+    assert_eq!(elem, Some(2));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 1));
+    let ((), profile) = measure(|| drop(iter));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 1));
+  }
+
+  #[test]
+  fn panic_safe_drops() {
+    let fill = |counter: &DropCounter| {
+      let list = List::new();
+      for _ in 0..5 {
+        list.push_back(counter.elem());
+      }
+      list
+    };
+
+    assert_panic_safe(5, fill, |list| {
+      let mut iter = list.into_iter();
+      iter.next_back();
+    });
+  }
+}
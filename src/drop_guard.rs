@@ -0,0 +1,24 @@
+// Tearing a list down one node at a time, in a way that survives an element's
+// destructor panicking: the rest of the nodes still get dropped while the
+// panic unwinds, instead of leaking or being dropped recursively by the
+// compiler's drop glue. A second panic while unwinding aborts, like anywhere
+// else.
+
+struct Guard<'a, S, F: FnMut(&mut S) -> bool> {
+  state: &'a mut S,
+  step: F,
+}
+
+impl<'a, S, F: FnMut(&mut S) -> bool> Drop for Guard<'a, S, F> {
+  fn drop(&mut self) {
+    while (self.step)(self.state) {}
+  }
+}
+
+// Calls `step` until it returns false. `step` must leave `state` consistent
+// before it drops anything that might panic, so it can be called again.
+pub(crate) fn drain<S>(state: &mut S, step: impl FnMut(&mut S) -> bool) {
+  let mut guard = Guard { state, step };
+  while (guard.step)(guard.state) {}
+  // Dropping the guard calls `step` once more, which finds nothing left.
+}
@@ -0,0 +1,302 @@
+use std::cell::RefCell;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::sync::Mutex;
+
+// Hazard pointers (Michael, 2004) for the lock-free structures in this crate.
+//
+// A thread about to dereference a shared node first publishes its address in
+// a hazard slot and then checks the node is still reachable. Nodes that have
+// been unlinked are `retire`d instead of freed, and only freed once no slot
+// holds their address.
+//
+// Slots live in a global, append-only list of records that is never freed;
+// each thread keeps the records it's done with around for reuse. Retired
+// nodes are kept per thread and scanned in batches. Whatever a thread leaves
+// behind when it exits is adopted by the next scan on any thread.
+
+struct Record {
+  hazard: AtomicPtr<u8>,
+  active: AtomicBool,
+  next: *mut Record,
+}
+
+static RECORDS: AtomicPtr<Record> = AtomicPtr::new(ptr::null_mut());
+
+struct Retired {
+  ptr: *mut u8,
+  drop: unsafe fn(*mut u8),
+}
+
+// Retired nodes are only touched again to be freed.
+unsafe impl Send for Retired {}
+
+static ORPHANS: Mutex<Vec<Retired>> = Mutex::new(Vec::new());
+
+const SCAN_THRESHOLD: usize = 64;
+
+struct Local {
+  records: RefCell<Vec<&'static Record>>,
+  retired: RefCell<Vec<Retired>>,
+}
+
+impl Drop for Local {
+  fn drop(&mut self) {
+    for record in self.records.get_mut().drain(..) {
+      record.active.store(false, Ordering::Release);
+    }
+
+    let mut retired = mem::take(self.retired.get_mut());
+    scan(&mut retired);
+    if !retired.is_empty() {
+      ORPHANS.lock().unwrap().append(&mut retired);
+    }
+  }
+}
+
+thread_local! {
+  static LOCAL: Local = const {
+    Local { records: RefCell::new(Vec::new()), retired: RefCell::new(Vec::new()) }
+  };
+}
+
+fn acquire_record() -> &'static Record {
+  if let Ok(Some(record)) = LOCAL.try_with(|local| local.records.borrow_mut().pop()) {
+    return record;
+  }
+
+  let mut cur = RECORDS.load(Ordering::Acquire);
+  while !cur.is_null() {
+    let record = unsafe { &*cur };
+    if !record.active.load(Ordering::Relaxed)
+      && record.active.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok() {
+      return record;
+    }
+    cur = record.next;
+  }
+
+  let record = Box::into_raw(Box::new(Record {
+    hazard: AtomicPtr::new(ptr::null_mut()),
+    active: AtomicBool::new(true),
+    next: ptr::null_mut(),
+  }));
+
+  let mut head = RECORDS.load(Ordering::Acquire);
+  loop {
+    unsafe {
+      (*record).next = head;
+    }
+    match RECORDS.compare_exchange(head, record, Ordering::AcqRel, Ordering::Acquire) {
+      Ok(_) => return unsafe { &*record },
+      Err(new_head) => head = new_head,
+    }
+  }
+}
+
+fn release_record(record: &'static Record) {
+  let kept = LOCAL.try_with(|local| local.records.borrow_mut().push(record));
+  if kept.is_err() {
+    record.active.store(false, Ordering::Release);
+  }
+}
+
+fn hazards() -> Vec<*mut u8> {
+  let mut hazards = Vec::new();
+
+  let mut cur = RECORDS.load(Ordering::Acquire);
+  while !cur.is_null() {
+    let record = unsafe { &*cur };
+    let hazard = record.hazard.load(Ordering::SeqCst);
+    if !hazard.is_null() {
+      hazards.push(hazard);
+    }
+    cur = record.next;
+  }
+
+  hazards.sort();
+  hazards
+}
+
+// Frees every node in `retired` that no hazard pointer protects.
+fn scan(retired: &mut Vec<Retired>) {
+  if let Ok(mut orphans) = ORPHANS.try_lock() {
+    retired.append(&mut orphans);
+  }
+
+  let hazards = hazards();
+  let mut freed = Vec::new();
+
+  retired.retain(|node| {
+    let protected = hazards.binary_search(&node.ptr).is_ok();
+    if !protected {
+      freed.push((node.ptr, node.drop));
+    }
+    protected
+  });
+
+  // Dropping a node may retire more of them, so this happens last.
+  for (ptr, drop) in freed {
+    unsafe { drop(ptr) };
+  }
+}
+
+// A hazard slot owned by the current thread.
+pub struct HazardPointer {
+  record: &'static Record,
+}
+
+impl HazardPointer {
+  pub fn new() -> Self {
+    HazardPointer { record: acquire_record() }
+  }
+
+  // Loads `src` and protects what it points to. The pointer stays safe to
+  // dereference until the slot is reset or reused, as long as whoever
+  // unlinks it from `src` `retire`s it rather than freeing it.
+  pub fn protect<T>(&self, src: &AtomicPtr<T>) -> *mut T {
+    let mut ptr = src.load(Ordering::SeqCst);
+    loop {
+      self.set(ptr);
+      let again = src.load(Ordering::SeqCst);
+      if again == ptr {
+        return ptr;
+      }
+      ptr = again;
+    }
+  }
+
+  // Publishes `ptr` without validating it. The caller has to check it's
+  // still reachable afterwards before dereferencing it.
+  pub fn set<T>(&self, ptr: *mut T) {
+    self.record.hazard.store(ptr as *mut u8, Ordering::SeqCst);
+  }
+
+  pub fn reset(&self) {
+    self.record.hazard.store(ptr::null_mut(), Ordering::Release);
+  }
+}
+
+impl Default for HazardPointer {
+  fn default() -> Self {
+    HazardPointer::new()
+  }
+}
+
+impl Drop for HazardPointer {
+  fn drop(&mut self) {
+    self.reset();
+    release_record(self.record);
+  }
+}
+
+// Frees `ptr` once no hazard pointer protects it.
+//
+// Safety: `ptr` must come from `Box::into_raw`, must already be unreachable
+// for threads that haven't protected it yet, and must be retired only once.
+#[allow(clippy::missing_safety_doc)]
+pub unsafe fn retire<T>(ptr: *mut T) {
+  unsafe fn drop_box<T>(ptr: *mut u8) {
+    drop(Box::from_raw(ptr as *mut T));
+  }
+
+  let mut node = Some(Retired { ptr: ptr as *mut u8, drop: drop_box::<T> });
+
+  let batch = LOCAL.try_with(|local| {
+    let mut retired = local.retired.borrow_mut();
+    retired.push(node.take().unwrap());
+    if retired.len() >= SCAN_THRESHOLD {
+      Some(mem::take(&mut *retired))
+    } else {
+      None
+    }
+  });
+
+  match batch {
+    Ok(Some(mut batch)) => {
+      scan(&mut batch);
+      LOCAL.with(|local| local.retired.borrow_mut().append(&mut batch));
+    }
+    Ok(None) => {}
+    Err(_) => {
+      ORPHANS.lock().unwrap().extend(node);
+    }
+  }
+}
+
+// Frees whatever this thread (or an exited one) retired that is no longer
+// protected, without waiting for the next batch.
+pub fn reclaim() {
+  let mut batch = LOCAL.with(|local| mem::take(&mut *local.retired.borrow_mut()));
+  scan(&mut batch);
+  LOCAL.with(|local| local.retired.borrow_mut().append(&mut batch));
+}
+
+#[cfg(test)]
+mod test {
+  use super::{reclaim, retire, HazardPointer};
+  use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+  use std::sync::Arc;
+  use std::thread;
+  use std::time::{Duration, Instant};
+
+  struct Counted(Arc<AtomicUsize>);
+
+  impl Drop for Counted {
+    fn drop(&mut self) {
+      self.0.fetch_add(1, Ordering::SeqCst);
+    }
+  }
+
+  #[test]
+  fn protected_nodes_outlive_retirement() {
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let first = Box::into_raw(Box::new(Counted(dropped.clone())));
+    let second = Box::into_raw(Box::new(Counted(dropped.clone())));
+    let shared = AtomicPtr::new(first);
+
+    let hazard = HazardPointer::new();
+    assert_eq!(hazard.protect(&shared), first);
+
+    shared.store(second, Ordering::SeqCst);
+    unsafe {
+      retire(first);
+      retire(second);
+    }
+
+    reclaim();
+    assert_eq!(dropped.load(Ordering::SeqCst), 1);
+
+    hazard.reset();
+    reclaim();
+    assert_eq!(dropped.load(Ordering::SeqCst), 2);
+  }
+
+  #[test]
+  fn exited_threads_hand_over_their_nodes() {
+    let dropped = Arc::new(AtomicUsize::new(0));
+    let node = Box::into_raw(Box::new(Counted(dropped.clone())));
+    let shared = Arc::new(AtomicPtr::new(node));
+
+    let hazard = HazardPointer::new();
+    hazard.protect(&shared);
+
+    let retirer = shared.clone();
+    thread::spawn(move || unsafe {
+      retire(retirer.swap(std::ptr::null_mut(), Ordering::SeqCst));
+    }).join().unwrap();
+
+    reclaim();
+    assert_eq!(dropped.load(Ordering::SeqCst), 0);
+
+    // Another test's thread may have adopted it in the meantime, in which
+    // case it's freed by that thread's next scan or when it exits.
+    drop(hazard);
+    let start = Instant::now();
+    while dropped.load(Ordering::SeqCst) == 0 && start.elapsed() < Duration::from_secs(10) {
+      reclaim();
+      thread::yield_now();
+    }
+    assert_eq!(dropped.load(Ordering::SeqCst), 1);
+  }
+}
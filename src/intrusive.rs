@@ -0,0 +1,46 @@
+// What the intrusive collections, `intrusive_mpsc` and `intrusive_list`, need
+// to know about their items: where the link is.
+
+// Finds the link inside an item, and the item around a link.
+//
+// Both ways go through raw pointers to the whole item. A pointer made from a
+// reference to the link field alone only covers that field, so stepping back
+// out from it to the item would be undefined behavior.
+//
+// Safety: `link` must point to a field of `item` and `item` must be its exact
+// inverse. `intrusive_adapter!` writes correct implementations.
+#[allow(clippy::missing_safety_doc)]
+pub unsafe trait Adapter {
+  type Item;
+  // `intrusive_mpsc::Link` or `intrusive_list::Link`.
+  type Link;
+
+  // Safety: `item` has to point to an `Item`.
+  unsafe fn link(item: *const Self::Item) -> *const Self::Link;
+
+  // Safety: `link` has to come from `link`.
+  unsafe fn item(link: *const Self::Link) -> *const Self::Item;
+}
+
+// `intrusive_adapter!(pub TaskAdapter = Task { link: Link });` declares
+// `TaskAdapter`, an `Adapter` for the `link` field of `Task`, which is a
+// `Link`.
+#[macro_export]
+macro_rules! intrusive_adapter {
+  ($vis:vis $name:ident = $item:ty { $field:ident: $link:ty }) => {
+    $vis struct $name;
+
+    unsafe impl $crate::intrusive::Adapter for $name {
+      type Item = $item;
+      type Link = $link;
+
+      unsafe fn link(item: *const $item) -> *const $link {
+        ::core::ptr::addr_of!((*item).$field)
+      }
+
+      unsafe fn item(link: *const $link) -> *const $item {
+        (link as *const u8).sub(::core::mem::offset_of!($item, $field)) as *const $item
+      }
+    }
+  };
+}
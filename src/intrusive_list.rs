@@ -0,0 +1,567 @@
+use alloc::boxed::Box;
+use core::cell::Cell;
+use core::marker::{PhantomData, PhantomPinned};
+use core::pin::Pin;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::intrusive::Adapter;
+
+// Intrusive doubly-linked list.
+//
+// Items carry their own `Link`, the way `unsafe_deque::Node` carries its
+// `prev` pointer, so linking them never allocates and they can live anywhere,
+// the stack included. The links are `Cell`s: the list only ever sees items as
+// `Pin<&'a Item>`, so they can't move or go away while linked, and the caller
+// gets the same reference back.
+//
+// The list is circular around a heap-allocated sentinel link. Every link
+// remembers the id of the list it's on, so removing an item through the wrong
+// list is caught instead of corrupting both. Ids are never reused, so not
+// even the items of a list that was `mem::forget`-ed can be mistaken for
+// another list's; they just stay linked for good.
+
+#[derive(Debug)]
+pub struct Link {
+  prev: Cell<*const Link>,
+  next: Cell<*const Link>,
+  // The id of the list it's on, or 0.
+  list: Cell<usize>,
+  _pinned: PhantomPinned,
+}
+
+impl Link {
+  pub const fn new() -> Self {
+    Link {
+      prev: Cell::new(ptr::null()),
+      next: Cell::new(ptr::null()),
+      list: Cell::new(0),
+      _pinned: PhantomPinned,
+    }
+  }
+
+  pub fn is_linked(&self) -> bool {
+    self.list.get() != 0
+  }
+}
+
+impl Default for Link {
+  fn default() -> Self {
+    Link::new()
+  }
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+pub struct List<'a, A: Adapter<Link = Link>> {
+  sentinel: Box<Link>,
+  id: usize,
+  len: usize,
+  _items: PhantomData<Pin<&'a A::Item>>,
+}
+
+impl<'a, A: Adapter<Link = Link>> List<'a, A> {
+  pub fn new() -> Self {
+    let sentinel = Box::new(Link::new());
+    let raw_sentinel: *const Link = &*sentinel;
+    sentinel.prev.set(raw_sentinel);
+    sentinel.next.set(raw_sentinel);
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    List { sentinel, id, len: 0, _items: PhantomData }
+  }
+
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  // Whether `item` is on this list, as opposed to another one or none.
+  pub fn contains(&self, item: Pin<&A::Item>) -> bool {
+    unsafe { (*A::link(item.get_ref())).list.get() == self.id }
+  }
+
+  // Pushes `item`, or hands it back if it's already on a list (this one or
+  // another).
+  pub fn push_front(&mut self, item: Pin<&'a A::Item>) -> Result<(), Pin<&'a A::Item>> {
+    let sentinel = self.sentinel();
+    self.link_between(item, sentinel, self.sentinel.next.get())
+  }
+
+  pub fn push_back(&mut self, item: Pin<&'a A::Item>) -> Result<(), Pin<&'a A::Item>> {
+    let sentinel = self.sentinel();
+    self.link_between(item, self.sentinel.prev.get(), sentinel)
+  }
+
+  pub fn pop_front(&mut self) -> Option<Pin<&'a A::Item>> {
+    let first = self.sentinel.next.get();
+    unsafe { self.unlink(first) }
+  }
+
+  pub fn pop_back(&mut self) -> Option<Pin<&'a A::Item>> {
+    let last = self.sentinel.prev.get();
+    unsafe { self.unlink(last) }
+  }
+
+  pub fn front(&self) -> Option<Pin<&'a A::Item>> {
+    unsafe { self.item(self.sentinel.next.get()) }
+  }
+
+  pub fn back(&self) -> Option<Pin<&'a A::Item>> {
+    unsafe { self.item(self.sentinel.prev.get()) }
+  }
+
+  // Takes `item` out, wherever it is in the list. Returns false, leaving it
+  // alone, if it isn't on this list.
+  pub fn remove(&mut self, item: Pin<&A::Item>) -> bool {
+    if !self.contains(item) {
+      return false;
+    }
+    unsafe { self.unlink(A::link(item.get_ref())) };
+    true
+  }
+
+  pub fn iter(&self) -> Iter<'_, 'a, A> {
+    Iter {
+      front: self.sentinel.next.get(),
+      back: self.sentinel.prev.get(),
+      len: self.len,
+      _list: PhantomData,
+    }
+  }
+
+  // Cursors start on an item, or on the "ghost" position between the back
+  // and the front when there's none, like `std`'s linked list cursors.
+  pub fn cursor_front_mut(&mut self) -> CursorMut<'_, 'a, A> {
+    let current = self.sentinel.next.get();
+    CursorMut { list: self, current }
+  }
+
+  pub fn cursor_back_mut(&mut self) -> CursorMut<'_, 'a, A> {
+    let current = self.sentinel.prev.get();
+    CursorMut { list: self, current }
+  }
+
+  // A cursor on `item`, if it's on this list.
+  pub fn cursor_mut_at(&mut self, item: Pin<&A::Item>) -> Option<CursorMut<'_, 'a, A>> {
+    if !self.contains(item) {
+      return None;
+    }
+    let current = unsafe { A::link(item.get_ref()) };
+    Some(CursorMut { list: self, current })
+  }
+
+  fn sentinel(&self) -> *const Link {
+    &*self.sentinel
+  }
+
+  // The item around `link`, or `None` for the sentinel.
+  unsafe fn item(&self, link: *const Link) -> Option<Pin<&'a A::Item>> {
+    if ptr::eq(link, self.sentinel()) {
+      None
+    } else {
+      Some(Pin::new_unchecked(&*A::item(link)))
+    }
+  }
+
+  fn link_between(
+    &mut self,
+    item: Pin<&'a A::Item>,
+    prev: *const Link,
+    next: *const Link,
+  ) -> Result<(), Pin<&'a A::Item>> {
+    unsafe {
+      let link = A::link(item.get_ref());
+      if (*link).is_linked() {
+        return Err(item);
+      }
+
+      (*link).list.set(self.id);
+      (*link).prev.set(prev);
+      (*link).next.set(next);
+      (*prev).next.set(link);
+      (*next).prev.set(link);
+    }
+    self.len += 1;
+    Ok(())
+  }
+
+  // Safety: `link` is the sentinel or on this list.
+  unsafe fn unlink(&mut self, link: *const Link) -> Option<Pin<&'a A::Item>> {
+    let item = self.item(link)?;
+    let (prev, next) = ((*link).prev.get(), (*link).next.get());
+    (*prev).next.set(next);
+    (*next).prev.set(prev);
+
+    (*link).prev.set(ptr::null());
+    (*link).next.set(ptr::null());
+    (*link).list.set(0);
+    self.len -= 1;
+    Some(item)
+  }
+}
+
+impl<'a, A: Adapter<Link = Link>> Default for List<'a, A> {
+  fn default() -> Self {
+    List::new()
+  }
+}
+
+impl<'a, A: Adapter<Link = Link>> Drop for List<'a, A> {
+  fn drop(&mut self) {
+    // Unlink whatever is left so it can go on another list.
+    while self.pop_front().is_some() {}
+  }
+}
+
+pub struct Iter<'l, 'a, A: Adapter<Link = Link>> {
+  front: *const Link,
+  back: *const Link,
+  len: usize,
+  _list: PhantomData<&'l List<'a, A>>,
+}
+
+impl<'l, 'a, A: Adapter<Link = Link>> Iterator for Iter<'l, 'a, A> {
+  type Item = Pin<&'a A::Item>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.len == 0 {
+      return None;
+    }
+
+    self.len -= 1;
+    unsafe {
+      let link = self.front;
+      self.front = (*link).next.get();
+      Some(Pin::new_unchecked(&*A::item(link)))
+    }
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.len, Some(self.len))
+  }
+}
+
+impl<'l, 'a, A: Adapter<Link = Link>> DoubleEndedIterator for Iter<'l, 'a, A> {
+  fn next_back(&mut self) -> Option<Self::Item> {
+    if self.len == 0 {
+      return None;
+    }
+
+    self.len -= 1;
+    unsafe {
+      let link = self.back;
+      self.back = (*link).prev.get();
+      Some(Pin::new_unchecked(&*A::item(link)))
+    }
+  }
+}
+
+impl<'l, 'a, A: Adapter<Link = Link>> ExactSizeIterator for Iter<'l, 'a, A> {}
+
+pub struct CursorMut<'l, 'a, A: Adapter<Link = Link>> {
+  list: &'l mut List<'a, A>,
+  // The sentinel when on the ghost position.
+  current: *const Link,
+}
+
+impl<'l, 'a, A: Adapter<Link = Link>> CursorMut<'l, 'a, A> {
+  // `None` on the ghost position.
+  pub fn current(&self) -> Option<Pin<&'a A::Item>> {
+    unsafe { self.list.item(self.current) }
+  }
+
+  // Past the back comes the ghost position, then the front again.
+  pub fn move_next(&mut self) {
+    self.current = unsafe { (*self.current).next.get() };
+  }
+
+  pub fn move_prev(&mut self) {
+    self.current = unsafe { (*self.current).prev.get() };
+  }
+
+  // Takes out the current item and moves on to the next one.
+  pub fn remove_current(&mut self) -> Option<Pin<&'a A::Item>> {
+    let current = self.current;
+    let next = unsafe { (*current).next.get() };
+    let item = unsafe { self.list.unlink(current)? };
+    self.current = next;
+    Some(item)
+  }
+
+  // On the ghost position, these insert at the back and at the front.
+  pub fn insert_before(&mut self, item: Pin<&'a A::Item>) -> Result<(), Pin<&'a A::Item>> {
+    let prev = unsafe { (*self.current).prev.get() };
+    self.list.link_between(item, prev, self.current)
+  }
+
+  pub fn insert_after(&mut self, item: Pin<&'a A::Item>) -> Result<(), Pin<&'a A::Item>> {
+    let next = unsafe { (*self.current).next.get() };
+    self.list.link_between(item, self.current, next)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::List;
+  use crate::intrusive_mpsc::{self, Queue};
+  use crate::rng::Rng;
+  use crate::test_support::{measure, Profile};
+  use std::collections::VecDeque;
+  use std::pin::{pin, Pin};
+
+  #[derive(Debug)]
+  struct Timer {
+    id: usize,
+    link: super::Link,
+    // So timers can also be queued for firing.
+    fire: intrusive_mpsc::Link,
+  }
+
+  impl Timer {
+    fn new(id: usize) -> Self {
+      Timer { id, link: super::Link::new(), fire: intrusive_mpsc::Link::new() }
+    }
+  }
+
+  crate::intrusive_adapter!(TimerAdapter = Timer { link: super::Link });
+  crate::intrusive_adapter!(FireAdapter = Timer { fire: intrusive_mpsc::Link });
+
+  fn ids(list: &List<'_, TimerAdapter>) -> Vec<usize> {
+    list.iter().map(|timer| timer.id).collect()
+  }
+
+  fn id(timer: Option<Pin<&Timer>>) -> Option<usize> {
+    timer.map(|timer| timer.id)
+  }
+
+  #[test]
+  fn basics() {
+    let a = pin!(Timer::new(1));
+    let b = pin!(Timer::new(2));
+    let c = pin!(Timer::new(3));
+    let (a, b, c) = (a.into_ref(), b.into_ref(), c.into_ref());
+
+    let mut list = List::<TimerAdapter>::new();
+    assert!(list.is_empty());
+    assert_eq!(id(list.pop_front()), None);
+    assert_eq!(id(list.pop_back()), None);
+
+    list.push_back(b).unwrap();
+    list.push_front(a).unwrap();
+    list.push_back(c).unwrap();
+    assert_eq!(list.len(), 3);
+    assert_eq!(ids(&list), [1, 2, 3]);
+    assert_eq!(id(list.front()), Some(1));
+    assert_eq!(id(list.back()), Some(3));
+    assert!(list.contains(b) && b.link.is_linked());
+
+    assert_eq!(id(list.pop_front()), Some(1));
+    assert_eq!(id(list.pop_back()), Some(3));
+    assert!(!a.link.is_linked() && !list.contains(a));
+
+    list.push_front(c).unwrap();
+    list.push_back(a).unwrap();
+    assert_eq!(ids(&list), [3, 2, 1]);
+    assert_eq!(list.iter().rev().map(|timer| timer.id).collect::<Vec<_>>(), [1, 2, 3]);
+
+    assert_eq!(id(list.pop_back()), Some(1));
+    assert_eq!(id(list.pop_back()), Some(2));
+    assert_eq!(id(list.pop_back()), Some(3));
+    assert_eq!(id(list.pop_back()), None);
+    assert!(list.is_empty());
+  }
+
+  #[test]
+  fn remove_by_reference() {
+    let timers: Vec<Pin<Box<Timer>>> = (0..5).map(|id| Box::pin(Timer::new(id))).collect();
+    let mut list = List::<TimerAdapter>::new();
+    for timer in &timers {
+      list.push_back(timer.as_ref()).unwrap();
+    }
+
+    assert!(list.remove(timers[2].as_ref()));
+    assert!(!list.remove(timers[2].as_ref()));
+    assert!(list.remove(timers[0].as_ref()));
+    assert!(list.remove(timers[4].as_ref()));
+    assert_eq!(ids(&list), [1, 3]);
+    assert_eq!(list.len(), 2);
+
+    list.push_front(timers[4].as_ref()).unwrap();
+    assert_eq!(ids(&list), [4, 1, 3]);
+  }
+
+  #[test]
+  fn one_list_at_a_time() {
+    let timer = pin!(Timer::new(0));
+    let timer = timer.into_ref();
+
+    let mut first = List::<TimerAdapter>::new();
+    let mut second = List::<TimerAdapter>::new();
+
+    first.push_back(timer).unwrap();
+    assert!(first.push_back(timer).is_err());
+    assert!(second.push_front(timer).is_err());
+    assert!(second.cursor_front_mut().insert_after(timer).is_err());
+
+    // The wrong list leaves it alone.
+    assert!(!second.remove(timer));
+    assert!(second.cursor_mut_at(timer).is_none());
+    assert_eq!(first.len(), 1);
+    assert_eq!(second.len(), 0);
+
+    // Dropping a list releases what's still on it.
+    drop(first);
+    assert!(!timer.link.is_linked());
+    second.push_back(timer).unwrap();
+    assert!(second.contains(timer));
+    // A forgotten list keeps its items, and no other list can touch them.
+    let stuck = pin!(Timer::new(1));
+    let stuck = stuck.into_ref();
+    let mut forgotten = List::<TimerAdapter>::new();
+    forgotten.push_back(stuck).unwrap();
+    std::mem::forget(forgotten);
+
+    let mut other = List::<TimerAdapter>::new();
+    assert!(!other.remove(stuck));
+    assert!(other.push_back(stuck).is_err());
+  }
+
+  #[test]
+  fn cursors() {
+    let timers: Vec<Pin<Box<Timer>>> = (0..8).map(|id| Box::pin(Timer::new(id))).collect();
+    let mut list = List::<TimerAdapter>::new();
+    for timer in &timers[..6] {
+      list.push_back(timer.as_ref()).unwrap();
+    }
+
+    // Remove the even ones on the way through.
+    let mut cursor = list.cursor_front_mut();
+    while let Some(timer) = cursor.current() {
+      if timer.id % 2 == 0 {
+        assert_eq!(id(cursor.remove_current()), Some(timer.id));
+      } else {
+        cursor.move_next();
+      }
+    }
+    // On the ghost position now; the next one is the front again.
+    assert_eq!(id(cursor.remove_current()), None);
+    cursor.move_next();
+    assert_eq!(id(cursor.current()), Some(1));
+    cursor.move_prev();
+    cursor.move_prev();
+    assert_eq!(id(cursor.current()), Some(5));
+    assert_eq!(ids(&list), [1, 3, 5]);
+
+    let mut cursor = list.cursor_mut_at(timers[3].as_ref()).unwrap();
+    cursor.insert_before(timers[2].as_ref()).unwrap();
+    cursor.insert_after(timers[4].as_ref()).unwrap();
+    assert_eq!(id(cursor.current()), Some(3));
+    cursor.move_prev();
+    cursor.move_prev();
+    cursor.move_prev();
+    assert_eq!(id(cursor.current()), None);
+    cursor.insert_after(timers[0].as_ref()).unwrap();
+    cursor.insert_before(timers[7].as_ref()).unwrap();
+    assert_eq!(ids(&list), [0, 1, 2, 3, 4, 5, 7]);
+
+    let mut cursor = list.cursor_back_mut();
+    cursor.insert_before(timers[6].as_ref()).unwrap();
+    assert_eq!(ids(&list), [0, 1, 2, 3, 4, 5, 6, 7]);
+    assert_eq!(list.len(), 8);
+
+    let mut empty = List::<TimerAdapter>::new();
+    let mut cursor = empty.cursor_back_mut();
+    assert_eq!(id(cursor.current()), None);
+    cursor.move_next();
+    assert_eq!(id(cursor.current()), None);
+  }
+
+  #[test]
+  fn also_on_a_queue() {
+    let a = pin!(Timer::new(1));
+    let b = pin!(Timer::new(2));
+    let (a, b) = (a.into_ref(), b.into_ref());
+
+    let queue = Queue::<FireAdapter>::new();
+    let mut pending = List::<TimerAdapter>::new();
+    pending.push_back(a).unwrap();
+    pending.push_back(b).unwrap();
+
+    // Firing `b` queues it without taking it off the list.
+    queue.push(b).unwrap();
+    let fired = queue.consumer().unwrap().pop().unwrap();
+    assert!(pending.remove(fired));
+    assert_eq!(ids(&pending), [1]);
+  }
+
+  #[test]
+  fn allocations() {
+    let a = pin!(Timer::new(1));
+    let b = pin!(Timer::new(2));
+    let (a, b) = (a.into_ref(), b.into_ref());
+
+    // Just the sentinel.
+    let (mut list, profile) = measure(List::<TimerAdapter>::new);
+    assert_eq!(profile.allocations, 1);
+
+    let ((), profile) = measure(|| {
+      list.push_back(a).unwrap();
+      list.push_front(b).unwrap();
+      assert_eq!(list.iter().count(), 2);
+      let mut cursor = list.cursor_front_mut();
+      cursor.move_next();
+      assert_eq!(id(cursor.remove_current()), Some(1));
+      cursor.insert_before(a).unwrap();
+      assert!(list.remove(b));
+      assert_eq!(id(list.pop_back()), Some(1));
+    });
+    assert_eq!(profile, Profile::default());
+  }
+
+  #[test]
+  fn against_brute_force() {
+    const TIMERS: usize = 20;
+
+    let timers: Vec<Pin<Box<Timer>>> = (0..TIMERS).map(|id| Box::pin(Timer::new(id))).collect();
+    let mut list = List::<TimerAdapter>::new();
+    let mut model = VecDeque::new();
+    let mut rng = Rng::new(47);
+
+    for _ in 0..10_000 {
+      let which = rng.below(TIMERS);
+      let timer = timers[which].as_ref();
+      let position = model.iter().position(|&other| other == which);
+
+      match rng.below(5) {
+        0 => {
+          assert_eq!(list.push_front(timer).is_ok(), position.is_none());
+          if position.is_none() {
+            model.push_front(which);
+          }
+        }
+        1 => {
+          assert_eq!(list.push_back(timer).is_ok(), position.is_none());
+          if position.is_none() {
+            model.push_back(which);
+          }
+        }
+        2 => assert_eq!(id(list.pop_front()), model.pop_front()),
+        3 => assert_eq!(id(list.pop_back()), model.pop_back()),
+        _ => {
+          assert_eq!(list.remove(timer), position.is_some());
+          if let Some(position) = position {
+            model.remove(position);
+          }
+        }
+      }
+
+      assert_eq!(list.len(), model.len());
+      assert!(list.iter().map(|timer| timer.id).eq(model.iter().copied()));
+      assert!(list.iter().rev().map(|timer| timer.id).eq(model.iter().rev().copied()));
+    }
+  }
+}
@@ -0,0 +1,362 @@
+use alloc::boxed::Box;
+use core::cell::UnsafeCell;
+use core::marker::{PhantomData, PhantomPinned};
+use core::pin::Pin;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+#[cfg(feature = "std")]
+use std::thread;
+
+use crate::intrusive::Adapter;
+
+// Intrusive multi-producer, single-consumer queue (Vyukov's algorithm).
+//
+// Items carry their own `Link`, so pushing never allocates. Like
+// `ch6_unsafe_singly_linked::List`, pushes go to the back through a tail
+// pointer and pops take from the front, but producers only need a single
+// `swap` on the tail, and a heap-allocated stub link keeps the list from ever
+// being truly empty.
+//
+// Items are borrowed as `Pin<&'a Item>`: they can't move or go away while
+// queued, and the consumer gets the same reference back.
+
+#[derive(Debug)]
+pub struct Link {
+  next: AtomicPtr<Link>,
+  queued: AtomicBool,
+  _pinned: PhantomPinned,
+}
+
+impl Link {
+  pub const fn new() -> Self {
+    Link {
+      next: AtomicPtr::new(ptr::null_mut()),
+      queued: AtomicBool::new(false),
+      _pinned: PhantomPinned,
+    }
+  }
+
+  pub fn is_queued(&self) -> bool {
+    self.queued.load(Ordering::Acquire)
+  }
+}
+
+impl Default for Link {
+  fn default() -> Self {
+    Link::new()
+  }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PopResult<T> {
+  Data(T),
+  Empty,
+  // A producer is halfway through a push; try again in a moment.
+  Inconsistent,
+}
+
+pub struct Queue<'a, A: Adapter<Link = Link>> {
+  // Where producers push.
+  tail: AtomicPtr<Link>,
+  // Where the consumer pops, only ever touched through a `Consumer`.
+  head: UnsafeCell<*mut Link>,
+  stub: Box<Link>,
+  consumer: AtomicBool,
+  _items: PhantomData<Pin<&'a A::Item>>,
+}
+
+unsafe impl<'a, A: Adapter<Link = Link>> Send for Queue<'a, A> where A::Item: Sync {}
+unsafe impl<'a, A: Adapter<Link = Link>> Sync for Queue<'a, A> where A::Item: Sync {}
+
+impl<'a, A: Adapter<Link = Link>> Queue<'a, A> {
+  pub fn new() -> Self {
+    let stub = Box::new(Link::new());
+    let raw_stub = &*stub as *const Link as *mut Link;
+
+    Queue {
+      tail: AtomicPtr::new(raw_stub),
+      head: UnsafeCell::new(raw_stub),
+      stub,
+      consumer: AtomicBool::new(false),
+      _items: PhantomData,
+    }
+  }
+
+  // Pushes `item`, or hands it back if it's already queued (here or in
+  // another queue).
+  pub fn push(&self, item: Pin<&'a A::Item>) -> Result<(), Pin<&'a A::Item>> {
+    let link = unsafe { A::link(item.get_ref()) };
+    if unsafe { (*link).queued.swap(true, Ordering::AcqRel) } {
+      return Err(item);
+    }
+
+    unsafe {
+      self.push_link(link as *mut Link);
+    }
+    Ok(())
+  }
+
+  unsafe fn push_link(&self, link: *mut Link) {
+    (*link).next.store(ptr::null_mut(), Ordering::Relaxed);
+    let prev = self.tail.swap(link, Ordering::AcqRel);
+    (*prev).next.store(link, Ordering::Release);
+  }
+
+  fn raw_stub(&self) -> *mut Link {
+    &*self.stub as *const Link as *mut Link
+  }
+
+  // The one handle allowed to pop, or `None` while someone else holds it.
+  pub fn consumer(&self) -> Option<Consumer<'_, 'a, A>> {
+    if self.consumer.swap(true, Ordering::Acquire) {
+      None
+    } else {
+      Some(Consumer { queue: self })
+    }
+  }
+
+  // Safety: only one thread at a time, i.e. through a `Consumer`.
+  unsafe fn try_pop(&self) -> PopResult<Pin<&'a A::Item>> {
+    let head = self.head.get();
+    let stub = self.raw_stub();
+
+    let mut first = *head;
+    let mut next = (*first).next.load(Ordering::Acquire);
+
+    if first == stub {
+      if next.is_null() {
+        return PopResult::Empty;
+      }
+      *head = next;
+      first = next;
+      next = (*next).next.load(Ordering::Acquire);
+    }
+
+    if next.is_null() {
+      // `first` is the last link, unless a producer already swapped in the
+      // next one and just hasn't linked it yet.
+      if first != self.tail.load(Ordering::Acquire) {
+        return PopResult::Inconsistent;
+      }
+
+      // Put the stub back behind it so it can be taken out.
+      self.push_link(stub);
+      next = (*first).next.load(Ordering::Acquire);
+      if next.is_null() {
+        return PopResult::Inconsistent;
+      }
+    }
+
+    *head = next;
+    (*first).queued.store(false, Ordering::Release);
+    PopResult::Data(Pin::new_unchecked(&*A::item(first)))
+  }
+}
+
+impl<'a, A: Adapter<Link = Link>> Default for Queue<'a, A> {
+  fn default() -> Self {
+    Queue::new()
+  }
+}
+
+impl<'a, A: Adapter<Link = Link>> Drop for Queue<'a, A> {
+  fn drop(&mut self) {
+    // Un-queue whatever is left so it can be pushed somewhere else. Without
+    // producers around, the queue can't be inconsistent.
+    while let PopResult::Data(_) = unsafe { self.try_pop() } {}
+  }
+}
+
+pub struct Consumer<'q, 'a, A: Adapter<Link = Link>> {
+  queue: &'q Queue<'a, A>,
+}
+
+impl<'q, 'a, A: Adapter<Link = Link>> Consumer<'q, 'a, A> {
+  pub fn try_pop(&mut self) -> PopResult<Pin<&'a A::Item>> {
+    unsafe { self.queue.try_pop() }
+  }
+
+  // Pops the front item, waiting out half-finished pushes. `None` means the
+  // queue was empty.
+  pub fn pop(&mut self) -> Option<Pin<&'a A::Item>> {
+    loop {
+      match self.try_pop() {
+        PopResult::Data(item) => return Some(item),
+        PopResult::Empty => return None,
+        PopResult::Inconsistent => {
+          #[cfg(feature = "std")]
+          thread::yield_now();
+          #[cfg(not(feature = "std"))]
+          core::hint::spin_loop();
+        }
+      }
+    }
+  }
+}
+
+impl<'q, 'a, A: Adapter<Link = Link>> Drop for Consumer<'q, 'a, A> {
+  fn drop(&mut self) {
+    self.queue.consumer.store(false, Ordering::Release);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Link, PopResult, Queue};
+  use std::pin::{pin, Pin};
+  use std::thread;
+
+  #[derive(Debug)]
+  struct Task {
+    id: (usize, usize),
+    link: Link,
+  }
+
+  impl Task {
+    fn new(id: (usize, usize)) -> Self {
+      Task { id, link: Link::new() }
+    }
+  }
+
+  crate::intrusive_adapter!(TaskAdapter = Task { link: Link });
+
+  fn id(task: Option<Pin<&Task>>) -> Option<(usize, usize)> {
+    task.map(|task| task.id)
+  }
+
+  #[test]
+  fn basics() {
+    let a = pin!(Task::new((0, 1)));
+    let b = pin!(Task::new((0, 2)));
+    let c = pin!(Task::new((0, 3)));
+    let (a, b, c) = (a.into_ref(), b.into_ref(), c.into_ref());
+
+    let queue = Queue::<TaskAdapter>::new();
+    let mut consumer = queue.consumer().unwrap();
+
+    assert!(matches!(consumer.try_pop(), PopResult::Empty));
+
+    queue.push(a).unwrap();
+    queue.push(b).unwrap();
+    assert!(a.link.is_queued());
+
+    assert_eq!(id(consumer.pop()), Some((0, 1)));
+    assert!(!a.link.is_queued());
+
+    queue.push(c).unwrap();
+    queue.push(a).unwrap();
+
+    assert_eq!(id(consumer.pop()), Some((0, 2)));
+    assert_eq!(id(consumer.pop()), Some((0, 3)));
+    assert_eq!(id(consumer.pop()), Some((0, 1)));
+    assert_eq!(id(consumer.pop()), None);
+
+    queue.push(b).unwrap();
+    assert_eq!(id(consumer.pop()), Some((0, 2)));
+    assert_eq!(id(consumer.pop()), None);
+  }
+
+  #[test]
+  fn no_double_push() {
+    let task = pin!(Task::new((0, 0)));
+    let task = task.into_ref();
+
+    let first = Queue::<TaskAdapter>::new();
+    let second = Queue::<TaskAdapter>::new();
+
+    assert!(first.push(task).is_ok());
+    assert!(first.push(task).is_err());
+    assert!(second.push(task).is_err());
+
+    // Dropping a queue releases what's still in it.
+    drop(first);
+    assert!(!task.link.is_queued());
+    assert!(second.push(task).is_ok());
+  }
+
+  #[test]
+  fn single_consumer() {
+    let queue = Queue::<TaskAdapter>::new();
+
+    let consumer = queue.consumer();
+    assert!(consumer.is_some());
+    assert!(queue.consumer().is_none());
+
+    drop(consumer);
+    assert!(queue.consumer().is_some());
+  }
+
+  #[test]
+  fn many_producers() {
+    const PRODUCERS: usize = 8;
+    const PER_PRODUCER: usize = 5_000;
+
+    let tasks: Vec<Vec<Pin<Box<Task>>>> = (0..PRODUCERS).map(|p| {
+      (0..PER_PRODUCER).map(|i| Box::pin(Task::new((p, i)))).collect()
+    }).collect();
+
+    let queue = Queue::<TaskAdapter>::new();
+    let mut consumer = queue.consumer().unwrap();
+
+    let mut last = [None; PRODUCERS];
+    let mut popped = 0;
+
+    thread::scope(|s| {
+      for mine in &tasks {
+        let queue = &queue;
+        s.spawn(move || {
+          for task in mine {
+            queue.push(task.as_ref()).unwrap();
+          }
+        });
+      }
+
+      while popped < PRODUCERS * PER_PRODUCER {
+        if let Some(task) = consumer.pop() {
+          let (p, i) = task.id;
+          assert!(last[p].is_none_or(|last| last + 1 == i), "{:?} out of order", (p, i));
+          last[p] = Some(i);
+          popped += 1;
+        }
+      }
+    });
+
+    assert_eq!(id(consumer.pop()), None);
+    assert!(tasks.iter().flatten().all(|task| !task.link.is_queued()));
+  }
+
+  #[test]
+  fn requeue_from_producers() {
+    // Producers keep pushing the same few tasks back as soon as the
+    // consumer has taken them out.
+    let tasks: Vec<Pin<Box<Task>>> = (0..4).map(|t| Box::pin(Task::new((t, 0)))).collect();
+    let queue = Queue::<TaskAdapter>::new();
+    let mut consumer = queue.consumer().unwrap();
+
+    let mut popped = 0;
+    thread::scope(|s| {
+      for task in &tasks {
+        let queue = &queue;
+        s.spawn(move || {
+          let mut pushed = 0;
+          while pushed < 1_000 {
+            if queue.push(task.as_ref()).is_ok() {
+              pushed += 1;
+            } else {
+              thread::yield_now();
+            }
+          }
+        });
+      }
+
+      while popped < 4_000 {
+        match consumer.pop() {
+          Some(_) => popped += 1,
+          None => thread::yield_now(),
+        }
+      }
+    });
+
+    assert_eq!(id(consumer.pop()), None);
+  }
+}
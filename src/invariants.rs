@@ -0,0 +1,103 @@
+use core::error::Error;
+use core::fmt;
+use core::ptr;
+
+// What `check_invariants` found wrong with the links of an unsafe list.
+// Indices count nodes from the front, starting at 0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InvariantError {
+  // `head` and `tail` disagree on whether the list is empty.
+  EmptyMismatch { head_is_none: bool },
+  // The first node's `prev` isn't null.
+  HeadHasPrev,
+  // The node at `index` has a `prev` that isn't the node before it.
+  BrokenPrev { index: usize },
+  // `tail` doesn't point at the last of the `len` nodes.
+  TailNotLast { len: usize },
+  // Following `next` from the node before `index` leads back to a node
+  // already seen.
+  Cycle { index: usize },
+}
+
+impl fmt::Display for InvariantError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match *self {
+      InvariantError::EmptyMismatch { head_is_none: true } => {
+        f.write_str("`head` is empty but `tail` points at a node")
+      }
+      InvariantError::EmptyMismatch { head_is_none: false } => {
+        f.write_str("`tail` is null but `head` points at a node")
+      }
+      InvariantError::HeadHasPrev => f.write_str("the first node has a `prev`"),
+      InvariantError::BrokenPrev { index } => {
+        write!(f, "node {} has a `prev` that isn't node {}", index, index.wrapping_sub(1))
+      }
+      InvariantError::TailNotLast { len } => {
+        write!(f, "`tail` doesn't point at the last of the {} nodes", len)
+      }
+      InvariantError::Cycle { index } => {
+        write!(f, "the `next` of node {} leads back to an earlier node", index.wrapping_sub(1))
+      }
+    }
+  }
+}
+
+impl Error for InvariantError {}
+
+// Brent's cycle detection over the `next` links from `first`, so checking a
+// list doesn't allocate. Returns the index of the first node `next` leads
+// back to a second time, which `Cycle` reports.
+pub(crate) fn find_cycle<'a, N>(first: &'a N, next: impl Fn(&'a N) -> Option<&'a N>) -> Option<usize> {
+  let mut power = 1;
+  let mut length = 1;
+  let mut tortoise = first;
+  let mut hare = next(first)?;
+
+  while !ptr::eq(tortoise, hare) {
+    if power == length {
+      tortoise = hare;
+      power *= 2;
+      length = 0;
+    }
+    hare = next(hare)?;
+    length += 1;
+  }
+
+  // The loop is `length` nodes long; walking two pointers that far apart
+  // finds where it starts.
+  let mut tortoise = first;
+  let mut hare = first;
+  for _ in 0..length {
+    hare = next(hare)?;
+  }
+
+  let mut start = 0;
+  while !ptr::eq(tortoise, hare) {
+    tortoise = next(tortoise)?;
+    hare = next(hare)?;
+    start += 1;
+  }
+  Some(start + length)
+}
+
+#[cfg(test)]
+mod test {
+  use super::find_cycle;
+
+  // Each entry is the index of the next one; out of range ends the list.
+  fn cycle(next: &[usize]) -> Option<usize> {
+    find_cycle(&next[0], |&i| next.get(i))
+  }
+
+  #[test]
+  fn finds_cycles() {
+    assert_eq!(cycle(&[9]), None);
+    assert_eq!(cycle(&[1, 2, 3, 9]), None);
+
+    assert_eq!(cycle(&[0]), Some(1));
+    assert_eq!(cycle(&[1, 2, 3, 0]), Some(4));
+    // 0 -> 1 -> 2 -> 3 -> 4 -> 2: the sixth node visited is node 2 again.
+    assert_eq!(cycle(&[1, 2, 3, 4, 2]), Some(5));
+    assert_eq!(cycle(&[1, 2, 3, 4, 4]), Some(5));
+  }
+}
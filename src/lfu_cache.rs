@@ -0,0 +1,497 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::mem;
+use std::ptr;
+use crate::drop_guard;
+
+// Least-frequently-used cache with O(1) `get`/`put`/eviction.
+//
+// Entries live in frequency buckets; the buckets form a list sorted by
+// frequency (lowest first) and every bucket holds its entries from least to
+// most recently used. Both lists are doubly linked through raw pointers, and
+// the map points straight at the entries too. So every node is owned through
+// those pointers alone: it's put on the heap with `Box::into_raw` when it's
+// created and only turned back into a `Box` to free it. A `Box` that moved
+// around while the other pointers into it were in use would invalidate them.
+pub struct LfuCache<K, V> {
+  map: HashMap<K, *mut Entry<K, V>>,
+  buckets: *mut Bucket<K, V>,
+  capacity: usize,
+}
+
+struct Bucket<K, V> {
+  freq: usize,
+  head: *mut Entry<K, V>,
+  tail: *mut Entry<K, V>,
+  next: *mut Bucket<K, V>,
+  prev: *mut Bucket<K, V>,
+}
+
+struct Entry<K, V> {
+  key: K,
+  value: V,
+  next: *mut Entry<K, V>,
+  prev: *mut Entry<K, V>,
+  bucket: *mut Bucket<K, V>,
+}
+
+impl<K, V> Bucket<K, V> {
+  // `entry` must not be in any bucket.
+  unsafe fn push_back(bucket: *mut Self, entry: *mut Entry<K, V>) {
+    (*entry).next = ptr::null_mut();
+    (*entry).prev = (*bucket).tail;
+    (*entry).bucket = bucket;
+
+    if (*bucket).tail.is_null() {
+      (*bucket).head = entry;
+    } else {
+      (*(*bucket).tail).next = entry;
+    }
+
+    (*bucket).tail = entry;
+  }
+
+  // `entry` must belong to `bucket`. It's left allocated, in no bucket.
+  unsafe fn unlink(bucket: *mut Self, entry: *mut Entry<K, V>) {
+    let prev = (*entry).prev;
+    let next = (*entry).next;
+
+    if prev.is_null() {
+      (*bucket).head = next;
+    } else {
+      (*prev).next = next;
+    }
+
+    if next.is_null() {
+      (*bucket).tail = prev;
+    } else {
+      (*next).prev = prev;
+    }
+
+    (*entry).next = ptr::null_mut();
+    (*entry).prev = ptr::null_mut();
+  }
+}
+
+impl<K: Hash + Eq + Clone, V> LfuCache<K, V> {
+  pub fn new(capacity: usize) -> Self {
+    LfuCache {
+      map: HashMap::with_capacity(capacity),
+      buckets: ptr::null_mut(),
+      capacity,
+    }
+  }
+
+  pub fn capacity(&self) -> usize {
+    self.capacity
+  }
+
+  pub fn len(&self) -> usize {
+    self.map.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.map.is_empty()
+  }
+
+  pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where K: Borrow<Q>, Q: Hash + Eq + ?Sized
+  {
+    self.map.contains_key(key)
+  }
+
+  // Inserts or updates `key`, counting as a use. Returns the previous value
+  // when the key was already cached. When the cache is full, the least
+  // frequently used entry is evicted first (the least recently used among
+  // ties).
+  pub fn put(&mut self, key: K, value: V) -> Option<V> {
+    if let Some(&entry) = self.map.get(&key) {
+      unsafe {
+        let old = mem::replace(&mut (*entry).value, value);
+        self.touch(entry);
+        return Some(old);
+      }
+    }
+
+    if self.capacity == 0 {
+      return None;
+    }
+
+    if self.map.len() == self.capacity {
+      self.pop_lfu();
+    }
+
+    unsafe {
+      let first = if !self.buckets.is_null() && (*self.buckets).freq == 1 {
+        self.buckets
+      } else {
+        self.insert_bucket_after(ptr::null_mut(), 1)
+      };
+
+      let entry = Box::into_raw(Box::new(Entry {
+        key: key.clone(),
+        value,
+        next: ptr::null_mut(),
+        prev: ptr::null_mut(),
+        bucket: first,
+      }));
+      Bucket::push_back(first, entry);
+
+      self.map.insert(key, entry);
+    }
+    None
+  }
+
+  pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where K: Borrow<Q>, Q: Hash + Eq + ?Sized
+  {
+    let entry = *self.map.get(key)?;
+    unsafe {
+      self.touch(entry);
+      Some(&(*entry).value)
+    }
+  }
+
+  pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where K: Borrow<Q>, Q: Hash + Eq + ?Sized
+  {
+    let entry = *self.map.get(key)?;
+    unsafe {
+      self.touch(entry);
+      Some(&mut (*entry).value)
+    }
+  }
+
+  // Like `get`, but doesn't count as a use.
+  pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where K: Borrow<Q>, Q: Hash + Eq + ?Sized
+  {
+    self.map.get(key).map(|&entry| unsafe { &(*entry).value })
+  }
+
+  pub fn frequency<Q>(&self, key: &Q) -> Option<usize>
+    where K: Borrow<Q>, Q: Hash + Eq + ?Sized
+  {
+    self.map.get(key).map(|&entry| unsafe { (*(*entry).bucket).freq })
+  }
+
+  pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where K: Borrow<Q>, Q: Hash + Eq + ?Sized
+  {
+    let entry = self.map.remove(key)?;
+    unsafe {
+      let bucket = (*entry).bucket;
+      Bucket::unlink(bucket, entry);
+      if (*bucket).head.is_null() {
+        self.remove_bucket(bucket);
+      }
+      Some(Box::from_raw(entry).value)
+    }
+  }
+
+  // Evicts the entry `put` would evict next.
+  pub fn pop_lfu(&mut self) -> Option<(K, V)> {
+    if self.buckets.is_null() {
+      return None;
+    }
+
+    unsafe {
+      let bucket = self.buckets;
+      let entry = (*bucket).head;
+      Bucket::unlink(bucket, entry);
+      if (*bucket).head.is_null() {
+        self.remove_bucket(bucket);
+      }
+
+      let Entry { key, value, .. } = *Box::from_raw(entry);
+      self.map.remove(&key);
+      Some((key, value))
+    }
+  }
+
+  // Moves `entry` from its bucket to the back of the next frequency's one.
+  unsafe fn touch(&mut self, entry: *mut Entry<K, V>) {
+    let bucket = (*entry).bucket;
+    let freq = (*bucket).freq + 1;
+
+    let next = (*bucket).next;
+    let target = if !next.is_null() && (*next).freq == freq {
+      next
+    } else {
+      self.insert_bucket_after(bucket, freq)
+    };
+
+    Bucket::unlink(bucket, entry);
+    Bucket::push_back(target, entry);
+
+    if (*bucket).head.is_null() {
+      self.remove_bucket(bucket);
+    }
+  }
+}
+
+impl<K, V> LfuCache<K, V> {
+  pub fn clear(&mut self) {
+    self.map.clear();
+
+    // One entry at a time, then the bucket once it's empty.
+    drop_guard::drain(&mut self.buckets, |bucket| unsafe {
+      if bucket.is_null() {
+        return false;
+      }
+
+      let entry = (**bucket).head;
+      if entry.is_null() {
+        let old = Box::from_raw(*bucket);
+        *bucket = old.next;
+      } else {
+        (**bucket).head = (*entry).next;
+        drop(Box::from_raw(entry));
+      }
+      true
+    });
+  }
+
+  // A null `after` inserts the bucket at the front.
+  unsafe fn insert_bucket_after(&mut self, after: *mut Bucket<K, V>, freq: usize) -> *mut Bucket<K, V> {
+    let next = if after.is_null() { self.buckets } else { (*after).next };
+
+    let new_bucket = Box::into_raw(Box::new(Bucket {
+      freq,
+      head: ptr::null_mut(),
+      tail: ptr::null_mut(),
+      next,
+      prev: after,
+    }));
+
+    if !next.is_null() {
+      (*next).prev = new_bucket;
+    }
+
+    if after.is_null() {
+      self.buckets = new_bucket;
+    } else {
+      (*after).next = new_bucket;
+    }
+
+    new_bucket
+  }
+
+  // `bucket` must be empty and belong to this cache.
+  unsafe fn remove_bucket(&mut self, bucket: *mut Bucket<K, V>) {
+    let old = Box::from_raw(bucket);
+
+    if old.prev.is_null() {
+      self.buckets = old.next;
+    } else {
+      (*old.prev).next = old.next;
+    }
+
+    if !old.next.is_null() {
+      (*old.next).prev = old.prev;
+    }
+  }
+}
+
+impl<K, V> Drop for LfuCache<K, V> {
+  fn drop(&mut self) {
+    self.clear();
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::LfuCache;
+  use crate::test_support::{measure, Profile};
+
+  #[test]
+  fn basics() {
+    let mut cache = LfuCache::new(2);
+
+    assert!(cache.is_empty());
+    assert_eq!(cache.capacity(), 2);
+    assert_eq!(cache.get(&1), None);
+
+    assert_eq!(cache.put(1, "one"), None);
+    assert_eq!(cache.put(2, "two"), None);
+    assert_eq!(cache.len(), 2);
+
+    assert_eq!(cache.get(&1), Some(&"one"));
+    assert_eq!(cache.get(&2), Some(&"two"));
+
+    assert_eq!(cache.put(1, "uno"), Some("one"));
+    assert_eq!(cache.get(&1), Some(&"uno"));
+    assert_eq!(cache.len(), 2);
+  }
+
+  #[test]
+  fn evicts_least_frequently_used() {
+    let mut cache = LfuCache::new(3);
+
+    cache.put(1, 10);
+    cache.put(2, 20);
+    cache.put(3, 30);
+
+    cache.get(&1);
+    cache.get(&1);
+    cache.get(&3);
+
+    // 2 has only been used once.
+    cache.put(4, 40);
+    assert!(!cache.contains_key(&2));
+    assert_eq!(cache.len(), 3);
+
+    // 4 is now the only entry used once.
+    cache.put(5, 50);
+    assert!(!cache.contains_key(&4));
+
+    cache.get(&5);
+    cache.get(&5);
+
+    // 3 and 5 were used twice, 1 three times.
+    cache.put(6, 60);
+    assert!(!cache.contains_key(&3));
+    assert_eq!(cache.get(&1), Some(&10));
+    assert_eq!(cache.get(&5), Some(&50));
+    assert_eq!(cache.get(&6), Some(&60));
+  }
+
+  #[test]
+  fn ties_evict_least_recently_used() {
+    let mut cache = LfuCache::new(3);
+
+    cache.put(1, 'a');
+    cache.put(2, 'b');
+    cache.put(3, 'c');
+
+    assert_eq!(cache.pop_lfu(), Some((1, 'a')));
+
+    cache.put(1, 'a');
+    cache.get(&3);
+    cache.get(&2);
+    cache.get(&1);
+
+    assert_eq!(cache.pop_lfu(), Some((3, 'c')));
+    assert_eq!(cache.pop_lfu(), Some((2, 'b')));
+    assert_eq!(cache.pop_lfu(), Some((1, 'a')));
+    assert_eq!(cache.pop_lfu(), None);
+    assert!(cache.is_empty());
+  }
+
+  #[test]
+  fn peek_and_frequency() {
+    let mut cache = LfuCache::new(2);
+
+    assert_eq!(cache.frequency(&1), None);
+
+    cache.put(1, 1);
+    cache.put(2, 2);
+    assert_eq!(cache.frequency(&1), Some(1));
+
+    assert_eq!(cache.peek(&1), Some(&1));
+    assert_eq!(cache.frequency(&1), Some(1));
+
+    cache.get(&1);
+    cache.put(1, 11);
+    assert_eq!(cache.frequency(&1), Some(3));
+    assert_eq!(cache.frequency(&2), Some(1));
+
+    if let Some(value) = cache.get_mut(&2) {
+      *value = 22;
+    }
+    assert_eq!(cache.peek(&2), Some(&22));
+    assert_eq!(cache.frequency(&2), Some(2));
+  }
+
+  #[test]
+  fn remove() {
+    let mut cache = LfuCache::new(3);
+
+    cache.put("a".to_string(), 1);
+    cache.put("b".to_string(), 2);
+    cache.put("c".to_string(), 3);
+    cache.get("b");
+
+    assert_eq!(cache.remove("b"), Some(2));
+    assert_eq!(cache.remove("b"), None);
+    assert_eq!(cache.len(), 2);
+
+    cache.put("d".to_string(), 4);
+    cache.put("e".to_string(), 5);
+
+    assert!(!cache.contains_key("a"));
+    assert_eq!(cache.pop_lfu(), Some(("c".to_string(), 3)));
+    assert_eq!(cache.pop_lfu(), Some(("d".to_string(), 4)));
+    assert_eq!(cache.pop_lfu(), Some(("e".to_string(), 5)));
+  }
+
+  #[test]
+  fn zero_capacity() {
+    let mut cache = LfuCache::new(0);
+
+    assert_eq!(cache.put(1, 1), None);
+    assert_eq!(cache.get(&1), None);
+    assert!(cache.is_empty());
+  }
+
+  #[test]
+  fn clear() {
+    let mut cache = LfuCache::new(100_000);
+
+    for i in 0..100_000 {
+      cache.put(i, i);
+    }
+    for i in 0..100_000 {
+      if i % 3 == 0 {
+        cache.get(&i);
+      }
+    }
+
+    cache.clear();
+    assert!(cache.is_empty());
+    assert_eq!(cache.get(&0), None);
+
+    cache.put(0, 0);
+    assert_eq!(cache.get(&0), Some(&0));
+  }
+
+  #[test]
+  fn allocations() {
+    // The map's table is allocated up front, for the whole capacity.
+    let (mut cache, profile) = measure(|| LfuCache::new(3));
+    assert_eq!((profile.allocations, profile.deallocations), (1, 0));
+
+    // A new key takes an entry, plus the first bucket if there isn't one.
+    let (_, profile) = measure(|| cache.put(1, 10));
+    assert_eq!((profile.allocations, profile.deallocations), (2, 0));
+    let (_, profile) = measure(|| cache.put(2, 20));
+    assert_eq!((profile.allocations, profile.deallocations), (1, 0));
+
+    // A use moves the entry to the next bucket, making it if needed and
+    // freeing the old one once it's empty.
+    let (_, profile) = measure(|| cache.get(&1));
+    assert_eq!((profile.allocations, profile.deallocations), (1, 0));
+    let (_, profile) = measure(|| cache.get(&2));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 1));
+
+    let ((), profile) = measure(|| {
+      assert_eq!(cache.peek(&1), Some(&10));
+      assert_eq!(cache.frequency(&2), Some(2));
+      assert!(cache.contains_key(&1));
+      assert_eq!(cache.get(&3), None);
+    });
+    assert_eq!(profile, Profile::default());
+
+    cache.put(3, 30);
+    // Evicting 3 frees its entry and bucket; 4 needs both again.
+    let (_, profile) = measure(|| cache.put(4, 40));
+    assert_eq!((profile.allocations, profile.deallocations), (2, 2));
+    let (_, profile) = measure(|| cache.remove(&4));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 2));
+
+    // Two entries in one bucket, and the map.
+    let ((), profile) = measure(|| drop(cache));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 4));
+  }
+}
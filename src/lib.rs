@@ -1,7 +1,57 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+// Without the `std` feature only the lists themselves are left: everything
+// that needs threads, locks, thread-locals or a hash map is gated on it. The
+// tests always have `std`, so they run in either configuration.
+extern crate alloc;
+
+pub mod arena_list;
+#[cfg(feature = "std")]
+pub mod async_channel;
+pub mod bounded_queue;
 pub mod ch2_warmup;
 pub mod ch3_singly_linked;
 pub mod ch4_immutable;
 pub mod ch5_mutable_deque_without_refs;
 pub mod ch6_unsafe_singly_linked;
+#[cfg(feature = "std")]
+pub mod channel;
+#[cfg(feature = "std")]
+pub mod chase_lev;
+#[cfg(feature = "std")]
+pub mod concurrent_deque;
+mod drop_guard;
+#[cfg(feature = "std")]
+pub mod hazard;
+pub mod intrusive;
+pub mod intrusive_list;
+pub mod intrusive_mpsc;
+#[cfg(any(debug_assertions, feature = "invariants"))]
+pub mod invariants;
+#[cfg(feature = "std")]
+pub mod lfu_cache;
+#[cfg(test)]
+mod model;
+#[cfg(feature = "std")]
+pub mod ms_queue;
+pub mod observer;
+pub mod ring;
+pub mod rng;
 pub mod singly_linked_by_myself;
+pub mod skip_list;
+// Only public so `src/bin/bench.rs` can count allocations; not part of the
+// crate's API.
+#[cfg(any(feature = "std", test))]
+#[doc(hidden)]
+pub mod test_support;
+#[cfg(feature = "std")]
+pub mod thread_pool;
+pub mod traits;
+#[cfg(feature = "std")]
+pub mod treiber_stack;
+pub mod unrolled;
 pub mod unsafe_deque;
+
+#[cfg(test)]
+#[global_allocator]
+static ALLOCATOR: test_support::CountingAlloc = test_support::CountingAlloc::system();
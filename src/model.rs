@@ -0,0 +1,490 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Write;
+use std::ops::Deref;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::rng::Rng;
+
+// Model-based randomized testing. A `Harness` describes how to drive one
+// list type; `check` generates random operation sequences from fixed seeds,
+// runs each one against the list and against a `VecDeque` oracle, and
+// compares the two after every step. A failing sequence is shrunk to a
+// minimal one and reported as a ready-to-paste Rust test.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+  PushFront(i32),
+  PushBack(i32),
+  PopFront,
+  PopBack,
+  PeekFront,
+  PeekBack,
+  Clear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpKind {
+  PushFront,
+  PushBack,
+  PopFront,
+  PopBack,
+  PeekFront,
+  PeekBack,
+  Clear,
+}
+
+impl Op {
+  fn generate(kind: OpKind, rng: &mut Rng) -> Op {
+    let value = rng.below(100) as i32;
+    match kind {
+      OpKind::PushFront => Op::PushFront(value),
+      OpKind::PushBack => Op::PushBack(value),
+      OpKind::PopFront => Op::PopFront,
+      OpKind::PopBack => Op::PopBack,
+      OpKind::PeekFront => Op::PeekFront,
+      OpKind::PeekBack => Op::PeekBack,
+      OpKind::Clear => Op::Clear,
+    }
+  }
+
+  // Pops and peeks hand back a value that has to match the oracle's.
+  fn returns_value(&self) -> bool {
+    matches!(self, Op::PopFront | Op::PopBack | Op::PeekFront | Op::PeekBack)
+  }
+
+  fn with_value(&self, value: i32) -> Op {
+    match *self {
+      Op::PushFront(_) => Op::PushFront(value),
+      Op::PushBack(_) => Op::PushBack(value),
+      op => op,
+    }
+  }
+
+  fn value(&self) -> Option<i32> {
+    match *self {
+      Op::PushFront(value) | Op::PushBack(value) => Some(value),
+      _ => None,
+    }
+  }
+}
+
+fn oracle(model: &mut VecDeque<i32>, op: Op) -> Option<i32> {
+  match op {
+    Op::PushFront(value) => {
+      model.push_front(value);
+      None
+    }
+    Op::PushBack(value) => {
+      model.push_back(value);
+      None
+    }
+    Op::PopFront => model.pop_front(),
+    Op::PopBack => model.pop_back(),
+    Op::PeekFront => model.front().copied(),
+    Op::PeekBack => model.back().copied(),
+    Op::Clear => {
+      model.clear();
+      None
+    }
+  }
+}
+
+// The `Harness` for a deque with the usual inherent methods: `push_*`,
+// `pop_*`, `peek_*`, `clear`, and a double-ended `iter`, which it observes
+// both ways. `$new` is the constructor, `$new_code` the same as a statement
+// binding `list`. Lists without `iter` pass their own `observe`, `expect` and
+// `observe_code` instead.
+macro_rules! deque_harness {
+  ($name:expr, $new:expr, $new_code:expr) => {
+    $crate::model::deque_harness!(
+      $name, $new, $new_code,
+      observe: |list| (list.iter().copied().collect(), list.iter().rev().copied().collect()),
+      expect: |model| (model.iter().copied().collect::<Vec<_>>(), model.iter().rev().copied().collect::<Vec<_>>()),
+      observe_code: "(list.iter().copied().collect::<Vec<_>>(), list.iter().rev().copied().collect::<Vec<_>>())",
+    )
+  };
+  (
+    $name:expr, $new:expr, $new_code:expr,
+    observe: $observe:expr, expect: $expect:expr, observe_code: $observe_code:expr $(,)?
+  ) => {
+    $crate::model::Harness {
+      name: $name,
+      new: $new,
+      ops: &[
+        $crate::model::OpKind::PushFront, $crate::model::OpKind::PushBack,
+        $crate::model::OpKind::PopFront, $crate::model::OpKind::PopBack,
+        $crate::model::OpKind::PeekFront, $crate::model::OpKind::PeekBack,
+        $crate::model::OpKind::Clear,
+      ],
+      apply: |list, op| {
+        use $crate::model::{peeked, Op};
+        match op {
+          Op::PushFront(value) => {
+            list.push_front(value);
+            None
+          }
+          Op::PushBack(value) => {
+            list.push_back(value);
+            None
+          }
+          Op::PopFront => list.pop_front(),
+          Op::PopBack => list.pop_back(),
+          Op::PeekFront => peeked(list.peek_front()),
+          Op::PeekBack => peeked(list.peek_back()),
+          Op::Clear => {
+            list.clear();
+            None
+          }
+        }
+      },
+      observe: $observe,
+      expect: $expect,
+      new_code: $new_code,
+      op_code: |op| {
+        use $crate::model::Op;
+        match op {
+          Op::PushFront(value) => format!("list.push_front({})", value),
+          Op::PushBack(value) => format!("list.push_back({})", value),
+          Op::PopFront => "list.pop_front()".to_string(),
+          Op::PopBack => "list.pop_back()".to_string(),
+          Op::PeekFront => "list.peek_front().map(|value| *value)".to_string(),
+          Op::PeekBack => "list.peek_back().map(|value| *value)".to_string(),
+          Op::Clear => "list.clear()".to_string(),
+        }
+      },
+      observe_code: $observe_code,
+    }
+  };
+}
+
+pub(crate) use deque_harness;
+
+// The same for a queue shaped like `ch6_unsafe_singly_linked::List`: `push`
+// at the back, `pop` and `peek` at the front, `peek_back`, and `iter` from
+// the front.
+macro_rules! queue_harness {
+  ($name:expr, $new:expr, $new_code:expr) => {
+    $crate::model::Harness {
+      name: $name,
+      new: $new,
+      ops: &[
+        $crate::model::OpKind::PushBack, $crate::model::OpKind::PopFront,
+        $crate::model::OpKind::PeekFront, $crate::model::OpKind::PeekBack,
+      ],
+      apply: |list, op| {
+        use $crate::model::{peeked, Op};
+        match op {
+          Op::PushBack(value) => {
+            list.push(value);
+            None
+          }
+          Op::PopFront => list.pop(),
+          Op::PeekFront => peeked(list.peek()),
+          Op::PeekBack => peeked(list.peek_back()),
+          op => unreachable!("{:?} isn't generated", op),
+        }
+      },
+      observe: |list| list.iter().copied().collect(),
+      expect: |model| model.iter().copied().collect::<Vec<_>>(),
+      new_code: $new_code,
+      op_code: |op| {
+        use $crate::model::Op;
+        match op {
+          Op::PushBack(value) => format!("list.push({})", value),
+          Op::PopFront => "list.pop()".to_string(),
+          Op::PeekFront => "list.peek().copied()".to_string(),
+          Op::PeekBack => "list.peek_back().copied()".to_string(),
+          op => unreachable!("{:?} isn't generated", op),
+        }
+      },
+      observe_code: "list.iter().copied().collect::<Vec<_>>()",
+    }
+  };
+}
+
+pub(crate) use queue_harness;
+
+// What a peek saw, whether it lends out a `&i32` or a `Ref<i32>`.
+pub fn peeked(value: Option<impl Deref<Target = i32>>) -> Option<i32> {
+  value.map(|value| *value)
+}
+
+pub struct Harness<L, V> {
+  // Names the generated test.
+  pub name: &'static str,
+  pub new: fn() -> L,
+  // Which operations to generate.
+  pub ops: &'static [OpKind],
+  pub apply: fn(&mut L, Op) -> Option<i32>,
+  // What the list looks like after each step, and what it should look like
+  // given the oracle's contents.
+  pub observe: fn(&mut L) -> V,
+  pub expect: fn(&VecDeque<i32>) -> V,
+  // The same three as Rust source, for the generated test: creating `list`,
+  // calling `op` on it, and observing it.
+  pub new_code: &'static str,
+  pub op_code: fn(Op) -> String,
+  pub observe_code: &'static str,
+}
+
+struct Failure {
+  step: usize,
+  message: String,
+}
+
+pub struct Report {
+  pub seed: u64,
+  pub ops: Vec<Op>,
+  pub message: String,
+  pub code: String,
+}
+
+impl fmt::Display for Report {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "model check failed for seed {}: {}", self.seed, self.message)?;
+    writeln!(f, "minimal reproduction:")?;
+    write!(f, "{}", self.code)
+  }
+}
+
+impl<L, V: PartialEq + Debug> Harness<L, V> {
+  // Panics with a `Report` if any of `cases` sequences of up to `len`
+  // operations makes the list and the oracle disagree.
+  pub fn assert(&self, cases: u64, len: usize) {
+    if let Err(report) = self.check(cases, len) {
+      panic!("{}", report);
+    }
+  }
+
+  pub fn check(&self, cases: u64, len: usize) -> Result<(), Report> {
+    for seed in 0..cases {
+      let ops = self.generate(seed, len);
+
+      if let Err(failure) = self.run(&ops) {
+        let ops = self.shrink(ops[..=failure.step].to_vec());
+        let message = self.run(&ops).err().map_or(failure.message, |failure| failure.message);
+        let code = self.code(seed, &ops);
+        return Err(Report { seed, ops, message, code });
+      }
+    }
+
+    Ok(())
+  }
+
+  fn generate(&self, seed: u64, len: usize) -> Vec<Op> {
+    let mut rng = Rng::new(seed);
+    let len = rng.below(len + 1);
+    (0..len).map(|_| {
+      let kind = *rng.choose(self.ops);
+      Op::generate(kind, &mut rng)
+    }).collect()
+  }
+
+  fn run(&self, ops: &[Op]) -> Result<(), Failure> {
+    let mut model = VecDeque::new();
+    let mut step = 0;
+
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+      let mut list = (self.new)();
+
+      for (i, &op) in ops.iter().enumerate() {
+        step = i;
+
+        let expected = oracle(&mut model, op);
+        let actual = (self.apply)(&mut list, op);
+        if actual != expected {
+          let message = format!("{:?} returned {:?}, expected {:?}", op, actual, expected);
+          return Err(Failure { step, message });
+        }
+
+        let actual = (self.observe)(&mut list);
+        let expected = (self.expect)(&model);
+        if actual != expected {
+          let message = format!("after {:?} the list looks like {:?}, expected {:?}", op, actual, expected);
+          return Err(Failure { step, message });
+        }
+      }
+
+      Ok(())
+    }));
+
+    outcome.unwrap_or_else(|payload| {
+      let reason = payload.downcast_ref::<&str>().map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_default();
+      Err(Failure { step, message: format!("panicked: {}", reason) })
+    })
+  }
+
+  // Deletes chunks of operations, then simplifies pushed values, for as long
+  // as the sequence keeps failing.
+  fn shrink(&self, mut ops: Vec<Op>) -> Vec<Op> {
+    loop {
+      let mut progress = false;
+
+      let mut chunk = ops.len().div_ceil(2);
+      while chunk > 0 {
+        let mut start = 0;
+        while start + chunk <= ops.len() {
+          let candidate = [&ops[..start], &ops[start + chunk..]].concat();
+          match self.run(&candidate) {
+            Err(failure) => {
+              ops = candidate;
+              ops.truncate(failure.step + 1);
+              progress = true;
+            }
+            Ok(()) => start += chunk,
+          }
+        }
+        chunk /= 2;
+      }
+
+      for i in 0..ops.len() {
+        let value = match ops[i].value() {
+          Some(value) if value != 0 => value,
+          _ => continue,
+        };
+
+        for simpler in [0, value / 2, value - 1] {
+          let mut candidate = ops.clone();
+          candidate[i] = ops[i].with_value(simpler);
+          if self.run(&candidate).is_err() {
+            ops = candidate;
+            progress = true;
+            break;
+          }
+        }
+      }
+
+      if !progress {
+        return ops;
+      }
+    }
+  }
+
+  fn code(&self, seed: u64, ops: &[Op]) -> String {
+    let mut model = VecDeque::new();
+    let mut code = String::new();
+
+    writeln!(code, "#[test]").unwrap();
+    writeln!(code, "fn {}_seed_{}() {{", self.name, seed).unwrap();
+    writeln!(code, "  {}", self.new_code).unwrap();
+
+    for &op in ops {
+      let expected = oracle(&mut model, op);
+      if op.returns_value() {
+        writeln!(code, "  assert_eq!({}, {:?});", (self.op_code)(op), expected).unwrap();
+      } else {
+        writeln!(code, "  {};", (self.op_code)(op)).unwrap();
+      }
+    }
+
+    writeln!(code, "  assert_eq!({}, {:?});", self.observe_code, (self.expect)(&model)).unwrap();
+    writeln!(code, "}}").unwrap();
+    code
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Harness, Op, OpKind};
+  use std::collections::VecDeque;
+
+  const ALL: &[OpKind] = &[
+    OpKind::PushFront, OpKind::PushBack, OpKind::PopFront, OpKind::PopBack,
+    OpKind::PeekFront, OpKind::PeekBack, OpKind::Clear,
+  ];
+
+  fn apply(list: &mut VecDeque<i32>, op: Op) -> Option<i32> {
+    match op {
+      Op::PushFront(value) => {
+        list.push_front(value);
+        None
+      }
+      Op::PushBack(value) => {
+        list.push_back(value);
+        None
+      }
+      Op::PopFront => list.pop_front(),
+      // Gets it wrong with exactly three elements.
+      Op::PopBack if list.len() == 3 => list.pop_front(),
+      Op::PopBack => list.pop_back(),
+      Op::PeekFront => list.front().copied(),
+      Op::PeekBack => list.back().copied(),
+      Op::Clear => {
+        list.clear();
+        None
+      }
+    }
+  }
+
+  fn harness(apply: fn(&mut VecDeque<i32>, Op) -> Option<i32>) -> Harness<VecDeque<i32>, Vec<i32>> {
+    Harness {
+      name: "buggy",
+      new: VecDeque::new,
+      ops: ALL,
+      apply,
+      observe: |list| list.iter().copied().collect(),
+      expect: |model| model.iter().copied().collect(),
+      new_code: "let mut list = VecDeque::new();",
+      op_code: |op| match op {
+        Op::PushFront(value) => format!("list.push_front({})", value),
+        Op::PushBack(value) => format!("list.push_back({})", value),
+        Op::PopFront => "list.pop_front()".to_string(),
+        Op::PopBack => "list.pop_back()".to_string(),
+        Op::PeekFront => "list.front().copied()".to_string(),
+        Op::PeekBack => "list.back().copied()".to_string(),
+        Op::Clear => "list.clear()".to_string(),
+      },
+      observe_code: "list.iter().copied().collect::<Vec<_>>()",
+    }
+  }
+
+  #[test]
+  fn shrinks_to_minimal_sequence() {
+    let report = match harness(apply).check(100, 50) {
+      Err(report) => report,
+      Ok(()) => panic!("the bug went unnoticed"),
+    };
+
+    // Three pushes with a different value at each end, then the `pop_back`.
+    assert_eq!(report.ops.len(), 4, "{}", report);
+    assert_eq!(report.ops[3], Op::PopBack);
+    assert!(report.ops[..3].iter().all(|op| matches!(op, Op::PushFront(_) | Op::PushBack(_))));
+    assert!(report.message.contains("PopBack"), "{}", report);
+
+    assert!(report.code.starts_with(&format!("#[test]\nfn buggy_seed_{}() {{\n", report.seed)));
+    assert!(report.code.contains("  let mut list = VecDeque::new();\n"));
+    assert!(report.code.contains("  assert_eq!(list.pop_back(), Some("), "{}", report);
+    assert!(report.code.ends_with("}\n"));
+  }
+
+  #[test]
+  fn reports_panics() {
+    let panicky: fn(&mut VecDeque<i32>, Op) -> Option<i32> = |list, op| {
+      if op == Op::Clear && list.len() > 1 {
+        panic!("clear is broken");
+      }
+      apply(list, op)
+    };
+
+    let mut harness = harness(panicky);
+    harness.ops = &[OpKind::PushBack, OpKind::Clear];
+    let report = harness.check(100, 50).err().unwrap();
+
+    assert_eq!(report.ops, [Op::PushBack(0), Op::PushBack(0), Op::Clear]);
+    assert!(report.message.contains("clear is broken"));
+  }
+
+  #[test]
+  fn correct_lists_pass() {
+    let mut harness = harness(|list, op| match op {
+      Op::PopBack => list.pop_back(),
+      op => apply(list, op),
+    });
+    harness.name = "vec_deque";
+    assert!(harness.check(200, 50).is_ok());
+  }
+}
@@ -0,0 +1,281 @@
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use crate::drop_guard;
+use crate::hazard::{self, HazardPointer};
+use crate::traits::Queue;
+
+// Michael–Scott lock-free queue: the same head/tail layout as
+// `ch6_unsafe_singly_linked`, but both ends are `AtomicPtr`s so any number of
+// threads can push and pop through a shared reference.
+//
+// `head` always points at a stub node whose element has already been taken
+// (or never existed); the front of the queue is the node after it. Popping
+// moves `head` forward and retires the old stub through `hazard`, so a
+// thread still looking at it never sees it freed.
+
+pub struct MsQueue<T> {
+  head: AtomicPtr<Node<T>>,
+  tail: AtomicPtr<Node<T>>,
+}
+
+struct Node<T> {
+  elem: MaybeUninit<T>,
+  next: AtomicPtr<Node<T>>,
+}
+
+impl<T> Node<T> {
+  fn alloc(elem: MaybeUninit<T>) -> *mut Node<T> {
+    Box::into_raw(Box::new(Node { elem, next: AtomicPtr::new(ptr::null_mut()) }))
+  }
+}
+
+unsafe impl<T: Send> Send for MsQueue<T> {}
+unsafe impl<T: Send> Sync for MsQueue<T> {}
+
+impl<T> MsQueue<T> {
+  pub fn new() -> Self {
+    let stub = Node::alloc(MaybeUninit::uninit());
+    MsQueue { head: AtomicPtr::new(stub), tail: AtomicPtr::new(stub) }
+  }
+
+  pub fn push(&self, elem: T) {
+    let new_tail = Node::alloc(MaybeUninit::new(elem));
+    let hazard = HazardPointer::new();
+
+    loop {
+      let tail = hazard.protect(&self.tail);
+      let next = unsafe { (*tail).next.load(Ordering::SeqCst) };
+
+      if !next.is_null() {
+        // Someone else linked a node but hasn't swung `tail` yet: help them.
+        let _ = self.tail.compare_exchange(tail, next, Ordering::SeqCst, Ordering::SeqCst);
+        continue;
+      }
+
+      let linked = unsafe {
+        (*tail).next.compare_exchange(ptr::null_mut(), new_tail, Ordering::SeqCst, Ordering::SeqCst)
+      };
+
+      if linked.is_ok() {
+        let _ = self.tail.compare_exchange(tail, new_tail, Ordering::SeqCst, Ordering::SeqCst);
+        return;
+      }
+    }
+  }
+
+  pub fn pop(&self) -> Option<T> {
+    let head_hazard = HazardPointer::new();
+    let next_hazard = HazardPointer::new();
+
+    loop {
+      let head = head_hazard.protect(&self.head);
+      let next = unsafe { (*head).next.load(Ordering::SeqCst) };
+
+      // `next` can't have been retired while `head` still points at it.
+      next_hazard.set(next);
+      if self.head.load(Ordering::SeqCst) != head {
+        continue;
+      }
+
+      if next.is_null() {
+        return None;
+      }
+
+      let tail = self.tail.load(Ordering::SeqCst);
+      if head == tail {
+        // `tail` is lagging behind; move it before `head` passes it.
+        let _ = self.tail.compare_exchange(tail, next, Ordering::SeqCst, Ordering::SeqCst);
+        continue;
+      }
+
+      if self.head.compare_exchange(head, next, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+        // Only the thread that moved `head` gets to take the element out of
+        // the new stub.
+        let elem = unsafe { ptr::read((*next).elem.as_ptr()) };
+
+        head_hazard.reset();
+        unsafe {
+          hazard::retire(head);
+        }
+
+        return Some(elem);
+      }
+    }
+  }
+
+  // Only a snapshot: other threads may change it right away.
+  pub fn is_empty(&self) -> bool {
+    let hazard = HazardPointer::new();
+    let head = hazard.protect(&self.head);
+    unsafe { (*head).next.load(Ordering::SeqCst).is_null() }
+  }
+}
+
+impl<T> Default for MsQueue<T> {
+  fn default() -> Self {
+    MsQueue::new()
+  }
+}
+
+impl<T> Drop for MsQueue<T> {
+  fn drop(&mut self) {
+    // Nobody else can hold a reference anymore, so no need for hazards.
+    let stub = unsafe { Box::from_raw(*self.head.get_mut()) };
+    let mut cur = stub.next.load(Ordering::Relaxed);
+
+    drop_guard::drain(&mut cur, |cur| {
+      if cur.is_null() {
+        return false;
+      }
+
+      unsafe {
+        let mut node = Box::from_raw(*cur);
+        *cur = *node.next.get_mut();
+        node.elem.assume_init_drop();
+      }
+      true
+    });
+  }
+}
+
+impl<T> Queue<T> for MsQueue<T> {
+  fn enqueue(&mut self, elem: T) {
+    self.push(elem);
+  }
+
+  fn dequeue(&mut self) -> Option<T> {
+    self.pop()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::MsQueue;
+  use crate::traits::conformance;
+  use std::collections::HashSet;
+  use std::rc::Rc;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::sync::Arc;
+  use std::thread;
+
+  #[test]
+  fn basics() {
+    let queue = MsQueue::new();
+
+    assert!(queue.is_empty());
+    assert_eq!(queue.pop(), None);
+
+    queue.push(1);
+    queue.push(2);
+    queue.push(3);
+
+    assert!(!queue.is_empty());
+    assert_eq!(queue.pop(), Some(1));
+    assert_eq!(queue.pop(), Some(2));
+
+    queue.push(4);
+
+    assert_eq!(queue.pop(), Some(3));
+    assert_eq!(queue.pop(), Some(4));
+    assert_eq!(queue.pop(), None);
+    assert!(queue.is_empty());
+  }
+
+  #[test]
+  fn drops_remaining_elements() {
+    let elem = Rc::new(());
+
+    let queue = MsQueue::new();
+    for _ in 0..10 {
+      queue.push(elem.clone());
+    }
+    queue.pop();
+    assert_eq!(Rc::strong_count(&elem), 10);
+
+    drop(queue);
+    assert_eq!(Rc::strong_count(&elem), 1);
+  }
+
+  #[test]
+  fn mpmc() {
+    const PRODUCERS: usize = 4;
+    const CONSUMERS: usize = 4;
+    const PER_PRODUCER: usize = 20_000;
+
+    let queue = MsQueue::new();
+    let popped = AtomicUsize::new(0);
+
+    let received: Vec<Vec<(usize, usize)>> = thread::scope(|s| {
+      for p in 0..PRODUCERS {
+        let queue = &queue;
+        s.spawn(move || {
+          for i in 0..PER_PRODUCER {
+            queue.push((p, i));
+          }
+        });
+      }
+
+      let consumers: Vec<_> = (0..CONSUMERS).map(|_| {
+        s.spawn(|| {
+          let mut received = Vec::new();
+          while popped.load(Ordering::SeqCst) < PRODUCERS * PER_PRODUCER {
+            if let Some(elem) = queue.pop() {
+              popped.fetch_add(1, Ordering::SeqCst);
+              received.push(elem);
+            }
+          }
+          received
+        })
+      }).collect();
+
+      consumers.into_iter().map(|consumer| consumer.join().unwrap()).collect()
+    });
+
+    assert!(queue.is_empty());
+
+    let mut seen = HashSet::new();
+    for received in &received {
+      // Each consumer sees every producer's elements in the order they were
+      // pushed, even if it only gets some of them.
+      let mut last = [None; PRODUCERS];
+      for &(p, i) in received {
+        assert!(last[p].is_none_or(|last| last < i));
+        last[p] = Some(i);
+        assert!(seen.insert((p, i)), "{:?} popped twice", (p, i));
+      }
+    }
+    assert_eq!(seen.len(), PRODUCERS * PER_PRODUCER);
+  }
+
+  #[test]
+  fn shared_elements_are_dropped_once() {
+    let elem = Arc::new(());
+    let queue = MsQueue::new();
+
+    thread::scope(|s| {
+      for _ in 0..4 {
+        s.spawn(|| {
+          for _ in 0..5_000 {
+            queue.push(elem.clone());
+            queue.pop();
+          }
+          for _ in 0..100 {
+            queue.push(elem.clone());
+          }
+        });
+      }
+    });
+
+    assert_eq!(Arc::strong_count(&elem), 401);
+    drop(queue);
+    assert_eq!(Arc::strong_count(&elem), 1);
+  }
+
+  #[test]
+  fn conformance() {
+    conformance::queue(MsQueue::new);
+    conformance::queue_drops(MsQueue::new);
+  }
+}
@@ -0,0 +1,72 @@
+// Change notifications for `unsafe_deque::List`, `arena_list::List` and
+// `ch5_mutable_deque_without_refs::List`.
+//
+// Lists are generic over their observer and default to `NoObserver`, whose
+// `notify` is empty, so a list nobody watches compiles to the same code as
+// before. Any `FnMut(Event<T>)` closure can be used as an observer.
+
+// Pushes and mutations borrow the element involved, which is enough to mirror
+// the list's contents on the other side.
+#[derive(Debug, PartialEq)]
+pub enum Event<'a, T> {
+  PushedFront(&'a T),
+  PushedBack(&'a T),
+  PoppedFront,
+  PoppedBack,
+  Cleared,
+  Mutated(usize, &'a T),
+}
+
+pub trait Observer<T> {
+  // Lets lists skip the work of building events nobody listens to.
+  const ENABLED: bool = true;
+
+  fn notify(&mut self, event: Event<'_, T>);
+}
+
+pub struct NoObserver;
+
+impl<T> Observer<T> for NoObserver {
+  const ENABLED: bool = false;
+
+  #[inline(always)]
+  fn notify(&mut self, _event: Event<'_, T>) {}
+}
+
+impl<T, F: FnMut(Event<'_, T>)> Observer<T> for F {
+  fn notify(&mut self, event: Event<'_, T>) {
+    self(event)
+  }
+}
+
+// Observer keeping a `VecDeque` in sync with the observed list.
+#[cfg(test)]
+pub(crate) struct Mirror<T> {
+  pub elems: std::collections::VecDeque<T>,
+}
+
+#[cfg(test)]
+impl<T> Mirror<T> {
+  pub fn new() -> Self {
+    Mirror { elems: std::collections::VecDeque::new() }
+  }
+}
+
+#[cfg(test)]
+impl<T: Clone> Observer<T> for Mirror<T> {
+  fn notify(&mut self, event: Event<'_, T>) {
+    let elems = &mut self.elems;
+    match event {
+      Event::PushedFront(elem) => elems.push_front(elem.clone()),
+      Event::PushedBack(elem) => elems.push_back(elem.clone()),
+      Event::PoppedFront => {
+        elems.pop_front();
+      }
+      Event::PoppedBack => {
+        elems.pop_back();
+      }
+      Event::Cleared => elems.clear(),
+      Event::Mutated(index, elem) => elems[index] = elem.clone(),
+    }
+  }
+}
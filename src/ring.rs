@@ -0,0 +1,461 @@
+use alloc::boxed::Box;
+use core::marker::PhantomData;
+use core::ptr;
+use crate::drop_guard;
+use crate::traits::{Queue, Stack};
+
+// Circular singly-linked list, for round-robin scheduling and the like.
+//
+// Like `ch6_unsafe_singly_linked::List` it keeps a raw pointer to the last
+// node, but here the last node's `next` is the first node, so that one pointer
+// reaches both ends. Moving the front element to the back is just moving
+// `tail` forward, with nothing relinked.
+
+pub struct Ring<T> {
+  tail: *mut Node<T>,
+  len: usize,
+  // The ring owns its nodes, even if only through raw pointers.
+  _owns: PhantomData<Box<Node<T>>>,
+}
+
+struct Node<T> {
+  elem: T,
+  next: *mut Node<T>,
+}
+
+// Nodes are only reachable through the ring.
+unsafe impl<T: Send> Send for Ring<T> {}
+unsafe impl<T: Sync> Sync for Ring<T> {}
+
+impl<T> Ring<T> {
+  pub fn new() -> Self {
+    Ring { tail: ptr::null_mut(), len: 0, _owns: PhantomData }
+  }
+
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  pub fn push_front(&mut self, elem: T) {
+    self.link_after_tail(elem);
+  }
+
+  pub fn push_back(&mut self, elem: T) {
+    // The new front, with the tail moved onto it.
+    self.tail = self.link_after_tail(elem);
+  }
+
+  pub fn pop_front(&mut self) -> Option<T> {
+    if self.tail.is_null() {
+      return None;
+    }
+
+    unsafe {
+      let head = (*self.tail).next;
+      if head == self.tail {
+        self.tail = ptr::null_mut();
+      } else {
+        (*self.tail).next = (*head).next;
+      }
+      self.len -= 1;
+      Some(Box::from_raw(head).elem)
+    }
+  }
+
+  pub fn peek_front(&self) -> Option<&T> {
+    unsafe { self.tail.as_ref().map(|tail| &(*tail.next).elem) }
+  }
+
+  pub fn peek_front_mut(&mut self) -> Option<&mut T> {
+    unsafe { self.tail.as_mut().map(|tail| &mut (*tail.next).elem) }
+  }
+
+  pub fn peek_back(&self) -> Option<&T> {
+    unsafe { self.tail.as_ref().map(|tail| &tail.elem) }
+  }
+
+  pub fn peek_back_mut(&mut self) -> Option<&mut T> {
+    unsafe { self.tail.as_mut().map(|tail| &mut tail.elem) }
+  }
+
+  // Moves the front element to the back and returns the new front. O(1).
+  pub fn advance(&mut self) -> Option<&mut T> {
+    unsafe {
+      let tail = self.tail.as_mut()?;
+      self.tail = tail.next;
+      Some(&mut (*(*self.tail).next).elem)
+    }
+  }
+
+  // Moves the first `n` elements to the back, like `VecDeque::rotate_left`
+  // but for any `n`: it wraps around, taking O(n mod len) steps.
+  pub fn rotate(&mut self, n: usize) {
+    if self.len == 0 {
+      return;
+    }
+    for _ in 0..n % self.len {
+      self.tail = unsafe { (*self.tail).next };
+    }
+  }
+
+  // Front to back, once.
+  pub fn iter(&self) -> Iter<'_, T> {
+    Iter {
+      next: unsafe { self.tail.as_ref().map_or(ptr::null(), |tail| tail.next) },
+      len: self.len,
+      _ring: PhantomData,
+    }
+  }
+
+  // Front to back, and around again, forever. Empty only for an empty ring.
+  pub fn cycle_iter(&self) -> CycleIter<'_, T> {
+    CycleIter {
+      next: unsafe { self.tail.as_ref().map_or(ptr::null(), |tail| tail.next) },
+      _ring: PhantomData,
+    }
+  }
+
+  #[allow(clippy::should_implement_trait)]
+  pub fn into_iter(self) -> IntoIter<T> {
+    IntoIter(self)
+  }
+
+  // Links a new node in between the tail and the front, making it the
+  // front, and returns it.
+  fn link_after_tail(&mut self, elem: T) -> *mut Node<T> {
+    let node = Box::into_raw(Box::new(Node { elem, next: ptr::null_mut() }));
+    unsafe {
+      if self.tail.is_null() {
+        (*node).next = node;
+        self.tail = node;
+      } else {
+        (*node).next = (*self.tail).next;
+        (*self.tail).next = node;
+      }
+    }
+    self.len += 1;
+    node
+  }
+}
+
+impl<T> Default for Ring<T> {
+  fn default() -> Self {
+    Ring::new()
+  }
+}
+
+impl<T> Drop for Ring<T> {
+  fn drop(&mut self) {
+    drop_guard::drain(self, |ring| ring.pop_front().is_some());
+  }
+}
+
+pub struct IntoIter<T>(Ring<T>);
+
+impl<T> Iterator for IntoIter<T> {
+  type Item = T;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.0.pop_front()
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.0.len, Some(self.0.len))
+  }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+pub struct Iter<'a, T> {
+  next: *const Node<T>,
+  len: usize,
+  _ring: PhantomData<&'a T>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+  type Item = &'a T;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.len == 0 {
+      return None;
+    }
+
+    self.len -= 1;
+    unsafe {
+      let node = &*self.next;
+      self.next = node.next;
+      Some(&node.elem)
+    }
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.len, Some(self.len))
+  }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+pub struct CycleIter<'a, T> {
+  // Null for an empty ring.
+  next: *const Node<T>,
+  _ring: PhantomData<&'a T>,
+}
+
+impl<'a, T> Iterator for CycleIter<'a, T> {
+  type Item = &'a T;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    unsafe {
+      let node = self.next.as_ref()?;
+      self.next = node.next;
+      Some(&node.elem)
+    }
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    if self.next.is_null() {
+      (0, Some(0))
+    } else {
+      (usize::MAX, None)
+    }
+  }
+}
+
+impl<T> Stack<T> for Ring<T> {
+  fn push(&mut self, elem: T) {
+    self.push_front(elem);
+  }
+
+  fn pop(&mut self) -> Option<T> {
+    self.pop_front()
+  }
+}
+
+impl<T> Queue<T> for Ring<T> {
+  fn enqueue(&mut self, elem: T) {
+    self.push_back(elem);
+  }
+
+  fn dequeue(&mut self) -> Option<T> {
+    self.pop_front()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::Ring;
+  use crate::rng::Rng;
+  use crate::test_support::{assert_panic_safe, measure, DropCounter, Profile};
+  use crate::traits::conformance;
+  use std::collections::VecDeque;
+
+  fn elems(ring: &Ring<i32>) -> Vec<i32> {
+    ring.iter().copied().collect()
+  }
+
+  #[test]
+  fn basics() {
+    let mut ring = Ring::new();
+    assert_eq!(ring.pop_front(), None);
+    assert!(ring.is_empty());
+
+    ring.push_back(2);
+    ring.push_front(1);
+    ring.push_back(3);
+    assert_eq!(ring.len(), 3);
+    assert_eq!(elems(&ring), [1, 2, 3]);
+    assert_eq!(ring.peek_front(), Some(&1));
+    assert_eq!(ring.peek_back(), Some(&3));
+
+    *ring.peek_front_mut().unwrap() = 10;
+    *ring.peek_back_mut().unwrap() = 30;
+    assert_eq!(ring.pop_front(), Some(10));
+    assert_eq!(ring.pop_front(), Some(2));
+    assert_eq!(ring.peek_front(), Some(&30));
+    assert_eq!(ring.peek_back(), Some(&30));
+    assert_eq!(ring.pop_front(), Some(30));
+    assert_eq!(ring.pop_front(), None);
+    assert_eq!(ring.peek_front(), None);
+    assert_eq!(ring.peek_back_mut(), None);
+
+    ring.push_front(4);
+    assert_eq!(ring.into_iter().collect::<Vec<_>>(), [4]);
+  }
+
+  #[test]
+  fn conformance() {
+    conformance::queue(Ring::new);
+    conformance::stack(Ring::new);
+    conformance::queue_allocations(Ring::new);
+    conformance::stack_allocations(Ring::new);
+    conformance::queue_drops(Ring::new);
+  }
+
+  #[test]
+  fn rotate_empty() {
+    let mut ring = Ring::<i32>::new();
+    ring.rotate(0);
+    ring.rotate(5);
+    assert_eq!(ring.advance(), None);
+    assert!(ring.is_empty());
+    assert_eq!(ring.iter().next(), None);
+    assert_eq!(ring.cycle_iter().next(), None);
+  }
+
+  #[test]
+  fn rotate_one() {
+    let mut ring = Ring::new();
+    ring.push_back(7);
+
+    for n in [0, 1, 2, usize::MAX] {
+      ring.rotate(n);
+      assert_eq!(elems(&ring), [7]);
+    }
+    assert_eq!(ring.advance(), Some(&mut 7));
+    assert_eq!(ring.advance(), Some(&mut 7));
+    assert_eq!((ring.peek_front(), ring.peek_back()), (Some(&7), Some(&7)));
+    assert_eq!(ring.cycle_iter().take(3).collect::<Vec<_>>(), [&7, &7, &7]);
+  }
+
+  #[test]
+  fn rotate_two() {
+    let mut ring = Ring::new();
+    ring.push_back(1);
+    ring.push_back(2);
+
+    ring.rotate(1);
+    assert_eq!(elems(&ring), [2, 1]);
+    ring.rotate(2);
+    assert_eq!(elems(&ring), [2, 1]);
+    ring.rotate(3);
+    assert_eq!(elems(&ring), [1, 2]);
+
+    assert_eq!(ring.advance(), Some(&mut 2));
+    assert_eq!((ring.peek_front(), ring.peek_back()), (Some(&2), Some(&1)));
+    assert_eq!(ring.advance(), Some(&mut 1));
+
+    // Pushing and popping still go to the right ends after rotating.
+    ring.rotate(1);
+    ring.push_back(3);
+    ring.push_front(0);
+    assert_eq!(elems(&ring), [0, 2, 1, 3]);
+    assert_eq!(ring.pop_front(), Some(0));
+    assert_eq!(ring.pop_front(), Some(2));
+  }
+
+  #[test]
+  fn cycle_iter() {
+    let mut ring = Ring::new();
+    for i in 0..3 {
+      ring.push_back(i);
+    }
+    ring.advance();
+
+    assert!(ring.cycle_iter().take(7).copied().eq([1, 2, 0, 1, 2, 0, 1]));
+    assert_eq!(ring.cycle_iter().size_hint(), (usize::MAX, None));
+    assert_eq!(ring.iter().len(), 3);
+  }
+
+  #[test]
+  fn round_robin() {
+    // Each task runs for one slice and goes to the back until it's done.
+    let mut tasks = Ring::new();
+    for (name, slices) in [('a', 3), ('b', 1), ('c', 2)] {
+      tasks.push_back((name, slices));
+    }
+
+    let mut order = String::new();
+    while let Some((name, left)) = tasks.peek_front_mut() {
+      order.push(*name);
+      *left -= 1;
+      if *left == 0 {
+        tasks.pop_front();
+      } else {
+        tasks.advance();
+      }
+    }
+    assert_eq!(order, "abcaca");
+  }
+
+  #[test]
+  fn against_brute_force() {
+    let mut rng = Rng::new(49);
+    let mut ring = Ring::new();
+    let mut model = VecDeque::new();
+
+    for _ in 0..10_000 {
+      let value = rng.next_u64() as i32;
+      match rng.below(5) {
+        0 => {
+          ring.push_front(value);
+          model.push_front(value);
+        }
+        1 => {
+          ring.push_back(value);
+          model.push_back(value);
+        }
+        2 => assert_eq!(ring.pop_front(), model.pop_front()),
+        3 => {
+          let n = rng.below(10);
+          ring.rotate(n);
+          if !model.is_empty() {
+            let len = model.len();
+            model.rotate_left(n % len);
+          }
+        }
+        _ => {
+          let front = ring.advance().copied();
+          if let Some(elem) = model.pop_front() {
+            model.push_back(elem);
+          }
+          assert_eq!(front, model.front().copied());
+        }
+      }
+
+      assert_eq!(ring.len(), model.len());
+      assert_eq!(ring.peek_back(), model.back());
+    }
+
+    assert!(ring.iter().eq(model.iter()));
+  }
+
+  #[test]
+  fn allocations() {
+    let mut ring = Ring::new();
+    ring.push_back(1);
+    ring.push_front(0);
+
+    // Rotating is just moving the tail.
+    let ((), profile) = measure(|| {
+      ring.rotate(3);
+      ring.advance();
+      assert_eq!(ring.iter().count(), 2);
+      assert_eq!(ring.cycle_iter().take(5).count(), 5);
+    });
+    assert_eq!(profile, Profile::default());
+  }
+
+  #[test]
+  fn panic_safe_drops() {
+    // `conformance::queue_drops` covers a ring that was never rotated. Here
+    // the walk round starts halfway through the pushed elements.
+    let fill = |counter: &DropCounter| {
+      let mut ring = Ring::new();
+      for _ in 0..5 {
+        ring.push_back(counter.elem());
+      }
+      ring.rotate(2);
+      ring
+    };
+
+    assert_panic_safe(5, fill, drop);
+    assert_panic_safe(5, fill, |ring| {
+      let mut iter = ring.into_iter();
+      iter.next();
+    });
+  }
+}
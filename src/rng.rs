@@ -0,0 +1,81 @@
+// Small deterministic pseudo-random number generator (SplitMix64), for the
+// randomized tests and for structures that need coin flips, like the skip
+// list. Same seed, same sequence, on every platform. Not for anything that
+// needs to be unpredictable.
+
+#[derive(Clone, Debug)]
+pub struct Rng {
+  state: u64,
+}
+
+impl Rng {
+  pub fn new(seed: u64) -> Self {
+    Rng { state: seed }
+  }
+
+  pub fn next_u64(&mut self) -> u64 {
+    self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+    let mut z = self.state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+  }
+
+  // Uniform in `0..n`, give or take a bias too small to matter here.
+  pub fn below(&mut self, n: usize) -> usize {
+    assert!(n > 0, "`below` needs a non-empty range");
+    ((self.next_u64() as u128 * n as u128) >> 64) as usize
+  }
+
+  // True with probability `numerator / denominator`.
+  pub fn chance(&mut self, numerator: usize, denominator: usize) -> bool {
+    self.below(denominator) < numerator
+  }
+
+  pub fn choose<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+    &items[self.below(items.len())]
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::Rng;
+
+  #[test]
+  fn deterministic() {
+    let first: Vec<_> = {
+      let mut rng = Rng::new(42);
+      (0..100).map(|_| rng.next_u64()).collect()
+    };
+    let mut rng = Rng::new(42);
+    assert!(first.iter().all(|&x| x == rng.next_u64()));
+
+    let mut other = Rng::new(43);
+    assert!(first.iter().any(|&x| x != other.next_u64()));
+  }
+
+  #[test]
+  fn below() {
+    let mut rng = Rng::new(0);
+    let mut counts = [0; 10];
+
+    for _ in 0..10_000 {
+      counts[rng.below(10)] += 1;
+    }
+
+    // Roughly uniform: each bucket within 20% of the expected 1000.
+    assert!(counts.iter().all(|&count| (800..1_200).contains(&count)), "{:?}", counts);
+  }
+
+  #[test]
+  fn chance_and_choose() {
+    let mut rng = Rng::new(7);
+
+    assert!((0..100).all(|_| rng.chance(1, 1)));
+    assert!((0..100).all(|_| !rng.chance(0, 1)));
+
+    let items = ['a', 'b', 'c'];
+    assert!((0..100).all(|_| items.contains(rng.choose(&items))));
+  }
+}
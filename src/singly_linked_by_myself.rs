@@ -1,3 +1,6 @@
+use alloc::boxed::Box;
+use crate::drop_guard;
+use crate::traits::Stack;
 
 pub struct List<T> {
   head: Link<T>,
@@ -63,10 +66,13 @@ impl<T> List<T> {
 
 impl<T> Drop for List<T> {
   fn drop(&mut self) {
-    let mut cur_node = self.head.take();
-    while let Some(mut node) = cur_node {
-      cur_node = node.next.take();
-    }
+    drop_guard::drain(&mut self.head, |head| match head.take() {
+      Some(mut node) => {
+        *head = node.next.take();
+        true
+      }
+      None => false,
+    });
   }
 }
 
@@ -111,9 +117,21 @@ impl<'a, T> Iterator for IterMut<'a, T> {
 }
   
 
+impl<T> Stack<T> for List<T> {
+  fn push(&mut self, elem: T) {
+    self.push(elem);
+  }
+
+  fn pop(&mut self) -> Option<T> {
+    self.pop()
+  }
+}
+
 #[cfg(test)]
 mod test {
   use super::List;
+  use crate::test_support::{assert_panic_safe, measure, DropCounter, Profile};
+  use crate::traits::conformance;
 
   #[test]
   fn basics() {
@@ -200,4 +218,54 @@ mod test {
     assert_eq!(iter.next(), Some(&mut 1));
     assert_eq!(iter.next(), None);
   }
+
+  #[test]
+  fn conformance() {
+    conformance::stack(List::new);
+    conformance::stack_allocations(List::new);
+    conformance::stack_drops(List::new);
+  }
+
+  #[test]
+  fn allocations() {
+    let mut list = List::new();
+    list.push(1);
+    list.push(2);
+    list.push(3);
+
+    // Looking around doesn't allocate.
+    let ((), profile) = measure(|| {
+      assert_eq!(list.peek(), Some(&3));
+      *list.peek_mut().unwrap() += 1;
+      assert_eq!(list.iter().count(), 3);
+      list.iter_mut().for_each(|elem| *elem *= 10);
+    });
+    assert_eq!(profile, Profile::default());
+
+    assert_eq!(list.pop(), Some(40));
+
+    let (mut iter, profile) = measure(|| list.into_iter());
+    assert_eq!(profile, Profile::default());
+    let (elem, profile) = measure(|| iter.next());
+    assert_eq!(elem, Some(20));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 1));
+    let ((), profile) = measure(|| drop(iter));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 1));
+  }
+
+  #[test]
+  fn panic_safe_drops() {
+    let fill = |counter: &DropCounter| {
+      let mut list = List::new();
+      for _ in 0..5 {
+        list.push(counter.elem());
+      }
+      list
+    };
+
+    assert_panic_safe(5, fill, |list| {
+      let mut iter = list.into_iter();
+      iter.next();
+    });
+  }
 }
@@ -0,0 +1,726 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::marker::PhantomData;
+use core::mem;
+use core::ops::{Bound, RangeBounds};
+use core::ptr;
+use crate::drop_guard;
+use crate::rng::Rng;
+
+// Sorted map and set on a probabilistic skip list.
+//
+// The bottom level is a doubly-linked list, so iteration works from both
+// ends. On top of it run express lanes, each skipping about half the nodes of
+// the one below, which makes searching O(log n) on average. Every level links
+// its nodes through raw pointers, so a node is owned by those alone: it's put
+// on the heap with `Box::into_raw` when it's inserted and only turned back
+// into a `Box` to free it. A `Box` moving into its place on the bottom level
+// would invalidate the lane pointers already taken to it.
+//
+// How many lanes a node is on is a coin flip per lane, made with the crate's
+// `Rng`: the same seed builds the same shape.
+
+// Plenty for anything that fits in memory: with half the nodes on each lane,
+// the 24th holds one in eight million.
+const MAX_HEIGHT: usize = 24;
+
+const DEFAULT_SEED: u64 = 0x5eed;
+
+pub struct SkipMap<K, V> {
+  head: *mut Node<K, V>,
+  tail: *mut Node<K, V>,
+  // The first node on each express lane, lowest first. Empty lanes at the
+  // top get dropped.
+  lanes: Vec<*mut Node<K, V>>,
+  len: usize,
+  rng: Rng,
+}
+
+struct Node<K, V> {
+  key: K,
+  value: V,
+  next: *mut Node<K, V>,
+  prev: *mut Node<K, V>,
+  // The next node on each express lane this one is on, lowest first.
+  lanes: Vec<*mut Node<K, V>>,
+}
+
+// The raw pointers only ever point into nodes the map owns.
+unsafe impl<K: Send, V: Send> Send for SkipMap<K, V> {}
+unsafe impl<K: Sync, V: Sync> Sync for SkipMap<K, V> {}
+
+// For each level, the last node before where the search stopped, or null for
+// the head.
+type Preds<K, V> = [*mut Node<K, V>; MAX_HEIGHT];
+
+impl<K, V> SkipMap<K, V> {
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  pub fn first(&self) -> Option<(&K, &V)> {
+    unsafe { self.head.as_ref().map(|node| (&node.key, &node.value)) }
+  }
+
+  pub fn last(&self) -> Option<(&K, &V)> {
+    unsafe { self.tail.as_ref().map(|node| (&node.key, &node.value)) }
+  }
+
+  pub fn iter(&self) -> Iter<'_, K, V> {
+    Iter { front: self.head, back: self.tail, _map: PhantomData }
+  }
+
+  // The first node that isn't `before` the target, or null if there's none.
+  fn first_not(&self, before: impl Fn(&K) -> bool) -> *mut Node<K, V> {
+    self.search(&mut [ptr::null_mut(); MAX_HEIGHT], before)
+  }
+
+  // Like `first_not`, but also leaves the node before it on each level in
+  // `preds`. Walks down from the top lane, staying in front of the first node
+  // that isn't `before` the target.
+  fn search(&self, preds: &mut Preds<K, V>, before: impl Fn(&K) -> bool) -> *mut Node<K, V> {
+    let mut pred: *mut Node<K, V> = ptr::null_mut();
+    unsafe {
+      for level in (0..=self.lanes.len()).rev() {
+        loop {
+          let succ = match (pred.is_null(), level) {
+            (true, 0) => self.head,
+            (true, _) => self.lanes[level - 1],
+            (false, 0) => (*pred).next,
+            (false, _) => (&(*pred).lanes)[level - 1],
+          };
+          if succ.is_null() || !before(&(*succ).key) {
+            break;
+          }
+          pred = succ;
+        }
+        preds[level] = pred;
+      }
+      if pred.is_null() { self.head } else { (*pred).next }
+    }
+  }
+
+  // The node before `node`, the last one if `node` is null.
+  unsafe fn before(&self, node: *mut Node<K, V>) -> *mut Node<K, V> {
+    if node.is_null() {
+      self.tail
+    } else {
+      (*node).prev
+    }
+  }
+
+  // How many lanes, counting the bottom level, the next node goes on.
+  fn random_height(&mut self) -> usize {
+    (self.rng.next_u64().trailing_ones() as usize + 1).min(MAX_HEIGHT)
+  }
+}
+
+impl<K: Ord, V> SkipMap<K, V> {
+  pub fn new() -> Self {
+    SkipMap::with_seed(DEFAULT_SEED)
+  }
+
+  pub fn with_seed(seed: u64) -> Self {
+    SkipMap { head: ptr::null_mut(), tail: ptr::null_mut(), lanes: Vec::new(), len: 0, rng: Rng::new(seed) }
+  }
+
+  pub fn get<Q>(&self, key: &Q) -> Option<&V>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let node = self.find(key);
+    unsafe { node.as_ref().map(|node| &node.value) }
+  }
+
+  pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let node = self.find(key);
+    unsafe { node.as_mut().map(|node| &mut node.value) }
+  }
+
+  pub fn contains_key<Q>(&self, key: &Q) -> bool
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    !self.find(key).is_null()
+  }
+
+  // Returns the value `key` had, if it was there already. The key itself
+  // isn't replaced then, like in `BTreeMap`.
+  pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+    let mut preds = [ptr::null_mut(); MAX_HEIGHT];
+
+    let found = self.search(&mut preds, |other| *other < key);
+
+    unsafe {
+      if !found.is_null() && (*found).key == key {
+        return Some(mem::replace(&mut (*found).value, value));
+      }
+
+      // Lanes the map doesn't have yet start at the head, which is what
+      // `preds` already says for them.
+      let height = self.random_height();
+      while self.lanes.len() < height - 1 {
+        self.lanes.push(ptr::null_mut());
+      }
+
+      let mut lanes = vec![ptr::null_mut(); height - 1];
+      for (level, &pred) in preds.iter().enumerate().take(height).skip(1) {
+        lanes[level - 1] = if pred.is_null() { self.lanes[level - 1] } else { (&(*pred).lanes)[level - 1] };
+      }
+
+      let prev = preds[0];
+      let next = if prev.is_null() { self.head } else { (*prev).next };
+      let node = Box::into_raw(Box::new(Node { key, value, next, prev, lanes }));
+
+      for (level, &pred) in preds.iter().enumerate().take(height).skip(1) {
+        let lane = if pred.is_null() { &mut self.lanes[level - 1] } else { &mut (&mut (*pred).lanes)[level - 1] };
+        *lane = node;
+      }
+      if prev.is_null() {
+        self.head = node;
+      } else {
+        (*prev).next = node;
+      }
+      if next.is_null() {
+        self.tail = node;
+      } else {
+        (*next).prev = node;
+      }
+    }
+
+    self.len += 1;
+    None
+  }
+
+  pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.remove_entry(key).map(|(_, value)| value)
+  }
+
+  pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let mut preds = [ptr::null_mut(); MAX_HEIGHT];
+
+    let found = self.search(&mut preds, |other| other.borrow() < key);
+
+    unsafe {
+      if found.is_null() || (*found).key.borrow() != key {
+        return None;
+      }
+
+      let found_lanes = &(*found).lanes;
+      for (level, &pred) in preds.iter().enumerate().take(found_lanes.len() + 1).skip(1) {
+        let lane = if pred.is_null() { &mut self.lanes[level - 1] } else { &mut (&mut (*pred).lanes)[level - 1] };
+        *lane = found_lanes[level - 1];
+      }
+      while self.lanes.last().is_some_and(|lane| lane.is_null()) {
+        self.lanes.pop();
+      }
+
+      let prev = preds[0];
+      let next = (*found).next;
+      if prev.is_null() {
+        self.head = next;
+      } else {
+        (*prev).next = next;
+      }
+      if next.is_null() {
+        self.tail = prev;
+      } else {
+        (*next).prev = prev;
+      }
+
+      self.len -= 1;
+      let Node { key, value, .. } = *Box::from_raw(found);
+      Some((key, value))
+    }
+  }
+
+  // The entries with keys in `range`, in order. A range that ends before it
+  // starts is just empty.
+  pub fn range<Q, R>(&self, range: R) -> Iter<'_, K, V>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+    R: RangeBounds<Q>,
+  {
+    let front = match range.start_bound() {
+      Bound::Included(start) => self.first_not(|key| key.borrow() < start),
+      Bound::Excluded(start) => self.first_not(|key| key.borrow() <= start),
+      Bound::Unbounded => self.head,
+    };
+    let back = unsafe {
+      match range.end_bound() {
+        Bound::Included(end) => self.before(self.first_not(|key| key.borrow() <= end)),
+        Bound::Excluded(end) => self.before(self.first_not(|key| key.borrow() < end)),
+        Bound::Unbounded => self.tail,
+      }
+    };
+
+    let empty = front.is_null() || back.is_null() || unsafe { (*front).key > (*back).key };
+    if empty {
+      Iter { front: ptr::null_mut(), back: ptr::null_mut(), _map: PhantomData }
+    } else {
+      Iter { front, back, _map: PhantomData }
+    }
+  }
+
+  // The node with `key`, or null.
+  fn find<Q>(&self, key: &Q) -> *mut Node<K, V>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    let node = self.first_not(|other| other.borrow() < key);
+    if !node.is_null() && unsafe { (*node).key.borrow() == key } {
+      node
+    } else {
+      ptr::null_mut()
+    }
+  }
+}
+
+impl<K: Ord, V> Default for SkipMap<K, V> {
+  fn default() -> Self {
+    SkipMap::new()
+  }
+}
+
+impl<K, V> Drop for SkipMap<K, V> {
+  fn drop(&mut self) {
+    self.tail = ptr::null_mut();
+    self.lanes.clear();
+    drop_guard::drain(&mut self.head, |head| unsafe {
+      if head.is_null() {
+        return false;
+      }
+      let node = Box::from_raw(*head);
+      *head = node.next;
+      true
+    });
+  }
+}
+
+// Walks the bottom level from both ends until they meet.
+pub struct Iter<'a, K, V> {
+  // Both null once they've met.
+  front: *mut Node<K, V>,
+  back: *mut Node<K, V>,
+  _map: PhantomData<&'a SkipMap<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+  type Item = (&'a K, &'a V);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    unsafe {
+      let node = self.front.as_ref()?;
+      if self.front == self.back {
+        self.front = ptr::null_mut();
+        self.back = ptr::null_mut();
+      } else {
+        self.front = node.next;
+      }
+      Some((&node.key, &node.value))
+    }
+  }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Iter<'a, K, V> {
+  fn next_back(&mut self) -> Option<Self::Item> {
+    unsafe {
+      let node = self.back.as_ref()?;
+      if self.front == self.back {
+        self.front = ptr::null_mut();
+        self.back = ptr::null_mut();
+      } else {
+        self.back = node.prev;
+      }
+      Some((&node.key, &node.value))
+    }
+  }
+}
+
+pub struct SkipSet<K> {
+  map: SkipMap<K, ()>,
+}
+
+impl<K: Ord> SkipSet<K> {
+  pub fn new() -> Self {
+    SkipSet { map: SkipMap::new() }
+  }
+
+  pub fn with_seed(seed: u64) -> Self {
+    SkipSet { map: SkipMap::with_seed(seed) }
+  }
+
+  pub fn len(&self) -> usize {
+    self.map.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.map.is_empty()
+  }
+
+  // Whether `key` is new.
+  pub fn insert(&mut self, key: K) -> bool {
+    self.map.insert(key, ()).is_none()
+  }
+
+  // Whether `key` was there.
+  pub fn remove<Q>(&mut self, key: &Q) -> bool
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.map.remove(key).is_some()
+  }
+
+  pub fn contains<Q>(&self, key: &Q) -> bool
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+  {
+    self.map.contains_key(key)
+  }
+
+  pub fn first(&self) -> Option<&K> {
+    self.map.first().map(|(key, _)| key)
+  }
+
+  pub fn last(&self) -> Option<&K> {
+    self.map.last().map(|(key, _)| key)
+  }
+
+  pub fn iter(&self) -> SetIter<'_, K> {
+    SetIter(self.map.iter())
+  }
+
+  pub fn range<Q, R>(&self, range: R) -> SetIter<'_, K>
+  where
+    K: Borrow<Q>,
+    Q: Ord + ?Sized,
+    R: RangeBounds<Q>,
+  {
+    SetIter(self.map.range(range))
+  }
+}
+
+impl<K: Ord> Default for SkipSet<K> {
+  fn default() -> Self {
+    SkipSet::new()
+  }
+}
+
+pub struct SetIter<'a, K>(Iter<'a, K, ()>);
+
+impl<'a, K> Iterator for SetIter<'a, K> {
+  type Item = &'a K;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.0.next().map(|(key, _)| key)
+  }
+}
+
+impl<'a, K> DoubleEndedIterator for SetIter<'a, K> {
+  fn next_back(&mut self) -> Option<Self::Item> {
+    self.0.next_back().map(|(key, _)| key)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{Node, SkipMap, SkipSet};
+  use crate::rng::Rng;
+  use crate::test_support::{assert_panic_safe, measure, DropCounter, Profile};
+  use std::collections::BTreeMap;
+  use std::ops::Bound;
+  use std::ptr;
+
+  // Checks the links: the bottom level is sorted with `prev` and `tail`
+  // agreeing, and every lane is a sorted subsequence of the bottom level
+  // whose nodes really are that tall. Returns how many nodes each lane has.
+  fn lanes<K: Ord, V>(map: &SkipMap<K, V>) -> Vec<usize> {
+    let mut bottom: Vec<*const Node<K, V>> = Vec::new();
+    let mut prev: *const Node<K, V> = ptr::null();
+    let mut cur = unsafe { map.head.as_ref() };
+    while let Some(node) = cur {
+      assert!(ptr::eq(node.prev, prev), "node {} has the wrong `prev`", bottom.len());
+      if let Some(&last) = bottom.last() {
+        assert!(unsafe { &(*last).key } < &node.key, "bottom level out of order at {}", bottom.len());
+      }
+      bottom.push(node);
+      prev = node;
+      cur = unsafe { node.next.as_ref() };
+    }
+    assert!(ptr::eq(map.tail, prev), "`tail` isn't the last node");
+    assert_eq!(bottom.len(), map.len());
+
+    let mut counts = Vec::new();
+    for level in 1..=map.lanes.len() {
+      let mut expected = bottom.iter().filter(|&&node| unsafe { (*node).lanes.len() >= level });
+      let mut node = map.lanes[level - 1];
+      let mut count = 0;
+      while !node.is_null() {
+        assert_eq!(Some(&(node as *const _)), expected.next(), "lane {} skips a node or goes backwards", level);
+        node = unsafe { (&(*node).lanes)[level - 1] };
+        count += 1;
+      }
+      assert_eq!(expected.next(), None, "lane {} stops early", level);
+      assert!(count > 0, "lane {} is empty", level);
+      counts.push(count);
+    }
+    counts
+  }
+
+  #[test]
+  fn basics() {
+    let mut map = SkipMap::new();
+    assert_eq!(map.get(&1), None);
+    assert_eq!(map.first(), None);
+    assert_eq!(map.last(), None);
+    assert_eq!(map.remove(&1), None);
+
+    assert_eq!(map.insert(3, 'c'), None);
+    assert_eq!(map.insert(1, 'a'), None);
+    assert_eq!(map.insert(2, 'b'), None);
+    assert_eq!(map.insert(2, 'B'), Some('b'));
+    assert_eq!(map.len(), 3);
+    lanes(&map);
+
+    assert_eq!(map.get(&2), Some(&'B'));
+    assert_eq!(map.get(&4), None);
+    assert!(map.contains_key(&3) && !map.contains_key(&0));
+    *map.get_mut(&1).unwrap() = 'A';
+    assert_eq!(map.first(), Some((&1, &'A')));
+    assert_eq!(map.last(), Some((&3, &'c')));
+
+    assert_eq!(map.remove(&3), Some('c'));
+    assert_eq!(map.remove(&3), None);
+    assert_eq!(map.remove_entry(&1), Some((1, 'A')));
+    assert_eq!(map.last(), Some((&2, &'B')));
+    assert_eq!(map.remove(&2), Some('B'));
+    assert!(map.is_empty());
+    assert_eq!(lanes(&map), []);
+  }
+
+  #[test]
+  fn borrowed_keys() {
+    let mut map = SkipMap::new();
+    for word in ["pear", "apple", "fig"] {
+      map.insert(word.to_string(), word.len());
+    }
+
+    assert_eq!(map.get("fig"), Some(&3));
+    assert!(map.range::<str, _>((Bound::Included("b"), Bound::Excluded("g"))).map(|(key, _)| key.as_str()).eq(["fig"]));
+    assert_eq!(map.remove("apple"), Some(5));
+    assert_eq!(map.first().map(|(key, _)| key.as_str()), Some("fig"));
+  }
+
+  #[test]
+  fn iter() {
+    let mut map = SkipMap::new();
+    assert_eq!(map.iter().next(), None);
+    assert_eq!(map.iter().next_back(), None);
+
+    for i in (0..10).rev() {
+      map.insert(i, i * 10);
+    }
+    assert!(map.iter().map(|(&key, _)| key).eq(0..10));
+    assert!(map.iter().rev().map(|(_, &value)| value).eq((0..10).rev().map(|i| i * 10)));
+
+    // From both ends until they meet, at every possible point.
+    for split in 0..=10 {
+      let mut iter = map.iter();
+      let front: Vec<_> = iter.by_ref().take(split).map(|(&key, _)| key).collect();
+      let back: Vec<_> = iter.rev().map(|(&key, _)| key).collect();
+      assert_eq!(front, (0..split).collect::<Vec<_>>());
+      assert_eq!(back, (split..10).rev().collect::<Vec<_>>());
+    }
+  }
+
+  #[test]
+  fn ranges() {
+    let mut map = SkipMap::new();
+    let mut model = BTreeMap::new();
+    for i in 0..50 {
+      map.insert(i * 2, i);
+      model.insert(i * 2, i);
+    }
+
+    let bound = |kind: usize, at: i32| match kind {
+      0 => Bound::Included(at),
+      1 => Bound::Excluded(at),
+      _ => Bound::Unbounded,
+    };
+
+    for start in -2..=102 {
+      for end in start..=102 {
+        for kinds in 0..9 {
+          let range = (bound(kinds / 3, start), bound(kinds % 3, end));
+          // `BTreeMap` panics on ranges that exclude both ends of one key.
+          if start == end && kinds == 4 {
+            assert_eq!(map.range(range).next(), None);
+            continue;
+          }
+          assert!(map.range(range).eq(model.range(range)), "{:?}", range);
+          assert!(map.range(range).rev().eq(model.range(range).rev()), "{:?}", range);
+        }
+      }
+    }
+
+    // Backwards ranges are empty rather than a panic.
+    assert_eq!(map.range((Bound::Included(10), Bound::Excluded(4))).next(), None);
+    assert_eq!(map.range(11..=11).next(), None);
+    assert!(map.range(..).eq(model.iter()));
+  }
+
+  #[test]
+  fn set() {
+    let mut set = SkipSet::new();
+    assert!(set.insert(5));
+    assert!(set.insert(1));
+    assert!(!set.insert(5));
+    assert!(set.insert(3));
+    assert_eq!(set.len(), 3);
+
+    assert!(set.contains(&3) && !set.contains(&2));
+    assert_eq!((set.first(), set.last()), (Some(&1), Some(&5)));
+    assert!(set.iter().eq(&[1, 3, 5]));
+    assert!(set.iter().rev().eq(&[5, 3, 1]));
+    assert!(set.range(2..).eq(&[3, 5]));
+
+    assert!(set.remove(&3));
+    assert!(!set.remove(&3));
+    assert!(set.iter().eq(&[1, 5]));
+  }
+
+  #[test]
+  fn against_brute_force() {
+    for seed in 0..20 {
+      let mut rng = Rng::new(seed);
+      let mut map = SkipMap::with_seed(seed);
+      let mut model = BTreeMap::new();
+
+      for step in 0..2_000 {
+        let key = rng.below(200) as u32;
+        match rng.below(4) {
+          0 | 1 => assert_eq!(map.insert(key, step), model.insert(key, step)),
+          2 => assert_eq!(map.remove(&key), model.remove(&key)),
+          _ => {
+            let end = key + rng.below(50) as u32;
+            assert!(map.range(key..end).eq(model.range(key..end)), "seed {} step {}", seed, step);
+          }
+        }
+
+        assert_eq!(map.get(&key), model.get(&key));
+        assert_eq!(map.len(), model.len());
+        if step % 100 == 0 {
+          lanes(&map);
+        }
+      }
+
+      lanes(&map);
+      assert!(map.iter().eq(model.iter()));
+      assert!(map.iter().rev().eq(model.iter().rev()));
+      assert_eq!(map.first(), model.first_key_value());
+      assert_eq!(map.last(), model.last_key_value());
+    }
+  }
+
+  #[test]
+  fn lanes_halve() {
+    let mut map = SkipMap::new();
+    for i in 0..10_000 {
+      map.insert(i, ());
+    }
+
+    let counts = lanes(&map);
+    assert!((10..=20).contains(&counts.len()), "{} lanes", counts.len());
+    // Each lane has roughly half the nodes of the one below.
+    let mut below = map.len();
+    for &count in counts.iter().take(6) {
+      assert!(count * 3 > below && count * 3 < below * 2, "{} nodes above {}", count, below);
+      below = count;
+    }
+
+    // Long enough that dropping recursively would blow the stack.
+    for i in 10_000..100_000 {
+      map.insert(i, ());
+    }
+    drop(map);
+  }
+
+  #[test]
+  fn same_seed_same_shape() {
+    let build = |seed| {
+      let mut map = SkipMap::with_seed(seed);
+      for i in 0..1_000 {
+        map.insert(i, ());
+      }
+      lanes(&map)
+    };
+
+    assert_eq!(build(1), build(1));
+    assert_ne!(build(1), build(2));
+  }
+
+  #[test]
+  fn allocations() {
+    let (mut map, profile) = measure(SkipMap::<u32, u32>::new);
+    assert_eq!(profile, Profile::default());
+
+    // A node, and a vector for its lanes if it's on any.
+    for i in 0..100 {
+      let (_, profile) = measure(|| map.insert(i, i));
+      let lanes = unsafe { (*map.find(&i)).lanes.len() };
+      let lane_allocs = usize::from(lanes > 0);
+      assert!(profile.allocations <= 1 + lane_allocs + 1, "{:?}", profile);
+      assert!(profile.allocations > lane_allocs, "{:?}", profile);
+    }
+
+    let ((), profile) = measure(|| {
+      assert_eq!(map.get(&50), Some(&50));
+      assert_eq!(map.insert(50, 0), Some(50));
+      assert_eq!(map.range(10..20).count(), 10);
+      assert_eq!(map.iter().rev().count(), 100);
+      assert!(map.first().is_some() && map.last().is_some());
+    });
+    assert_eq!(profile, Profile::default());
+
+    let (_, profile) = measure(|| map.remove(&0));
+    assert_eq!(profile.allocations, 0);
+    assert!(profile.deallocations >= 1);
+  }
+
+  #[test]
+  fn panic_safe_drops() {
+    let fill = |counter: &DropCounter| {
+      let mut map = SkipMap::new();
+      for i in 0..5 {
+        map.insert(i, counter.elem());
+      }
+      map
+    };
+
+    assert_panic_safe(5, fill, drop);
+  }
+}
@@ -0,0 +1,325 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+
+// Allocation counting, for tests and benchmarks that want to know how much a
+// piece of code allocates. Register a `CountingAlloc` as the global allocator
+// (the crate's own tests already do) and wrap the code in `measure`:
+//
+//   #[global_allocator]
+//   static ALLOCATOR: CountingAlloc = CountingAlloc::system();
+//
+//   let (list, profile) = measure(|| list.append(1));
+//   assert_eq!(profile.allocations, 1);
+//
+// Only the calling thread's allocations are counted, so tests running in
+// parallel don't see each other's.
+
+pub struct CountingAlloc<A = System> {
+  inner: A,
+}
+
+impl CountingAlloc {
+  pub const fn system() -> Self {
+    CountingAlloc { inner: System }
+  }
+}
+
+impl<A> CountingAlloc<A> {
+  pub const fn new(inner: A) -> Self {
+    CountingAlloc { inner }
+  }
+}
+
+// What one `measure` saw. A `realloc` counts as one allocation of the new
+// size and one deallocation. `peak` is the most bytes that were live at once
+// on top of what already was, which is zero for code that only frees.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+  pub allocations: usize,
+  pub deallocations: usize,
+  pub bytes: usize,
+  pub peak: usize,
+}
+
+#[derive(Clone, Copy)]
+struct Counters {
+  allocations: usize,
+  deallocations: usize,
+  bytes: usize,
+  // Relative to the start of the measurement, so freeing older memory makes
+  // it negative.
+  live: isize,
+  peak: isize,
+}
+
+const ZERO: Counters = Counters { allocations: 0, deallocations: 0, bytes: 0, live: 0, peak: 0 };
+
+thread_local! {
+  static COUNTERS: Cell<Counters> = const { Cell::new(ZERO) };
+}
+
+fn update(f: impl FnOnce(&mut Counters)) {
+  // Fails while the thread is being torn down; nothing is measured by then.
+  let _ = COUNTERS.try_with(|counters| {
+    let mut updated = counters.get();
+    f(&mut updated);
+    counters.set(updated);
+  });
+}
+
+fn allocated(size: usize) {
+  update(|counters| {
+    counters.allocations += 1;
+    counters.bytes += size;
+    counters.live += size as isize;
+    counters.peak = counters.peak.max(counters.live);
+  });
+}
+
+fn deallocated(size: usize) {
+  update(|counters| {
+    counters.deallocations += 1;
+    counters.live -= size as isize;
+  });
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CountingAlloc<A> {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    let ptr = self.inner.alloc(layout);
+    if !ptr.is_null() {
+      allocated(layout.size());
+    }
+    ptr
+  }
+
+  unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+    let ptr = self.inner.alloc_zeroed(layout);
+    if !ptr.is_null() {
+      allocated(layout.size());
+    }
+    ptr
+  }
+
+  unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    deallocated(layout.size());
+    self.inner.dealloc(ptr, layout)
+  }
+
+  unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+    let new_ptr = self.inner.realloc(ptr, layout, new_size);
+    if !new_ptr.is_null() {
+      deallocated(layout.size());
+      allocated(new_size);
+    }
+    new_ptr
+  }
+}
+
+// Runs `f` and reports what it allocated on this thread. Measurements nest:
+// the outer one includes everything the inner one saw.
+pub fn measure<R>(f: impl FnOnce() -> R) -> (R, Profile) {
+  // Puts the outer counters back even if `f` panics.
+  struct Restore(Counters);
+
+  impl Drop for Restore {
+    fn drop(&mut self) {
+      let outer = self.0;
+      update(|counters| {
+        let inner = *counters;
+        *counters = Counters {
+          allocations: outer.allocations + inner.allocations,
+          deallocations: outer.deallocations + inner.deallocations,
+          bytes: outer.bytes + inner.bytes,
+          live: outer.live + inner.live,
+          peak: outer.peak.max(outer.live + inner.peak),
+        };
+      });
+    }
+  }
+
+  let restore = Restore(COUNTERS.with(|counters| counters.replace(ZERO)));
+  let result = f();
+  let inner = COUNTERS.with(Cell::get);
+  drop(restore);
+
+  let profile = Profile {
+    allocations: inner.allocations,
+    deallocations: inner.deallocations,
+    bytes: inner.bytes,
+    peak: inner.peak as usize,
+  };
+  (result, profile)
+}
+
+// Hands out `PanicOnDrop` elements and keeps count of them. The `k`-th one
+// dropped (counting from 1, in whatever order) panics.
+#[derive(Clone, Debug)]
+pub struct DropCounter {
+  counts: Rc<Counts>,
+}
+
+#[derive(Debug)]
+struct Counts {
+  created: Cell<usize>,
+  dropped: Cell<usize>,
+  panic_on: usize,
+}
+
+#[derive(Debug)]
+pub struct PanicOnDrop {
+  counts: Rc<Counts>,
+}
+
+impl DropCounter {
+  pub fn panicking_on(k: usize) -> Self {
+    DropCounter {
+      counts: Rc::new(Counts { created: Cell::new(0), dropped: Cell::new(0), panic_on: k }),
+    }
+  }
+
+  // Never panics.
+  pub fn new() -> Self {
+    DropCounter::panicking_on(0)
+  }
+
+  pub fn elem(&self) -> PanicOnDrop {
+    self.counts.created.set(self.counts.created.get() + 1);
+    PanicOnDrop { counts: self.counts.clone() }
+  }
+
+  pub fn created(&self) -> usize {
+    self.counts.created.get()
+  }
+
+  pub fn dropped(&self) -> usize {
+    self.counts.dropped.get()
+  }
+
+  // Goes negative if something got dropped twice.
+  pub fn live(&self) -> isize {
+    self.created() as isize - self.dropped() as isize
+  }
+}
+
+impl Default for DropCounter {
+  fn default() -> Self {
+    DropCounter::new()
+  }
+}
+
+impl Drop for PanicOnDrop {
+  fn drop(&mut self) {
+    let dropped = self.counts.dropped.get() + 1;
+    self.counts.dropped.set(dropped);
+    if dropped == self.counts.panic_on {
+      panic!("drop number {} panics", dropped);
+    }
+  }
+}
+
+// For each `k` up to `len`, builds a container of `len` elements with `fill`
+// and gets rid of it with `empty` while the `k`-th drop panics. Checks that
+// the panic comes out of `empty` and that every element got dropped exactly
+// once anyway.
+pub fn assert_panic_safe<C>(len: usize, fill: impl Fn(&DropCounter) -> C, empty: impl Fn(C)) {
+  for k in 1..=len {
+    let counter = DropCounter::panicking_on(k);
+    let container = fill(&counter);
+    assert_eq!(counter.created(), len, "`fill` should make {} elements", len);
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| empty(container)));
+    assert!(result.is_err(), "drop number {} of {} didn't panic", k, len);
+    assert_eq!(counter.live(), 0, "{} of {} elements left after drop number {} panicked", counter.live(), len, k);
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{assert_panic_safe, measure, DropCounter, Profile};
+  use std::panic::{self, AssertUnwindSafe};
+  use std::hint::black_box;
+  use std::mem;
+  use std::thread;
+
+  // Release builds are free to optimize allocations away, so everything
+  // allocated here goes through `black_box`.
+
+  #[test]
+  fn counts() {
+    let (boxed, profile) = measure(|| black_box(Box::new(0u64)));
+    assert_eq!(profile, Profile { allocations: 1, deallocations: 0, bytes: 8, peak: 8 });
+
+    let ((), profile) = measure(|| drop(boxed));
+    assert_eq!(profile, Profile { allocations: 0, deallocations: 1, bytes: 0, peak: 0 });
+
+    let ((), profile) = measure(|| {
+      let mut v = black_box(Vec::<u8>::with_capacity(16));
+      v.reserve_exact(32);
+      black_box(v);
+    });
+    assert_eq!(profile, Profile { allocations: 2, deallocations: 2, bytes: 16 + 32, peak: 32 });
+  }
+
+  #[test]
+  fn peak() {
+    let ((), profile) = measure(|| {
+      let a = black_box(vec![0u8; 100]);
+      let b = black_box(vec![0u8; 50]);
+      drop(a);
+      let c = black_box(vec![0u8; 20]);
+      mem::drop((b, c));
+    });
+    assert_eq!(profile.peak, 150);
+    assert_eq!(profile.bytes, 170);
+  }
+
+  #[test]
+  fn nests() {
+    let ((inner, first), outer) = measure(|| {
+      let first = black_box(Box::new(1u32));
+      let (second, inner) = measure(|| black_box(Box::new(2u32)));
+      (inner, (first, second))
+    });
+
+    assert_eq!(inner.allocations, 1);
+    assert_eq!(outer.allocations, 2);
+    assert_eq!(outer.peak, 8);
+    drop(first);
+  }
+
+  #[test]
+  fn other_threads_dont_count() {
+    let ((), profile) = measure(|| {
+      let handle = thread::spawn(|| black_box(vec![0u8; 1_000]).len());
+      // Spawning allocates on this thread too, just never the vector.
+      assert_eq!(handle.join().unwrap(), 1_000);
+    });
+    assert!(profile.bytes < 1_000, "{:?}", profile);
+  }
+
+  #[test]
+  fn drop_counter() {
+    let counter = DropCounter::panicking_on(2);
+    let (a, b, c) = (counter.elem(), counter.elem(), counter.elem());
+    assert_eq!((counter.created(), counter.live()), (3, 3));
+
+    drop(c);
+    assert!(panic::catch_unwind(AssertUnwindSafe(|| drop(a))).is_err());
+    drop(b);
+    assert_eq!((counter.dropped(), counter.live()), (3, 0));
+  }
+
+  #[test]
+  #[should_panic(expected = "2 of 3 elements left after drop number 1 panicked")]
+  fn assert_panic_safe_catches_leaks() {
+    assert_panic_safe(3, |counter| vec![counter.elem(), counter.elem(), counter.elem()], |v| {
+      let mut v = mem::ManuallyDrop::new(v);
+      // Drops elements one by one, giving up (and leaking) on a panic.
+      while let Some(elem) = v.pop() {
+        drop(elem);
+      }
+    });
+  }
+}
@@ -0,0 +1,496 @@
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+
+use crate::ch6_unsafe_singly_linked::List;
+
+// Fixed-size thread pool. Jobs wait in a `ch6_unsafe_singly_linked::List`
+// behind a `Mutex`, and idle workers sleep on a `Condvar`, the same way
+// `channel` does it.
+//
+// A panicking job is caught and counted; the worker carries on with the next
+// one. `shutdown` stops new jobs but lets the queued ones finish, while
+// `shutdown_now` throws the queued ones away.
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Metrics {
+  // Jobs waiting for a worker.
+  pub queued: usize,
+  // The most jobs ever waiting at once.
+  pub peak_queued: usize,
+  pub running: usize,
+  // Jobs that ran to the end or panicked.
+  pub completed: usize,
+  pub panicked: usize,
+}
+
+struct State {
+  jobs: List<Job>,
+  metrics: Metrics,
+  shutdown: bool,
+}
+
+struct Shared {
+  state: Mutex<State>,
+  job_ready: Condvar,
+}
+
+impl Shared {
+  fn lock(&self) -> MutexGuard<'_, State> {
+    self.state.lock().unwrap()
+  }
+}
+
+pub struct ThreadPool {
+  shared: Arc<Shared>,
+  workers: Vec<JoinHandle<()>>,
+}
+
+impl ThreadPool {
+  pub fn new(threads: usize) -> Self {
+    assert!(threads > 0, "a thread pool needs at least one thread");
+
+    let shared = Arc::new(Shared {
+      state: Mutex::new(State {
+        jobs: List::new(),
+        metrics: Metrics::default(),
+        shutdown: false,
+      }),
+      job_ready: Condvar::new(),
+    });
+
+    let workers = (0..threads).map(|_| {
+      let shared = shared.clone();
+      thread::spawn(move || work(&shared))
+    }).collect();
+
+    ThreadPool { shared, workers }
+  }
+
+  pub fn threads(&self) -> usize {
+    self.workers.len()
+  }
+
+  pub fn metrics(&self) -> Metrics {
+    self.shared.lock().metrics
+  }
+
+  // Queues `job`, or hands it back if the pool is shutting down.
+  pub fn execute<F>(&self, job: F) -> Result<(), ExecuteError<F>>
+    where F: FnOnce() + Send + 'static
+  {
+    let mut state = self.shared.lock();
+    if state.shutdown {
+      return Err(ExecuteError(job));
+    }
+
+    state.jobs.push(Box::new(job));
+    let metrics = &mut state.metrics;
+    metrics.queued += 1;
+    metrics.peak_queued = metrics.peak_queued.max(metrics.queued);
+    drop(state);
+
+    self.shared.job_ready.notify_one();
+    Ok(())
+  }
+
+  // Runs `f` with a `Scope` whose jobs may borrow from the caller's stack,
+  // and waits for all of them before returning. If one of them panicked, so
+  // does `scope`, once everything is done.
+  //
+  // The jobs need free workers to make progress, so calling this from inside
+  // a job can deadlock a small pool.
+  pub fn scope<'env, F, R>(&self, f: F) -> R
+    where F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> R
+  {
+    let scope = Scope {
+      pool: self,
+      state: Arc::new(ScopeState {
+        pending: Mutex::new(0),
+        all_done: Condvar::new(),
+        panicked: AtomicBool::new(false),
+      }),
+      scope: PhantomData,
+      env: PhantomData,
+    };
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+    scope.state.wait();
+
+    match result {
+      Err(payload) => panic::resume_unwind(payload),
+      Ok(_) if scope.state.panicked.load(Ordering::SeqCst) => panic!("a scoped job panicked"),
+      Ok(result) => result,
+    }
+  }
+
+  // Stops accepting jobs. The ones already queued still run.
+  pub fn shutdown(&self) {
+    self.shared.lock().shutdown = true;
+    self.shared.job_ready.notify_all();
+  }
+
+  // Stops accepting jobs and drops the queued ones, returning how many there
+  // were. Jobs already running are left alone.
+  pub fn shutdown_now(&self) -> usize {
+    let mut state = self.shared.lock();
+    state.shutdown = true;
+    let jobs = mem::replace(&mut state.jobs, List::new());
+    let dropped = mem::replace(&mut state.metrics.queued, 0);
+    drop(state);
+
+    self.shared.job_ready.notify_all();
+    drop(jobs);
+    dropped
+  }
+
+  // Shuts down and waits for the workers to finish the queued jobs.
+  pub fn join(mut self) {
+    self.join_workers();
+  }
+
+  fn join_workers(&mut self) {
+    self.shutdown();
+    for worker in self.workers.drain(..) {
+      // Jobs' panics are caught, so this can't fail.
+      worker.join().unwrap();
+    }
+  }
+}
+
+impl Drop for ThreadPool {
+  fn drop(&mut self) {
+    self.join_workers();
+  }
+}
+
+fn work(shared: &Shared) {
+  loop {
+    let mut state = shared.lock();
+    let job = loop {
+      if let Some(job) = state.jobs.pop() {
+        break job;
+      }
+      if state.shutdown {
+        return;
+      }
+      state = shared.job_ready.wait(state).unwrap();
+    };
+    state.metrics.queued -= 1;
+    state.metrics.running += 1;
+    drop(state);
+
+    let result = panic::catch_unwind(AssertUnwindSafe(job));
+
+    let mut state = shared.lock();
+    state.metrics.running -= 1;
+    state.metrics.completed += 1;
+    if result.is_err() {
+      state.metrics.panicked += 1;
+    }
+  }
+}
+
+struct ScopeState {
+  pending: Mutex<usize>,
+  all_done: Condvar,
+  panicked: AtomicBool,
+}
+
+impl ScopeState {
+  fn wait(&self) {
+    let mut pending = self.pending.lock().unwrap();
+    while *pending > 0 {
+      pending = self.all_done.wait(pending).unwrap();
+    }
+  }
+}
+
+// Counts a scoped job as done when dropped, whether it ran or was thrown
+// away by `shutdown_now`.
+struct ScopedJob(Arc<ScopeState>);
+
+// A scoped job and its `ScopedJob`. Fields drop in declaration order, so a
+// job thrown away unrun is gone, along with everything it borrows, before it
+// counts as done and `scope` may return.
+struct Scoped<F> {
+  job: F,
+  guard: ScopedJob,
+}
+
+impl<F: FnOnce()> Scoped<F> {
+  fn run(self) {
+    let Scoped { job, guard } = self;
+    if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+      guard.0.panicked.store(true, Ordering::SeqCst);
+    }
+  }
+}
+
+impl Drop for ScopedJob {
+  fn drop(&mut self) {
+    let mut pending = self.0.pending.lock().unwrap();
+    *pending -= 1;
+    if *pending == 0 {
+      self.0.all_done.notify_all();
+    }
+  }
+}
+
+pub struct Scope<'scope, 'env: 'scope> {
+  pool: &'scope ThreadPool,
+  state: Arc<ScopeState>,
+  scope: PhantomData<&'scope mut &'scope ()>,
+  env: PhantomData<&'env mut &'env ()>,
+}
+
+impl<'scope, 'env> Scope<'scope, 'env> {
+  // Queues a job that may borrow anything that outlives the scope. If the
+  // pool is shutting down, the job runs right away on this thread instead.
+  pub fn execute<F>(&'scope self, job: F)
+    where F: FnOnce() + Send + 'scope
+  {
+    *self.state.pending.lock().unwrap() += 1;
+    let scoped = Scoped { job, guard: ScopedJob(self.state.clone()) };
+
+    let job: Box<dyn FnOnce() + Send + 'scope> = Box::new(move || scoped.run());
+
+    // `ThreadPool::scope` doesn't return before every job is done or
+    // dropped, so nothing the job borrows can go away before that.
+    let job: Job = unsafe { mem::transmute::<Box<dyn FnOnce() + Send + 'scope>, Job>(job) };
+
+    if let Err(ExecuteError(job)) = self.pool.execute(job) {
+      job();
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ExecuteError<F>(pub F);
+
+impl<F> fmt::Debug for ExecuteError<F> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("ExecuteError { .. }")
+  }
+}
+
+impl<F> fmt::Display for ExecuteError<F> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("executing on a thread pool that is shutting down")
+  }
+}
+
+impl<F> Error for ExecuteError<F> {}
+
+#[cfg(test)]
+mod test {
+  use super::{Metrics, ThreadPool};
+  use crate::channel::bounded;
+  use std::panic::{self, AssertUnwindSafe};
+  use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+  use std::sync::Arc;
+  use std::thread;
+  use std::time::Duration;
+
+  // Occupies the pool's only worker until the returned sender is used or
+  // dropped.
+  fn block(pool: &ThreadPool) -> crate::channel::Sender<()> {
+    let (tx, rx) = bounded(1);
+    let (started_tx, started_rx) = bounded(1);
+    pool.execute(move || {
+      started_tx.send(()).unwrap();
+      let _ = rx.recv();
+    }).unwrap();
+    started_rx.recv().unwrap();
+    tx
+  }
+
+  fn counter_job(counter: &Arc<AtomicUsize>) -> impl FnOnce() + Send + 'static {
+    let counter = counter.clone();
+    move || {
+      counter.fetch_add(1, Ordering::SeqCst);
+    }
+  }
+
+  #[test]
+  fn runs_every_job() {
+    let pool = ThreadPool::new(4);
+    let counter = Arc::new(AtomicUsize::new(0));
+
+    assert_eq!(pool.threads(), 4);
+    for _ in 0..1_000 {
+      pool.execute(counter_job(&counter)).unwrap();
+    }
+    pool.join();
+
+    assert_eq!(counter.load(Ordering::SeqCst), 1_000);
+  }
+
+  #[test]
+  fn panicking_jobs() {
+    let pool = ThreadPool::new(2);
+    let counter = Arc::new(AtomicUsize::new(0));
+
+    for i in 0..20 {
+      if i % 4 == 0 {
+        pool.execute(|| panic!("job failed")).unwrap();
+      } else {
+        pool.execute(counter_job(&counter)).unwrap();
+      }
+    }
+
+    // Let everything run, then check the workers are still around.
+    while pool.metrics().completed < 20 {
+      thread::sleep(Duration::from_millis(1));
+    }
+    pool.execute(counter_job(&counter)).unwrap();
+    let shared = pool.shared.clone();
+    pool.join();
+
+    assert_eq!(counter.load(Ordering::SeqCst), 16);
+    let metrics = shared.lock().metrics;
+    assert_eq!(metrics.completed, 21);
+    assert_eq!(metrics.panicked, 5);
+  }
+
+  #[test]
+  fn shutdown_runs_pending_jobs() {
+    let pool = ThreadPool::new(1);
+    let counter = Arc::new(AtomicUsize::new(0));
+    let unblock = block(&pool);
+
+    for _ in 0..10 {
+      pool.execute(counter_job(&counter)).unwrap();
+    }
+    assert_eq!(pool.metrics(), Metrics { queued: 10, peak_queued: 10, running: 1, ..Metrics::default() });
+
+    pool.shutdown();
+    assert!(pool.execute(counter_job(&counter)).is_err());
+
+    drop(unblock);
+    pool.join();
+    assert_eq!(counter.load(Ordering::SeqCst), 10);
+  }
+
+  #[test]
+  fn shutdown_now_drops_pending_jobs() {
+    let pool = ThreadPool::new(1);
+    let counter = Arc::new(AtomicUsize::new(0));
+    let unblock = block(&pool);
+
+    for _ in 0..10 {
+      pool.execute(counter_job(&counter)).unwrap();
+    }
+
+    assert_eq!(pool.shutdown_now(), 10);
+    assert_eq!(pool.metrics().queued, 0);
+    // The dropped jobs took their clones of the counter with them.
+    assert_eq!(Arc::strong_count(&counter), 1);
+
+    drop(unblock);
+    pool.join();
+    assert_eq!(counter.load(Ordering::SeqCst), 0);
+  }
+
+  #[test]
+  fn scope_borrows() {
+    let pool = ThreadPool::new(3);
+    let mut numbers: Vec<usize> = (0..100).collect();
+    let total = AtomicUsize::new(0);
+
+    pool.scope(|s| {
+      for chunk in numbers.chunks_mut(10) {
+        let total = &total;
+        s.execute(move || {
+          for n in chunk.iter_mut() {
+            *n *= 2;
+            total.fetch_add(*n, Ordering::SeqCst);
+          }
+        });
+      }
+    });
+
+    assert_eq!(numbers, (0..100).map(|n| n * 2).collect::<Vec<_>>());
+    assert_eq!(total.load(Ordering::SeqCst), 9_900);
+  }
+
+  #[test]
+  fn scope_waits_for_panicking_jobs() {
+    let pool = ThreadPool::new(2);
+    let counter = AtomicUsize::new(0);
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+      pool.scope(|s| {
+        s.execute(|| panic!("scoped job failed"));
+        for _ in 0..10 {
+          s.execute(|| {
+            thread::sleep(Duration::from_millis(1));
+            counter.fetch_add(1, Ordering::SeqCst);
+          });
+        }
+      })
+    }));
+
+    assert!(result.is_err());
+    assert_eq!(counter.load(Ordering::SeqCst), 10);
+
+    // The pool itself is fine.
+    assert_eq!(pool.scope(|s| { s.execute(|| {}); 42 }), 42);
+  }
+
+  #[test]
+  fn scope_survives_shutdown_now() {
+    let pool = ThreadPool::new(1);
+    let counter = AtomicUsize::new(0);
+    let unblock = block(&pool);
+
+    pool.scope(|s| {
+      s.execute(|| {
+        counter.fetch_add(1, Ordering::SeqCst);
+      });
+      assert_eq!(pool.shutdown_now(), 1);
+      // Runs inline now that the pool is shutting down.
+      s.execute(|| {
+        counter.fetch_add(10, Ordering::SeqCst);
+      });
+      drop(unblock);
+    });
+
+    assert_eq!(counter.load(Ordering::SeqCst), 10);
+  }
+  #[test]
+  fn scope_outlives_dropped_jobs() {
+    struct SetOnDrop<'a>(&'a AtomicBool);
+
+    impl Drop for SetOnDrop<'_> {
+      fn drop(&mut self) {
+        // Plenty of time for `scope` to return too early.
+        thread::sleep(Duration::from_millis(20));
+        self.0.store(true, Ordering::SeqCst);
+      }
+    }
+
+    let pool = ThreadPool::new(1);
+    let dropped = AtomicBool::new(false);
+    let unblock = block(&pool);
+
+    thread::scope(|threads| {
+      pool.scope(|s| {
+        let set = SetOnDrop(&dropped);
+        s.execute(move || drop(set));
+        // Thrown away from another thread while `scope` waits for it.
+        threads.spawn(|| pool.shutdown_now());
+      });
+      assert!(dropped.load(Ordering::SeqCst));
+    });
+
+    drop(unblock);
+  }
+}
@@ -0,0 +1,255 @@
+// Common interfaces for the lists in this crate, so generic code can work
+// with any of them. Each module implements the ones that fit it; the deques
+// implement all three mutable ones, using the front as the top of the stack.
+//
+// They only cover what every implementation can offer: `ch5` can't hand out
+// a plain `&T`, for instance, so there's no `peek`.
+
+// Last in, first out.
+pub trait Stack<T> {
+  fn push(&mut self, elem: T);
+  fn pop(&mut self) -> Option<T>;
+}
+
+// First in, first out.
+pub trait Queue<T> {
+  fn enqueue(&mut self, elem: T);
+  fn dequeue(&mut self) -> Option<T>;
+}
+
+pub trait Deque<T> {
+  fn push_front(&mut self, elem: T);
+  fn push_back(&mut self, elem: T);
+  fn pop_front(&mut self) -> Option<T>;
+  fn pop_back(&mut self) -> Option<T>;
+}
+
+// A stack whose operations return new versions and leave the old ones alone.
+pub trait PersistentStack<T>: Sized {
+  fn push(&self, elem: T) -> Self;
+  fn peek(&self) -> Option<&T>;
+  // Everything but the top. The tail of an empty stack is empty.
+  fn tail(&self) -> Self;
+}
+
+// Tests every implementation has to pass. Each module calls the ones for the
+// traits it implements, passing its constructor.
+#[cfg(test)]
+pub(crate) mod conformance {
+  use super::{Deque, PersistentStack, Queue, Stack};
+  use crate::test_support::{assert_panic_safe, measure, PanicOnDrop, Profile};
+
+  // Big enough to blow the stack if dropping recursed. Strict invariant
+  // checking walks the whole list on every push, so it gets a shorter one.
+  const LONG: i32 = if cfg!(feature = "strict-invariants") { 2_000 } else { 100_000 };
+
+  pub fn stack<S: Stack<i32>>(new: impl Fn() -> S) {
+    let mut stack = new();
+    assert_eq!(stack.pop(), None);
+
+    stack.push(1);
+    stack.push(2);
+    stack.push(3);
+    assert_eq!(stack.pop(), Some(3));
+    assert_eq!(stack.pop(), Some(2));
+
+    stack.push(4);
+    assert_eq!(stack.pop(), Some(4));
+    assert_eq!(stack.pop(), Some(1));
+    assert_eq!(stack.pop(), None);
+
+    for i in 0..1_000 {
+      stack.push(i);
+    }
+    for i in (0..1_000).rev() {
+      assert_eq!(stack.pop(), Some(i));
+    }
+    assert_eq!(stack.pop(), None);
+
+    let mut stack = new();
+    for i in 0..LONG {
+      stack.push(i);
+    }
+    drop(stack);
+  }
+
+  pub fn queue<Q: Queue<i32>>(new: impl Fn() -> Q) {
+    let mut queue = new();
+    assert_eq!(queue.dequeue(), None);
+
+    queue.enqueue(1);
+    queue.enqueue(2);
+    queue.enqueue(3);
+    assert_eq!(queue.dequeue(), Some(1));
+    assert_eq!(queue.dequeue(), Some(2));
+
+    queue.enqueue(4);
+    assert_eq!(queue.dequeue(), Some(3));
+    assert_eq!(queue.dequeue(), Some(4));
+    assert_eq!(queue.dequeue(), None);
+
+    // Works again once drained.
+    queue.enqueue(5);
+    assert_eq!(queue.dequeue(), Some(5));
+    assert_eq!(queue.dequeue(), None);
+
+    for i in 0..1_000 {
+      queue.enqueue(i);
+    }
+    for i in 0..1_000 {
+      assert_eq!(queue.dequeue(), Some(i));
+    }
+    assert_eq!(queue.dequeue(), None);
+
+    let mut queue = new();
+    for i in 0..LONG {
+      queue.enqueue(i);
+    }
+    drop(queue);
+  }
+
+  pub fn deque<D: Deque<i32>>(new: impl Fn() -> D) {
+    let mut deque = new();
+    assert_eq!(deque.pop_front(), None);
+    assert_eq!(deque.pop_back(), None);
+
+    deque.push_front(2);
+    deque.push_front(1);
+    deque.push_back(3);
+    deque.push_back(4);
+    assert_eq!(deque.pop_front(), Some(1));
+    assert_eq!(deque.pop_back(), Some(4));
+    assert_eq!(deque.pop_back(), Some(3));
+    assert_eq!(deque.pop_back(), Some(2));
+    assert_eq!(deque.pop_back(), None);
+    assert_eq!(deque.pop_front(), None);
+
+    // A single element is both the front and the back.
+    deque.push_back(5);
+    assert_eq!(deque.pop_front(), Some(5));
+    deque.push_front(6);
+    assert_eq!(deque.pop_back(), Some(6));
+    assert_eq!(deque.pop_front(), None);
+
+    for i in 0..500 {
+      deque.push_back(i);
+      deque.push_front(-i - 1);
+    }
+    for i in (0..500).rev() {
+      assert_eq!(deque.pop_back(), Some(i));
+      assert_eq!(deque.pop_front(), Some(-i - 1));
+    }
+    assert_eq!(deque.pop_front(), None);
+
+    let mut deque = new();
+    for i in 0..LONG {
+      deque.push_back(i);
+    }
+    drop(deque);
+  }
+
+  // Dropping a full stack still drops every element exactly once when one of
+  // their destructors panics.
+  pub fn stack_drops<S: Stack<PanicOnDrop>>(new: impl Fn() -> S) {
+    assert_panic_safe(5, |counter| {
+      let mut stack = new();
+      for _ in 0..5 {
+        stack.push(counter.elem());
+      }
+      stack
+    }, drop);
+  }
+
+  pub fn queue_drops<Q: Queue<PanicOnDrop>>(new: impl Fn() -> Q) {
+    assert_panic_safe(5, |counter| {
+      let mut queue = new();
+      for _ in 0..5 {
+        queue.enqueue(counter.elem());
+      }
+      queue
+    }, drop);
+  }
+
+  pub fn persistent_stack_drops<S: PersistentStack<PanicOnDrop>>(new: impl Fn() -> S) {
+    assert_panic_safe(5, |counter| {
+      (0..5).fold(new(), |stack, _| stack.push(counter.elem()))
+    }, drop);
+
+    // Dropping `shorter` frees nothing, `longer` still needs all of it.
+    assert_panic_safe(6, |counter| {
+      let shorter = (0..5).fold(new(), |stack, _| stack.push(counter.elem()));
+      let longer = shorter.push(counter.elem());
+      (shorter, longer)
+    }, drop);
+  }
+
+  // For the lists with a node per element: an empty one allocates nothing,
+  // every push allocates a node, and every pop or drop of an element frees it.
+  pub fn stack_allocations<S: Stack<i32>>(new: impl Fn() -> S) {
+    let (mut stack, profile) = measure(&new);
+    assert_eq!(profile, Profile::default());
+
+    for i in 0..3 {
+      let ((), profile) = measure(|| stack.push(i));
+      assert_eq!((profile.allocations, profile.deallocations), (1, 0));
+    }
+    let (elem, profile) = measure(|| stack.pop());
+    assert_eq!(elem, Some(2));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 1));
+
+    for i in 0..8 {
+      stack.push(i);
+    }
+    let ((), profile) = measure(|| drop(stack));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 10));
+  }
+
+  pub fn queue_allocations<Q: Queue<i32>>(new: impl Fn() -> Q) {
+    let (mut queue, profile) = measure(&new);
+    assert_eq!(profile, Profile::default());
+
+    for i in 0..3 {
+      let ((), profile) = measure(|| queue.enqueue(i));
+      assert_eq!((profile.allocations, profile.deallocations), (1, 0));
+    }
+    let (elem, profile) = measure(|| queue.dequeue());
+    assert_eq!(elem, Some(0));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 1));
+
+    for i in 0..8 {
+      queue.enqueue(i);
+    }
+    let ((), profile) = measure(|| drop(queue));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 10));
+  }
+
+  pub fn persistent_stack<S: PersistentStack<i32>>(new: impl Fn() -> S) {
+    let empty = new();
+    assert_eq!(empty.peek(), None);
+    assert_eq!(empty.tail().peek(), None);
+
+    let one = empty.push(1);
+    let two = one.push(2);
+    let three = one.push(3);
+
+    // Older versions don't see what was pushed on newer ones.
+    assert_eq!(empty.peek(), None);
+    assert_eq!(one.peek(), Some(&1));
+    assert_eq!(two.peek(), Some(&2));
+    assert_eq!(three.peek(), Some(&3));
+    assert_eq!(two.tail().peek(), Some(&1));
+    assert_eq!(three.tail().peek(), Some(&1));
+    assert_eq!(three.tail().tail().peek(), None);
+
+    drop(one);
+    assert_eq!(two.tail().peek(), Some(&1));
+
+    let mut long = new();
+    for i in 0..LONG {
+      long = long.push(i);
+    }
+    assert_eq!(long.peek(), Some(&(LONG - 1)));
+    assert_eq!(long.tail().peek(), Some(&(LONG - 2)));
+    drop(long);
+  }
+}
@@ -0,0 +1,391 @@
+use std::mem::ManuallyDrop;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::thread;
+
+use crate::ch3_singly_linked::List;
+use crate::drop_guard;
+use crate::hazard::{self, HazardPointer};
+use crate::traits::Stack;
+
+// Treiber stack: `ch3_singly_linked::List` with an `AtomicPtr` head, pushed
+// and popped with compare-and-swap from any number of threads.
+//
+// Popped nodes are retired through `hazard` rather than freed. A node can't
+// be reused while another thread holds a hazard pointer to it, so a `pop`
+// whose CAS succeeds really did see the node it expected: no ABA.
+//
+// `peek_with` lends the top element out in place. Each node counts the
+// threads looking at its element, and whoever unlinks the node waits for
+// them before moving the element out. So only `push` is lock-free: `pop` and
+// `take_all` can be held up by a slow reader.
+
+pub struct TreiberStack<T> {
+  head: AtomicPtr<Node<T>>,
+}
+
+struct Node<T> {
+  elem: ManuallyDrop<T>,
+  next: *mut Node<T>,
+  readers: AtomicUsize,
+}
+
+unsafe impl<T: Send> Send for TreiberStack<T> {}
+unsafe impl<T: Send + Sync> Sync for TreiberStack<T> {}
+
+impl<T> Node<T> {
+  // Takes the element out of a node this thread just unlinked.
+  unsafe fn take_elem(node: *mut Node<T>) -> T {
+    while (*node).readers.load(Ordering::SeqCst) > 0 {
+      thread::yield_now();
+    }
+    ManuallyDrop::take(&mut (*node).elem)
+  }
+}
+
+impl<T> TreiberStack<T> {
+  pub fn new() -> Self {
+    TreiberStack { head: AtomicPtr::new(ptr::null_mut()) }
+  }
+
+  pub fn push(&self, elem: T) {
+    let new_node = Box::into_raw(Box::new(Node {
+      elem: ManuallyDrop::new(elem),
+      next: ptr::null_mut(),
+      readers: AtomicUsize::new(0),
+    }));
+
+    let mut head = self.head.load(Ordering::SeqCst);
+    loop {
+      unsafe {
+        (*new_node).next = head;
+      }
+      match self.head.compare_exchange(head, new_node, Ordering::SeqCst, Ordering::SeqCst) {
+        Ok(_) => return,
+        Err(new_head) => head = new_head,
+      }
+    }
+  }
+
+  pub fn pop(&self) -> Option<T> {
+    let hazard = HazardPointer::new();
+
+    loop {
+      let head = hazard.protect(&self.head);
+      if head.is_null() {
+        return None;
+      }
+
+      let next = unsafe { (*head).next };
+      if self.head.compare_exchange(head, next, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+        let elem = unsafe { Node::take_elem(head) };
+
+        hazard.reset();
+        unsafe {
+          hazard::retire(head);
+        }
+
+        return Some(elem);
+      }
+    }
+  }
+
+  // Calls `f` with the top element, if any, without popping it. Whoever
+  // pops that element meanwhile waits for `f` to return, so `f` itself must
+  // not pop from this stack.
+  pub fn peek_with<F, R>(&self, f: F) -> R
+    where F: FnOnce(Option<&T>) -> R
+  {
+    let hazard = HazardPointer::new();
+
+    loop {
+      let head = hazard.protect(&self.head);
+      if head.is_null() {
+        return f(None);
+      }
+
+      let node = unsafe { &*head };
+      let reader = Reader::new(node);
+
+      // Still on top after announcing ourselves: whoever unlinks it from now
+      // on is bound to see the reader.
+      if self.head.load(Ordering::SeqCst) == head {
+        let result = f(Some(&node.elem));
+        drop(reader);
+        return result;
+      }
+    }
+  }
+
+  // Atomically empties the stack, handing over everything it held as a
+  // `ch3_singly_linked::List` with the same top.
+  //
+  // Only the elements change hands, not the nodes: a `pop` or `peek_with`
+  // that protected one of them before the swap may still read it, so they
+  // have to be retired like popped nodes rather than freed by the list. That
+  // costs a fresh list node per element, plus a `Vec` to turn the elements
+  // around, since the chain only leads away from the top.
+  pub fn take_all(&self) -> List<T> {
+    let mut cur = self.head.swap(ptr::null_mut(), Ordering::SeqCst);
+    let mut elems = Vec::new();
+
+    while !cur.is_null() {
+      unsafe {
+        elems.push(Node::take_elem(cur));
+        let next = (*cur).next;
+        // Other threads may still be holding on to the nodes themselves.
+        hazard::retire(cur);
+        cur = next;
+      }
+    }
+
+    let mut list = List::new();
+    for elem in elems.into_iter().rev() {
+      list.push(elem);
+    }
+    list
+  }
+
+  // Only a snapshot: other threads may change it right away.
+  pub fn is_empty(&self) -> bool {
+    self.head.load(Ordering::SeqCst).is_null()
+  }
+}
+
+// Counts as a reader of the node's element until dropped, so a panicking
+// `peek_with` doesn't leave poppers waiting forever.
+struct Reader<'a, T>(&'a Node<T>);
+
+impl<'a, T> Reader<'a, T> {
+  fn new(node: &'a Node<T>) -> Self {
+    node.readers.fetch_add(1, Ordering::SeqCst);
+    Reader(node)
+  }
+}
+
+impl<'a, T> Drop for Reader<'a, T> {
+  fn drop(&mut self) {
+    self.0.readers.fetch_sub(1, Ordering::SeqCst);
+  }
+}
+
+impl<T> Default for TreiberStack<T> {
+  fn default() -> Self {
+    TreiberStack::new()
+  }
+}
+
+impl<T> Drop for TreiberStack<T> {
+  fn drop(&mut self) {
+    drop_guard::drain(self.head.get_mut(), |head| {
+      if head.is_null() {
+        return false;
+      }
+
+      let mut node = unsafe { Box::from_raw(*head) };
+      *head = node.next;
+      unsafe {
+        ManuallyDrop::drop(&mut node.elem);
+      }
+      true
+    });
+  }
+}
+
+impl<T> Stack<T> for TreiberStack<T> {
+  fn push(&mut self, elem: T) {
+    TreiberStack::push(self, elem);
+  }
+
+  fn pop(&mut self) -> Option<T> {
+    TreiberStack::pop(self)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::TreiberStack;
+  use crate::traits::conformance;
+  use std::collections::HashSet;
+  use std::panic::{self, AssertUnwindSafe};
+  use std::sync::atomic::{AtomicBool, Ordering};
+  use std::sync::{Arc, Mutex};
+  use std::thread;
+
+  #[test]
+  fn basics() {
+    let stack = TreiberStack::new();
+
+    assert!(stack.is_empty());
+    assert_eq!(stack.pop(), None);
+
+    stack.push(1);
+    stack.push(2);
+    stack.push(3);
+
+    assert_eq!(stack.pop(), Some(3));
+    assert_eq!(stack.pop(), Some(2));
+
+    stack.push(4);
+    stack.push(5);
+
+    assert_eq!(stack.pop(), Some(5));
+    assert_eq!(stack.pop(), Some(4));
+    assert_eq!(stack.pop(), Some(1));
+    assert_eq!(stack.pop(), None);
+    assert!(stack.is_empty());
+  }
+
+  #[test]
+  fn peek_with() {
+    let stack = TreiberStack::new();
+
+    assert_eq!(stack.peek_with(|top| top.cloned()), None);
+
+    stack.push(String::from("a"));
+    stack.push(String::from("b"));
+
+    assert_eq!(stack.peek_with(|top| top.map(|s| s.len())), Some(1));
+    assert_eq!(stack.peek_with(|top| top.cloned()), Some(String::from("b")));
+    assert_eq!(stack.pop(), Some(String::from("b")));
+    assert_eq!(stack.peek_with(|top| top.cloned()), Some(String::from("a")));
+  }
+
+  #[test]
+  fn peek_with_panicking() {
+    let stack = TreiberStack::new();
+    stack.push(1);
+    stack.push(2);
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| stack.peek_with(|_| panic!("peek"))));
+    assert!(result.is_err());
+
+    // Would wait forever for the reader that panicked.
+    assert_eq!(stack.pop(), Some(2));
+    assert_eq!(stack.take_all().pop(), Some(1));
+  }
+
+  #[test]
+  fn take_all() {
+    let stack = TreiberStack::new();
+
+    assert_eq!(stack.take_all().pop(), None);
+
+    stack.push(1);
+    stack.push(2);
+    stack.push(3);
+
+    let mut list = stack.take_all();
+    assert!(stack.is_empty());
+
+    stack.push(4);
+
+    assert_eq!(list.pop(), Some(3));
+    assert_eq!(list.pop(), Some(2));
+    assert_eq!(list.pop(), Some(1));
+    assert_eq!(list.pop(), None);
+    assert_eq!(stack.pop(), Some(4));
+  }
+
+  #[test]
+  fn drops_remaining_elements() {
+    let elem = Arc::new(());
+
+    let stack = TreiberStack::new();
+    for _ in 0..10 {
+      stack.push(elem.clone());
+    }
+    stack.pop();
+    drop(stack.take_all());
+    for _ in 0..5 {
+      stack.push(elem.clone());
+    }
+    assert_eq!(Arc::strong_count(&elem), 6);
+
+    drop(stack);
+    assert_eq!(Arc::strong_count(&elem), 1);
+  }
+
+  #[test]
+  fn contention() {
+    const THREADS: usize = 8;
+    const PER_THREAD: usize = 10_000;
+
+    let stack = TreiberStack::new();
+    let taken = Mutex::new(Vec::new());
+
+    thread::scope(|s| {
+      for t in 0..THREADS {
+        let (stack, taken) = (&stack, &taken);
+        s.spawn(move || {
+          let mut mine = Vec::new();
+
+          for i in 0..PER_THREAD {
+            stack.push((t, i));
+
+            match i % 100 {
+              // Every now and then, steal the whole thing.
+              99 => mine.extend(stack.take_all().into_iter()),
+              n if n % 2 == 0 => mine.extend(stack.pop()),
+              _ => {}
+            }
+          }
+
+          taken.lock().unwrap().extend(mine);
+        });
+      }
+    });
+
+    let mut taken = taken.into_inner().unwrap();
+    taken.extend(stack.take_all().into_iter());
+
+    let unique: HashSet<_> = taken.iter().cloned().collect();
+    assert_eq!(taken.len(), THREADS * PER_THREAD);
+    assert_eq!(unique.len(), THREADS * PER_THREAD);
+  }
+
+  #[test]
+  fn peek_while_popping() {
+    let stack = TreiberStack::new();
+    let done = AtomicBool::new(false);
+
+    thread::scope(|s| {
+      for _ in 0..2 {
+        s.spawn(|| {
+          for i in 0..20_000 {
+            stack.push(vec![i; 8]);
+            stack.pop();
+          }
+        });
+      }
+
+      for _ in 0..2 {
+        s.spawn(|| {
+          while !done.load(Ordering::SeqCst) {
+            // A popped element would have been freed already.
+            stack.peek_with(|top| {
+              if let Some(v) = top {
+                assert!(v.iter().all(|&x| x == v[0]));
+              }
+            });
+          }
+        });
+      }
+
+      s.spawn(|| {
+        for _ in 0..1_000 {
+          stack.take_all();
+        }
+      });
+
+      thread::sleep(std::time::Duration::from_millis(100));
+      done.store(true, Ordering::SeqCst);
+    });
+  }
+
+  #[test]
+  fn conformance() {
+    conformance::stack(TreiberStack::new);
+    conformance::stack_drops(TreiberStack::new);
+  }
+}
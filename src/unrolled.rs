@@ -0,0 +1,876 @@
+use alloc::boxed::Box;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::ptr;
+use crate::drop_guard;
+use crate::traits::{Deque, Queue, Stack};
+
+// A doubly-linked list of blocks holding up to `B` elements each, so walking
+// it follows one pointer per block instead of one per element, and small
+// elements sit next to each other in memory.
+//
+// Inside a block the elements are contiguous, in `elems[start..start + len]`.
+// Pushing at an end uses the room on that side of the end block, shifting its
+// elements over when all the room is on the other side. Inserting into a full
+// block splits it in two, and removing from a block that's less than half
+// full merges it with a neighbour when they fit in one. No block is ever
+// empty.
+
+pub struct List<T, const B: usize = 16> {
+  head: *mut Node<T, B>,
+  tail: *mut Node<T, B>,
+  len: usize,
+  // The list owns the nodes and, through them, the elements.
+  _owns: PhantomData<Box<Node<T, B>>>,
+}
+
+struct Node<T, const B: usize> {
+  elems: [MaybeUninit<T>; B],
+  start: usize,
+  len: usize,
+  prev: *mut Node<T, B>,
+  next: *mut Node<T, B>,
+}
+
+impl<T, const B: usize> Node<T, B> {
+  // Empty, with its free room split so that `start` elements fit before.
+  fn new(start: usize) -> Box<Self> {
+    Box::new(Node {
+      elems: [const { MaybeUninit::uninit() }; B],
+      start,
+      len: 0,
+      prev: ptr::null_mut(),
+      next: ptr::null_mut(),
+    })
+  }
+
+  fn is_full(&self) -> bool {
+    self.len == B
+  }
+
+  // May point one past the last slot, for copying nothing there.
+  fn slot(&mut self, index: usize) -> *mut T {
+    self.elems.as_mut_ptr().cast::<T>().wrapping_add(self.start + index)
+  }
+
+  // Like `slot`, but without borrowing the whole node: elements `IterMut`
+  // already lent out from it stay valid.
+  unsafe fn raw_slot(node: *mut Self, index: usize) -> *mut T {
+    ptr::addr_of_mut!((*node).elems).cast::<T>().add((*node).start + index)
+  }
+
+  fn get(&self, index: usize) -> &T {
+    unsafe { self.elems[self.start + index].assume_init_ref() }
+  }
+
+  fn get_mut(&mut self, index: usize) -> &mut T {
+    unsafe { self.elems[self.start + index].assume_init_mut() }
+  }
+
+  // Moves the elements `count` slots towards the back, starting from `from`.
+  // Negative counts move them towards the front.
+  unsafe fn shift(&mut self, from: usize, count: isize) {
+    let src = self.slot(from);
+    ptr::copy(src, src.offset(count), self.len - from);
+  }
+
+  fn push_front(&mut self, elem: T) {
+    debug_assert!(!self.is_full());
+    if self.start == 0 {
+      // Move everything to the back, leaving all the room in front.
+      let start = B - self.len;
+      unsafe { self.shift(0, start as isize) };
+      self.start = start;
+    }
+
+    self.start -= 1;
+    self.len += 1;
+    unsafe { self.slot(0).write(elem) };
+  }
+
+  fn push_back(&mut self, elem: T) {
+    debug_assert!(!self.is_full());
+    if self.start + self.len == B {
+      unsafe { self.shift(0, -(self.start as isize)) };
+      self.start = 0;
+    }
+
+    self.len += 1;
+    unsafe { self.slot(self.len - 1).write(elem) };
+  }
+
+  fn pop_front(&mut self) -> T {
+    let elem = unsafe { self.slot(0).read() };
+    self.start += 1;
+    self.len -= 1;
+    elem
+  }
+
+  fn pop_back(&mut self) -> T {
+    self.len -= 1;
+    unsafe { self.slot(self.len).read() }
+  }
+
+  // Moves whichever side of `index` is shorter, as long as there's room on
+  // that side.
+  fn insert(&mut self, index: usize, elem: T) {
+    debug_assert!(!self.is_full() && index <= self.len);
+    let room_before = self.start > 0;
+    let room_after = self.start + self.len < B;
+
+    unsafe {
+      if room_before && (index < self.len / 2 || !room_after) {
+        ptr::copy(self.slot(0), self.slot(0).sub(1), index);
+        self.start -= 1;
+      } else if room_after {
+        self.shift(index, 1);
+      }
+      self.len += 1;
+      self.slot(index).write(elem);
+    }
+  }
+
+  fn remove(&mut self, index: usize) -> T {
+    unsafe {
+      let elem = self.slot(index).read();
+      if index < self.len / 2 {
+        // Close the gap from the front.
+        ptr::copy(self.slot(0), self.slot(1), index);
+        self.start += 1;
+      } else {
+        self.shift(index + 1, -1);
+      }
+      self.len -= 1;
+      elem
+    }
+  }
+
+  // Moves all of `other`'s elements to the end of this node's, leaving
+  // `other` empty.
+  unsafe fn append(&mut self, other: &mut Node<T, B>) {
+    debug_assert!(self.len + other.len <= B);
+    if self.start + self.len + other.len > B {
+      self.shift(0, -(self.start as isize));
+      self.start = 0;
+    }
+
+    ptr::copy_nonoverlapping(other.slot(0), self.slot(self.len), other.len);
+    self.len += other.len;
+    other.len = 0;
+  }
+}
+
+impl<T, const B: usize> List<T, B> {
+  pub fn new() -> Self {
+    // Splitting a full block has to leave something on both sides.
+    assert!(B >= 2, "blocks need room for at least two elements");
+    List { head: ptr::null_mut(), tail: ptr::null_mut(), len: 0, _owns: PhantomData }
+  }
+
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  pub fn push_front(&mut self, elem: T) {
+    unsafe {
+      if self.head.is_null() || (*self.head).is_full() {
+        self.link_after(ptr::null_mut(), Node::new(B));
+      }
+      (*self.head).push_front(elem);
+    }
+    self.len += 1;
+  }
+
+  pub fn push_back(&mut self, elem: T) {
+    unsafe {
+      if self.tail.is_null() || (*self.tail).is_full() {
+        self.link_after(self.tail, Node::new(0));
+      }
+      (*self.tail).push_back(elem);
+    }
+    self.len += 1;
+  }
+
+  pub fn pop_front(&mut self) -> Option<T> {
+    if self.head.is_null() {
+      return None;
+    }
+
+    self.len -= 1;
+    unsafe {
+      let head = self.head;
+      let elem = (*head).pop_front();
+      if (*head).len == 0 {
+        drop(self.unlink(head));
+      }
+      Some(elem)
+    }
+  }
+
+  pub fn pop_back(&mut self) -> Option<T> {
+    if self.tail.is_null() {
+      return None;
+    }
+
+    self.len -= 1;
+    unsafe {
+      let tail = self.tail;
+      let elem = (*tail).pop_back();
+      if (*tail).len == 0 {
+        drop(self.unlink(tail));
+      }
+      Some(elem)
+    }
+  }
+
+  pub fn peek_front(&self) -> Option<&T> {
+    unsafe { self.head.as_ref().map(|node| node.get(0)) }
+  }
+
+  pub fn peek_front_mut(&mut self) -> Option<&mut T> {
+    unsafe { self.head.as_mut().map(|node| node.get_mut(0)) }
+  }
+
+  pub fn peek_back(&self) -> Option<&T> {
+    unsafe { self.tail.as_ref().map(|node| node.get(node.len - 1)) }
+  }
+
+  pub fn peek_back_mut(&mut self) -> Option<&mut T> {
+    unsafe { self.tail.as_mut().map(|node| node.get_mut(node.len - 1)) }
+  }
+
+  // Walks from whichever end is closer, a block at a time.
+  pub fn get(&self, index: usize) -> Option<&T> {
+    self.locate(index).map(|(node, offset)| unsafe { (*node).get(offset) })
+  }
+
+  pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+    self.locate(index).map(|(node, offset)| unsafe { (*node).get_mut(offset) })
+  }
+
+  // Puts `elem` at `index`, moving everything after it back by one. Panics
+  // if `index` is past the end, like `Vec::insert`.
+  pub fn insert(&mut self, index: usize, elem: T) {
+    assert!(index <= self.len, "insertion index {} is past the end of a list of {}", index, self.len);
+    if index == self.len {
+      return self.push_back(elem);
+    }
+
+    let (mut node, mut offset) = self.locate(index).unwrap();
+    unsafe {
+      if (*node).is_full() {
+        // Split it, moving the back half into a new node right after.
+        let half = B / 2;
+        let mut back = Node::new(0);
+        ptr::copy_nonoverlapping((*node).slot(half), back.slot(0), B - half);
+        back.len = B - half;
+        (*node).len = half;
+
+        let back = self.link_after(node, back);
+        if offset > half {
+          node = back;
+          offset -= half;
+        }
+      }
+      (*node).insert(offset, elem);
+    }
+    self.len += 1;
+  }
+
+  // Takes out the element at `index`, moving everything after it forward by
+  // one.
+  pub fn remove(&mut self, index: usize) -> Option<T> {
+    let (node, offset) = self.locate(index)?;
+    self.len -= 1;
+
+    unsafe {
+      let elem = (*node).remove(offset);
+      if (*node).len == 0 {
+        drop(self.unlink(node));
+      } else if (*node).len < B / 2 {
+        let (prev, next) = ((*node).prev, (*node).next);
+        if !next.is_null() && (*node).len + (*next).len <= B {
+          (*node).append(&mut *next);
+          drop(self.unlink(next));
+        } else if !prev.is_null() && (*prev).len + (*node).len <= B {
+          (*prev).append(&mut *node);
+          drop(self.unlink(node));
+        }
+      }
+      Some(elem)
+    }
+  }
+
+  pub fn clear(&mut self) {
+    drop_guard::drain(self, |list| list.pop_front().is_some());
+  }
+
+  pub fn iter(&self) -> Iter<'_, T, B> {
+    Iter {
+      front: self.head,
+      front_index: 0,
+      back: self.tail,
+      back_index: unsafe { self.tail.as_ref().map_or(0, |node| node.len) },
+      len: self.len,
+      _list: PhantomData,
+    }
+  }
+
+  pub fn iter_mut(&mut self) -> IterMut<'_, T, B> {
+    IterMut {
+      front: self.head,
+      front_index: 0,
+      back: self.tail,
+      back_index: unsafe { self.tail.as_ref().map_or(0, |node| node.len) },
+      len: self.len,
+      _list: PhantomData,
+    }
+  }
+
+  #[allow(clippy::should_implement_trait)]
+  pub fn into_iter(self) -> IntoIter<T, B> {
+    IntoIter(self)
+  }
+
+  // The node holding the element at `index` and where in the node it is.
+  fn locate(&self, index: usize) -> Option<(*mut Node<T, B>, usize)> {
+    if index >= self.len {
+      return None;
+    }
+
+    unsafe {
+      if index < self.len / 2 {
+        let mut node = self.head;
+        let mut offset = index;
+        while offset >= (*node).len {
+          offset -= (*node).len;
+          node = (*node).next;
+        }
+        Some((node, offset))
+      } else {
+        let mut node = self.tail;
+        // Counting from the back, 0 being the last element.
+        let mut from_back = self.len - 1 - index;
+        while from_back >= (*node).len {
+          from_back -= (*node).len;
+          node = (*node).prev;
+        }
+        Some((node, (*node).len - 1 - from_back))
+      }
+    }
+  }
+
+  // Links `node` in right after `prev`, or at the front if `prev` is null.
+  unsafe fn link_after(&mut self, prev: *mut Node<T, B>, node: Box<Node<T, B>>) -> *mut Node<T, B> {
+    let node = Box::into_raw(node);
+    let next = if prev.is_null() { self.head } else { (*prev).next };
+
+    (*node).prev = prev;
+    (*node).next = next;
+    if prev.is_null() {
+      self.head = node;
+    } else {
+      (*prev).next = node;
+    }
+    if next.is_null() {
+      self.tail = node;
+    } else {
+      (*next).prev = node;
+    }
+    node
+  }
+
+  // Takes `node` out of the list. Whatever elements it still holds are the
+  // caller's to deal with.
+  unsafe fn unlink(&mut self, node: *mut Node<T, B>) -> Box<Node<T, B>> {
+    let (prev, next) = ((*node).prev, (*node).next);
+    if prev.is_null() {
+      self.head = next;
+    } else {
+      (*prev).next = next;
+    }
+    if next.is_null() {
+      self.tail = prev;
+    } else {
+      (*next).prev = prev;
+    }
+    Box::from_raw(node)
+  }
+}
+
+impl<T, const B: usize> Default for List<T, B> {
+  fn default() -> Self {
+    List::new()
+  }
+}
+
+impl<T, const B: usize> Drop for List<T, B> {
+  fn drop(&mut self) {
+    self.clear();
+  }
+}
+
+pub struct IntoIter<T, const B: usize = 16>(List<T, B>);
+
+impl<T, const B: usize> Iterator for IntoIter<T, B> {
+  type Item = T;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.0.pop_front()
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.0.len, Some(self.0.len))
+  }
+}
+
+impl<T, const B: usize> DoubleEndedIterator for IntoIter<T, B> {
+  fn next_back(&mut self) -> Option<Self::Item> {
+    self.0.pop_back()
+  }
+}
+
+impl<T, const B: usize> ExactSizeIterator for IntoIter<T, B> {}
+
+// Both iterators keep a cursor at each end and count what's left between
+// them, so they stop when the cursors meet.
+pub struct Iter<'a, T, const B: usize = 16> {
+  front: *const Node<T, B>,
+  front_index: usize,
+  back: *const Node<T, B>,
+  // One past the next element from the back.
+  back_index: usize,
+  len: usize,
+  _list: PhantomData<&'a T>,
+}
+
+impl<'a, T, const B: usize> Iterator for Iter<'a, T, B> {
+  type Item = &'a T;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.len == 0 {
+      return None;
+    }
+
+    self.len -= 1;
+    unsafe {
+      let node = &*self.front;
+      let elem = node.get(self.front_index);
+      self.front_index += 1;
+      if self.front_index == node.len {
+        self.front = node.next;
+        self.front_index = 0;
+      }
+      Some(elem)
+    }
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.len, Some(self.len))
+  }
+}
+
+impl<'a, T, const B: usize> DoubleEndedIterator for Iter<'a, T, B> {
+  fn next_back(&mut self) -> Option<Self::Item> {
+    if self.len == 0 {
+      return None;
+    }
+
+    self.len -= 1;
+    unsafe {
+      let node = &*self.back;
+      self.back_index -= 1;
+      let elem = node.get(self.back_index);
+      if self.back_index == 0 {
+        self.back = node.prev;
+        self.back_index = node.prev.as_ref().map_or(0, |prev| prev.len);
+      }
+      Some(elem)
+    }
+  }
+}
+
+impl<'a, T, const B: usize> ExactSizeIterator for Iter<'a, T, B> {}
+
+pub struct IterMut<'a, T, const B: usize = 16> {
+  front: *mut Node<T, B>,
+  front_index: usize,
+  back: *mut Node<T, B>,
+  back_index: usize,
+  len: usize,
+  _list: PhantomData<&'a mut T>,
+}
+
+impl<'a, T, const B: usize> Iterator for IterMut<'a, T, B> {
+  type Item = &'a mut T;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.len == 0 {
+      return None;
+    }
+
+    self.len -= 1;
+    unsafe {
+      let node = self.front;
+      let elem = &mut *Node::raw_slot(node, self.front_index);
+      self.front_index += 1;
+      if self.front_index == (*node).len {
+        self.front = (*node).next;
+        self.front_index = 0;
+      }
+      Some(elem)
+    }
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.len, Some(self.len))
+  }
+}
+
+impl<'a, T, const B: usize> DoubleEndedIterator for IterMut<'a, T, B> {
+  fn next_back(&mut self) -> Option<Self::Item> {
+    if self.len == 0 {
+      return None;
+    }
+
+    self.len -= 1;
+    unsafe {
+      let node = self.back;
+      self.back_index -= 1;
+      let elem = &mut *Node::raw_slot(node, self.back_index);
+      if self.back_index == 0 {
+        let prev = (*node).prev;
+        self.back = prev;
+        self.back_index = if prev.is_null() { 0 } else { (*prev).len };
+      }
+      Some(elem)
+    }
+  }
+}
+
+impl<'a, T, const B: usize> ExactSizeIterator for IterMut<'a, T, B> {}
+
+impl<T, const B: usize> Stack<T> for List<T, B> {
+  fn push(&mut self, elem: T) {
+    self.push_front(elem);
+  }
+
+  fn pop(&mut self) -> Option<T> {
+    self.pop_front()
+  }
+}
+
+impl<T, const B: usize> Queue<T> for List<T, B> {
+  fn enqueue(&mut self, elem: T) {
+    self.push_back(elem);
+  }
+
+  fn dequeue(&mut self) -> Option<T> {
+    self.pop_front()
+  }
+}
+
+impl<T, const B: usize> Deque<T> for List<T, B> {
+  fn push_front(&mut self, elem: T) {
+    List::push_front(self, elem);
+  }
+
+  fn push_back(&mut self, elem: T) {
+    List::push_back(self, elem);
+  }
+
+  fn pop_front(&mut self) -> Option<T> {
+    List::pop_front(self)
+  }
+
+  fn pop_back(&mut self) -> Option<T> {
+    List::pop_back(self)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::{List, Node};
+  use crate::model::deque_harness;
+  use crate::rng::Rng;
+  use crate::test_support::{assert_panic_safe, measure, DropCounter, Profile};
+  use crate::traits::conformance;
+  use std::panic::{self, AssertUnwindSafe};
+  use std::ptr;
+
+  // Checks the links and the block bookkeeping, returning how full each block
+  // is, front to back.
+  fn blocks<T, const B: usize>(list: &List<T, B>) -> Vec<usize> {
+    let mut lens = Vec::new();
+    let mut prev: *mut Node<T, B> = ptr::null_mut();
+    let mut node = list.head;
+
+    unsafe {
+      while !node.is_null() {
+        assert!(ptr::eq((*node).prev, prev), "block {} has the wrong `prev`", lens.len());
+        assert!((*node).len > 0, "block {} is empty", lens.len());
+        assert!((*node).start + (*node).len <= B, "block {} overflows", lens.len());
+        lens.push((*node).len);
+        prev = node;
+        node = (*node).next;
+      }
+    }
+
+    assert!(ptr::eq(list.tail, prev), "`tail` isn't the last block");
+    assert_eq!(lens.iter().sum::<usize>(), list.len());
+    lens
+  }
+
+  #[test]
+  fn basics() {
+    let mut list = List::<i32, 4>::new();
+    assert_eq!(list.pop_front(), None);
+    assert_eq!(list.pop_back(), None);
+
+    for i in 0..10 {
+      list.push_back(i);
+      list.push_front(-i);
+    }
+    assert_eq!(list.len(), 20);
+    assert_eq!(blocks(&list), [4, 4, 4, 4, 4]);
+
+    assert_eq!(list.peek_front(), Some(&-9));
+    assert_eq!(list.peek_back(), Some(&9));
+    *list.peek_front_mut().unwrap() = -90;
+    *list.peek_back_mut().unwrap() = 90;
+
+    assert_eq!(list.pop_front(), Some(-90));
+    assert_eq!(list.pop_back(), Some(90));
+    for i in (0..9).rev() {
+      assert_eq!(list.pop_front(), Some(-i));
+      assert_eq!(list.pop_back(), Some(i));
+    }
+    assert!(list.is_empty());
+    assert_eq!(blocks(&list), []);
+    assert_eq!(list.peek_front(), None);
+    assert_eq!(list.peek_back(), None);
+
+    // Pushing at one end of a block whose room is all at the other end.
+    list.push_front(2);
+    list.push_back(3);
+    list.push_back(4);
+    list.push_back(5);
+    assert_eq!(blocks(&list), [4]);
+    assert!(list.iter().eq(&[2, 3, 4, 5]));
+  }
+
+  #[test]
+  fn conformance() {
+    conformance::deque(List::<i32>::new);
+    conformance::queue(List::<i32>::new);
+    conformance::stack(List::<i32>::new);
+    conformance::deque(List::<i32, 2>::new);
+    conformance::stack_drops(List::<_, 4>::new);
+  }
+
+  #[test]
+  #[should_panic(expected = "at least two elements")]
+  fn blocks_of_one() {
+    List::<i32, 1>::new();
+  }
+
+  #[test]
+  fn iter() {
+    let mut list = List::<i32, 3>::new();
+    assert_eq!(list.iter().next(), None);
+    assert_eq!(list.iter_mut().next_back(), None);
+
+    for i in 0..10 {
+      list.push_back(i);
+    }
+    assert!(list.iter().copied().eq(0..10));
+    assert!(list.iter().rev().copied().eq((0..10).rev()));
+    assert_eq!(list.iter().len(), 10);
+
+    // From both ends until they meet, at every possible point.
+    for split in 0..=10 {
+      let mut iter = list.iter();
+      let front: Vec<_> = iter.by_ref().take(split).copied().collect();
+      let back: Vec<_> = iter.rev().copied().collect();
+      assert_eq!(front, (0..split as i32).collect::<Vec<_>>());
+      assert_eq!(back, (split as i32..10).rev().collect::<Vec<_>>());
+    }
+
+    let mut iter = list.iter_mut();
+    while let (Some(front), Some(back)) = (iter.next(), iter.next_back()) {
+      std::mem::swap(front, back);
+    }
+    assert!(list.iter().eq((0..10).rev().collect::<Vec<_>>().iter()));
+
+    let mut iter = list.into_iter();
+    assert_eq!(iter.len(), 10);
+    assert_eq!(iter.next(), Some(9));
+    assert_eq!(iter.next_back(), Some(0));
+    assert_eq!(iter.collect::<Vec<_>>(), [8, 7, 6, 5, 4, 3, 2, 1]);
+  }
+
+  #[test]
+  fn splits_and_merges() {
+    let mut list = List::<i32, 4>::new();
+    for i in 0..8 {
+      list.push_back(i * 10);
+    }
+    assert_eq!(blocks(&list), [4, 4]);
+
+    // A full block splits in half, and the new element goes in either one.
+    list.insert(1, 5);
+    assert_eq!(blocks(&list), [3, 2, 4]);
+    list.insert(6, 45);
+    assert_eq!(blocks(&list), [3, 2, 3, 2]);
+    assert!(list.iter().eq(&[0, 5, 10, 20, 30, 40, 45, 50, 60, 70]));
+
+    // Less than half full and fitting with the next block: merged.
+    assert_eq!(list.remove(4), Some(30));
+    assert_eq!(blocks(&list), [3, 4, 2]);
+    // The last block merges with the one before, if it can.
+    assert_eq!(list.remove(8), Some(70));
+    assert_eq!(blocks(&list), [3, 4, 1]);
+    assert_eq!(list.remove(7), Some(60));
+    assert_eq!(blocks(&list), [3, 4]);
+    assert!(list.iter().eq(&[0, 5, 10, 20, 40, 45, 50]));
+
+    assert_eq!(list.remove(7), None);
+    list.insert(7, 99);
+    list.insert(0, -1);
+    assert!(list.iter().eq(&[-1, 0, 5, 10, 20, 40, 45, 50, 99]));
+  }
+
+  #[test]
+  #[should_panic(expected = "insertion index 4 is past the end of a list of 3")]
+  fn insert_past_the_end() {
+    let mut list = List::<i32>::new();
+    list.push_back(1);
+    list.push_back(2);
+    list.push_back(3);
+    list.insert(4, 4);
+  }
+
+  fn against_vec<const B: usize>(seed: u64) {
+    let mut rng = Rng::new(seed);
+    let mut list = List::<u32, B>::new();
+    let mut vec = Vec::new();
+
+    for step in 0..3_000 {
+      let value = rng.next_u64() as u32;
+      match rng.below(8) {
+        0 => {
+          list.push_front(value);
+          vec.insert(0, value);
+        }
+        1 => {
+          list.push_back(value);
+          vec.push(value);
+        }
+        2 => assert_eq!(list.pop_front(), (!vec.is_empty()).then(|| vec.remove(0))),
+        3 => assert_eq!(list.pop_back(), vec.pop()),
+        4 | 5 => {
+          let index = rng.below(vec.len() + 1);
+          list.insert(index, value);
+          vec.insert(index, value);
+        }
+        6 => {
+          let index = rng.below(vec.len() + 1);
+          let expected = if index < vec.len() { Some(vec.remove(index)) } else { None };
+          assert_eq!(list.remove(index), expected, "step {}", step);
+        }
+        _ => {
+          let index = rng.below(vec.len() + 1);
+          if let Some(elem) = list.get_mut(index) {
+            *elem = value;
+            vec[index] = value;
+          }
+          assert_eq!(list.get(index), vec.get(index));
+        }
+      }
+
+      blocks(&list);
+      assert_eq!(list.len(), vec.len());
+    }
+
+    assert!(list.iter().eq(vec.iter()));
+    assert!(list.iter().rev().eq(vec.iter().rev()));
+  }
+
+  #[test]
+  fn against_brute_force() {
+    against_vec::<2>(46);
+    against_vec::<3>(47);
+    against_vec::<4>(48);
+    against_vec::<16>(49);
+  }
+
+  #[test]
+  fn model() {
+    deque_harness!("unrolled", List::<i32, 4>::new, "let mut list = List::<i32, 4>::new();").assert(500, 100);
+  }
+
+  #[test]
+  fn allocations() {
+    let (mut list, profile) = measure(List::<u8, 8>::new);
+    assert_eq!(profile, Profile::default());
+
+    // One block per eight elements.
+    let ((), profile) = measure(|| {
+      for i in 0..20 {
+        list.push_back(i);
+      }
+    });
+    assert_eq!((profile.allocations, profile.deallocations), (3, 0));
+
+    let ((), profile) = measure(|| {
+      assert_eq!(list.get(13), Some(&13));
+      *list.get_mut(3).unwrap() += 100;
+      assert_eq!(list.iter().count(), 20);
+      list.iter_mut().for_each(|elem| *elem += 1);
+      // Into the last block, which has room.
+      list.insert(18, 0);
+      assert_eq!(list.remove(18), Some(0));
+    });
+    assert_eq!(profile, Profile::default());
+
+    // A block goes when its last element does.
+    let ((), profile) = measure(|| {
+      for _ in 0..8 {
+        list.pop_front();
+      }
+    });
+    assert_eq!((profile.allocations, profile.deallocations), (0, 1));
+
+    let ((), profile) = measure(|| drop(list));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 2));
+  }
+
+  #[test]
+  fn panic_safe_drops() {
+    let fill = |counter: &DropCounter| {
+      let mut list = List::<_, 4>::new();
+      for _ in 0..10 {
+        list.push_back(counter.elem());
+      }
+      list
+    };
+
+    assert_panic_safe(10, fill, |mut list| list.clear());
+    assert_panic_safe(10, fill, |list| {
+      let mut iter = list.into_iter();
+      iter.next_back();
+    });
+
+    let counter = DropCounter::panicking_on(3);
+    let mut list = fill(&counter);
+    assert!(panic::catch_unwind(AssertUnwindSafe(|| list.clear())).is_err());
+    assert!(list.is_empty() && list.peek_front().is_none());
+    assert_eq!(counter.live(), 0);
+    list.push_back(counter.elem());
+    assert_eq!(blocks(&list), [1]);
+  }
+}
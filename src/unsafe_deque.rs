@@ -1,8 +1,15 @@
-use std::ptr;
-
-pub struct List<T> {
+use alloc::boxed::Box;
+use core::ptr;
+#[cfg(any(debug_assertions, feature = "invariants"))]
+use crate::invariants::{self, InvariantError};
+use crate::drop_guard;
+use crate::observer::{Event, NoObserver, Observer};
+use crate::traits::{Deque, Queue, Stack};
+
+pub struct List<T, O = NoObserver> {
   head: Link<T>,
   tail: *mut Node<T>,
+  observer: O,
 }
 
 type Link<T> = Option<Box<Node<T>>>;
@@ -15,12 +22,59 @@ struct Node<T> {
 
 impl<T> List<T> {
   pub fn new() -> Self {
+    List::with_observer(NoObserver)
+  }
+
+  // Mutable access that bypasses the observer is only handed out when nobody
+  // is watching; observed lists go through `update`.
+  pub fn peek_front_mut(&mut self) -> Option<&mut T> {
+    self.head.as_mut().map(|node| {
+      &mut node.elem
+    })
+  }
+
+  pub fn peek_back_mut(&mut self) -> Option<&mut T> {
+    if self.tail.is_null() {
+      None
+    } else {
+      unsafe {
+        Some(&mut (*self.tail).elem)
+      }
+    }
+  }
+
+  pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+    IterMut {
+      has_iterated_over_all_elements: self.head.is_none() && self.tail.is_null(),
+      next: self.head.as_mut().map(|node| &mut **node),
+      prev: unsafe {
+        if self.tail.is_null() {
+          None
+        } else {
+          Some(&mut *self.tail)
+        }
+      },
+    }
+  }
+}
+
+impl<T, O: Observer<T>> List<T, O> {
+  pub fn with_observer(observer: O) -> Self {
     List {
       head: None,
       tail: ptr::null_mut(),
+      observer,
     }
   }
 
+  pub fn observer(&self) -> &O {
+    &self.observer
+  }
+
+  pub fn observer_mut(&mut self) -> &mut O {
+    &mut self.observer
+  }
+
   pub fn push_front(&mut self, elem: T) {
     let mut new_head = Box::new(Node {
       elem: elem,
@@ -41,10 +95,15 @@ impl<T> List<T> {
     }
 
     self.head = Some(new_head);
+    self.after_mutation();
+    // `raw_head` went stale when the box moved into `head`.
+    if let Some(head) = self.head.as_deref() {
+      self.observer.notify(Event::PushedFront(&head.elem));
+    }
   }
 
   pub fn pop_front(&mut self) -> Option<T> {
-    self.head.take().map(|mut old_head| {
+    let elem = self.head.take().map(|mut old_head| {
       match old_head.next.take() {
         None => {
           self.tail = ptr::null_mut();
@@ -56,7 +115,13 @@ impl<T> List<T> {
       }
 
       old_head.elem
-    })
+    });
+
+    self.after_mutation();
+    if elem.is_some() {
+      self.observer.notify(Event::PoppedFront);
+    }
+    elem
   }
 
   pub fn push_back(&mut self, elem: T) {
@@ -76,6 +141,9 @@ impl<T> List<T> {
         prev.next = Some(new_tail);
       }
     }
+
+    self.after_mutation();
+    self.observer.notify(Event::PushedBack(unsafe { &(*self.tail).elem }));
   }
 
   pub fn pop_back(&mut self) -> Option<T> {
@@ -97,21 +165,44 @@ impl<T> List<T> {
       }
     }
 
+    self.after_mutation();
+    self.observer.notify(Event::PoppedBack);
     pop_node.map(|node| node.elem)
   }
 
+  pub fn clear(&mut self) {
+    self.drop_nodes();
+    self.after_mutation();
+    self.observer.notify(Event::Cleared);
+  }
+
+  // Runs `f` on the element at `index`, counting from the front, and reports
+  // the change. Returns `None` if there's no such element.
+  pub fn update<R, F: FnOnce(&mut T) -> R>(&mut self, index: usize, f: F) -> Option<R> {
+    let mut cur_link = self.head.as_mut();
+    for _ in 0..index {
+      cur_link = cur_link?.next.as_mut();
+    }
+
+    let node = cur_link?;
+    let result = f(&mut node.elem);
+    self.observer.notify(Event::Mutated(index, &node.elem));
+    self.after_mutation();
+    Some(result)
+  }
+
+  pub fn into_iter(self) -> IntoIter<T, O> {
+    IntoIter(self)
+  }
+}
+
+impl<T, O> List<T, O> {
   pub fn peek_front(&self) -> Option<&T> {
     self.head.as_ref().map(|node| {
       &node.elem
     })
   }
 
-  pub fn peek_front_mut(&mut self) -> Option<&mut T> {
-    self.head.as_mut().map(|node| {
-      &mut node.elem
-    })
-  }
-
   pub fn peek_back(&mut self) -> Option<&T> {
     if self.tail.is_null() {
       None
@@ -122,20 +213,6 @@ impl<T> List<T> {
     }
   }
 
-  pub fn peek_back_mut(&mut self) -> Option<&mut T> {
-    if self.tail.is_null() {
-      None
-    } else {
-      unsafe {
-        Some(&mut (*self.tail).elem)
-      }
-    }
-  }
-
-  pub fn into_iter(self) -> IntoIter<T> {
-    IntoIter(self)
-  }
-
   pub fn iter(&self) -> Iter<'_, T> {
     Iter {
       has_iterated_over_all_elements: self.head.is_none() && self.tail.is_null(),
@@ -150,33 +227,79 @@ impl<T> List<T> {
     }
   }
 
-  pub fn iter_mut(&mut self) -> IterMut<'_, T> {
-    IterMut {
-      has_iterated_over_all_elements: self.head.is_none() && self.tail.is_null(),
-      next: self.head.as_mut().map(|node| &mut **node),
-      prev: unsafe {
-        if self.tail.is_null() {
-          None
-        } else {
-          Some(&mut *self.tail)
+  // Walks the whole list checking that the raw pointers agree with the boxes:
+  // `tail` is the last node, every `prev` is the node before, and following
+  // `next` ends. It can only report what it can reach without crashing.
+  #[cfg(any(debug_assertions, feature = "invariants"))]
+  pub fn check_invariants(&self) -> Result<(), InvariantError> {
+    let head = match &self.head {
+      None if self.tail.is_null() => return Ok(()),
+      None => return Err(InvariantError::EmptyMismatch { head_is_none: true }),
+      Some(_) if self.tail.is_null() => return Err(InvariantError::EmptyMismatch { head_is_none: false }),
+      Some(head) => head,
+    };
+
+    if let Some(index) = invariants::find_cycle(&**head, |node| node.next.as_deref()) {
+      return Err(InvariantError::Cycle { index });
+    }
+    if !head.prev.is_null() {
+      return Err(InvariantError::HeadHasPrev);
+    }
+
+    let mut prev: *const Node<T> = ptr::null();
+    let mut cur: &Node<T> = head;
+    let mut index = 0;
+
+    loop {
+      if !ptr::eq(cur.prev, prev) {
+        return Err(InvariantError::BrokenPrev { index });
+      }
+
+      match &cur.next {
+        Some(next) => {
+          prev = cur;
+          cur = next;
+          index += 1;
         }
-      },
+        None => break,
+      }
+    }
+
+    if !ptr::eq(cur, self.tail) {
+      return Err(InvariantError::TailNotLast { len: index + 1 });
+    }
+    Ok(())
+  }
+
+  // Called at the end of every mutating method.
+  fn after_mutation(&self) {
+    #[cfg(feature = "strict-invariants")]
+    if let Err(error) = self.check_invariants() {
+      panic!("broken `unsafe_deque::List`: {}", error);
     }
   }
+
+  fn drop_nodes(&mut self) {
+    self.tail = ptr::null_mut();
+    drop_guard::drain(&mut self.head, |head| match head.take() {
+      Some(mut node) => {
+        *head = node.next.take();
+        true
+      }
+      None => false,
+    });
+  }
 }
 
-impl<T> Drop for List<T> {
+impl<T, O> Drop for List<T, O> {
   fn drop(&mut self) {
-    let mut cur_link = self.head.take();
-    while let Some(mut boxed_node) = cur_link {
-      cur_link = boxed_node.next.take();
-    }
+    self.drop_nodes();
   }
 }
 
-pub struct IntoIter<T>(List<T>);
+pub struct IntoIter<T, O = NoObserver>(List<T, O>);
 
-impl<T> Iterator for IntoIter<T> {
+impl<T, O: Observer<T>> Iterator for IntoIter<T, O> {
   type Item = T;
 
   fn next(&mut self) -> Option<Self::Item> {
@@ -184,7 +307,7 @@ impl<T> Iterator for IntoIter<T> {
   }
 }
 
-impl<T> DoubleEndedIterator for IntoIter<T> {
+impl<T, O: Observer<T>> DoubleEndedIterator for IntoIter<T, O> {
   fn next_back(&mut self) -> Option<Self::Item> {
     self.0.pop_back()
   }
@@ -301,9 +424,52 @@ impl<'a, T> DoubleEndedIterator for IterMut<'a, T> {
 }
   
 
+impl<T, O: Observer<T>> Stack<T> for List<T, O> {
+  fn push(&mut self, elem: T) {
+    self.push_front(elem);
+  }
+
+  fn pop(&mut self) -> Option<T> {
+    self.pop_front()
+  }
+}
+
+impl<T, O: Observer<T>> Queue<T> for List<T, O> {
+  fn enqueue(&mut self, elem: T) {
+    self.push_back(elem);
+  }
+
+  fn dequeue(&mut self) -> Option<T> {
+    self.pop_front()
+  }
+}
+
+impl<T, O: Observer<T>> Deque<T> for List<T, O> {
+  fn push_front(&mut self, elem: T) {
+    List::push_front(self, elem);
+  }
+
+  fn push_back(&mut self, elem: T) {
+    List::push_back(self, elem);
+  }
+
+  fn pop_front(&mut self) -> Option<T> {
+    List::pop_front(self)
+  }
+
+  fn pop_back(&mut self) -> Option<T> {
+    List::pop_back(self)
+  }
+}
+
 #[cfg(test)]
 mod test {
   use super::List;
+  use crate::model::deque_harness;
+  use crate::observer::NoObserver;
+  use crate::test_support::{assert_panic_safe, measure, DropCounter, Profile};
+  use crate::traits::conformance;
+  use std::panic::{self, AssertUnwindSafe};
 
   #[test]
   fn basics_front() {
@@ -592,4 +758,223 @@ mod test {
     assert_eq!(iter.next(), None);
     assert_eq!(iter.next_back(), None);
   }
+
+  #[test]
+  fn unobserved_lists_pay_nothing() {
+    use std::mem::size_of;
+
+    assert_eq!(size_of::<List<i32>>(), 2 * size_of::<usize>());
+  }
+
+  #[test]
+  fn observer() {
+    use crate::observer::Mirror;
+
+    type Op = fn(&mut List<i32, Mirror<i32>>);
+
+    let ops: Vec<Op> = vec![
+      |list| list.push_back(1),
+      |list| list.push_back(2),
+      |list| list.push_front(3),
+      |list| { list.pop_back(); },
+      |list| list.push_back(4),
+      |list| list.push_front(5),
+      |list| { list.pop_front(); },
+      |list| { list.update(1, |elem| *elem *= 10); },
+      |list| { list.update(3, |elem| *elem *= 10); },
+      |list| list.clear(),
+      |list| { list.pop_back(); },
+      |list| { list.pop_front(); },
+      |list| list.push_front(6),
+      |list| { list.update(0, |elem| *elem += 1); },
+      |list| { list.pop_front(); },
+      |list| list.push_back(7),
+      |list| list.push_front(8),
+    ];
+
+    let mut list = List::with_observer(Mirror::new());
+
+    for op in ops {
+      op(&mut list);
+      assert!(list.iter().eq(list.observer().elems.iter()));
+    }
+  }
+
+  #[test]
+  fn observer_closure() {
+    use crate::observer::Event;
+
+    let mut log = Vec::new();
+    {
+      let mut list = List::with_observer(|event: Event<'_, i32>| {
+        log.push(format!("{:?}", event));
+      });
+
+      list.push_front(1);
+      list.push_back(2);
+      list.pop_front();
+      list.pop_front();
+      list.pop_back();
+      list.update(0, |elem| *elem += 1);
+      list.push_back(3);
+      list.update(0, |elem| *elem += 1);
+      list.clear();
+    }
+
+    assert_eq!(log, [
+      "PushedFront(1)", "PushedBack(2)", "PoppedFront", "PoppedFront",
+      "PushedBack(3)", "Mutated(0, 4)", "Cleared",
+    ]);
+  }
+
+  #[test]
+  fn conformance() {
+    conformance::deque(List::new);
+    conformance::queue(List::new);
+    conformance::stack(List::new);
+    conformance::queue_allocations(List::new);
+    conformance::stack_allocations(List::new);
+    conformance::stack_drops(List::new);
+  }
+
+  #[cfg(any(debug_assertions, feature = "invariants"))]
+  #[test]
+  fn invariants() {
+    use crate::invariants::InvariantError;
+    use std::ptr;
+
+    let mut list = List::new();
+    assert_eq!(list.check_invariants(), Ok(()));
+
+    for i in 0..5 {
+      list.push_back(i);
+      list.push_front(-i);
+    }
+    list.pop_back();
+    list.pop_front();
+    assert_eq!(list.check_invariants(), Ok(()));
+
+    // Break one thing at a time, putting it back before the next.
+    let tail = list.tail;
+    let head: *mut _ = &mut **list.head.as_mut().unwrap();
+
+    list.tail = ptr::null_mut();
+    assert_eq!(list.check_invariants(), Err(InvariantError::EmptyMismatch { head_is_none: false }));
+    list.tail = head;
+    assert_eq!(list.check_invariants(), Err(InvariantError::TailNotLast { len: 8 }));
+    list.tail = tail;
+
+    unsafe {
+      (*head).prev = tail;
+      assert_eq!(list.check_invariants(), Err(InvariantError::HeadHasPrev));
+      (*head).prev = ptr::null_mut();
+
+      let prev = (*tail).prev;
+      (*tail).prev = head;
+      assert_eq!(list.check_invariants(), Err(InvariantError::BrokenPrev { index: 7 }));
+      (*tail).prev = prev;
+    }
+    // Closing a cycle of `Box`es takes two of them owning one node, so
+    // `invariants::test::finds_cycles` covers that on plain indices.
+    assert_eq!(list.check_invariants(), Ok(()));
+
+    let mut empty = List::<i32>::new();
+    empty.tail = tail;
+    assert_eq!(empty.check_invariants(), Err(InvariantError::EmptyMismatch { head_is_none: true }));
+    empty.tail = ptr::null_mut();
+
+    assert_eq!(
+      InvariantError::BrokenPrev { index: 7 }.to_string(),
+      "node 7 has a `prev` that isn't node 6",
+    );
+  }
+
+  #[cfg(feature = "strict-invariants")]
+  #[test]
+  #[should_panic(expected = "the first node has a `prev`")]
+  fn strict_invariants() {
+    let mut list = List::new();
+    list.push_back(1);
+    list.push_back(2);
+
+    let head: *mut _ = &mut **list.head.as_mut().unwrap();
+    list.head.as_mut().unwrap().prev = head;
+    list.push_back(3);
+  }
+
+  #[test]
+  fn model() {
+    deque_harness!("unsafe_deque", List::new, "let mut list = List::new();").assert(500, 100);
+  }
+
+  #[test]
+  fn allocations() {
+    let mut list = List::with_observer(NoObserver);
+    list.push_front(2);
+    list.push_back(3);
+    list.push_front(1);
+    list.push_back(4);
+
+    let ((), profile) = measure(|| {
+      assert_eq!(list.peek_front(), Some(&1));
+      assert_eq!(list.peek_back(), Some(&4));
+      *list.peek_front_mut().unwrap() += 10;
+      *list.peek_back_mut().unwrap() += 10;
+      assert_eq!(list.update(1, |elem| *elem *= 10), Some(()));
+      assert_eq!(list.iter().count(), 4);
+      list.iter_mut().for_each(|elem| *elem += 1);
+      let _ = list.observer();
+      let _ = list.observer_mut();
+    });
+    assert_eq!(profile, Profile::default());
+
+    #[cfg(any(debug_assertions, feature = "invariants"))]
+    assert_eq!(measure(|| list.check_invariants()), (Ok(()), Profile::default()));
+
+    assert_eq!(list.pop_front(), Some(12));
+    let (elem, profile) = measure(|| list.pop_back());
+    assert_eq!(elem, Some(15));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 1));
+
+    let (mut iter, profile) = measure(|| list.into_iter());
+    assert_eq!(profile, Profile::default());
+    let (elem, profile) = measure(|| iter.next_back());
+    assert_eq!(elem, Some(4));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 1));
+    let ((), profile) = measure(|| drop(iter));
+    assert_eq!((profile.allocations, profile.deallocations), (0, 1));
+
+    let mut list = List::new();
+    for i in 0..10 {
+      list.push_back(i);
+    }
+    let ((), profile) = measure(|| list.clear());
+    assert_eq!((profile.allocations, profile.deallocations), (0, 10));
+  }
+
+  #[test]
+  fn panic_safe_drops() {
+    let fill = |counter: &DropCounter| {
+      let mut list = List::new();
+      for _ in 0..5 {
+        list.push_back(counter.elem());
+      }
+      list
+    };
+
+    assert_panic_safe(5, fill, |mut list| list.clear());
+    assert_panic_safe(5, fill, |list| {
+      let mut iter = list.into_iter();
+      iter.next_back();
+    });
+
+    // A panicking `clear` still leaves an empty, usable list behind.
+    let counter = DropCounter::panicking_on(2);
+    let mut list = fill(&counter);
+    assert!(panic::catch_unwind(AssertUnwindSafe(|| list.clear())).is_err());
+    assert!(list.peek_front().is_none() && list.peek_back().is_none());
+    assert_eq!(counter.live(), 0);
+    list.push_back(counter.elem());
+    assert!(list.peek_front().is_some());
+  }
 }
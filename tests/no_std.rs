@@ -0,0 +1,37 @@
+use std::path::Path;
+use std::process::Command;
+
+// Runs cargo on the library with `--no-default-features`, which makes it
+// `#![no_std]`, so anything reaching for `std` outside the modules gated on it
+// fails here instead of in a firmware build.
+fn cargo_without_std(command: &[&str], features: &[&str]) {
+  let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+  // The target directory of the `cargo test` running this is locked.
+  let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("no_std");
+
+  let output = Command::new(env!("CARGO"))
+    .args(command)
+    .args(["--lib", "--no-default-features", "--features", &features.join(",")])
+    .arg("--manifest-path").arg(manifest)
+    .arg("--target-dir").arg(target_dir)
+    .output()
+    .expect("couldn't run cargo");
+
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn builds_without_std() {
+  cargo_without_std(&["build"], &[]);
+}
+
+#[test]
+fn builds_without_std_with_strict_invariants() {
+  cargo_without_std(&["build"], &["strict-invariants"]);
+}
+
+// The unit tests of the modules that don't need `std` should build too.
+#[test]
+fn tests_build_without_std() {
+  cargo_without_std(&["test", "--no-run"], &[]);
+}
@@ -0,0 +1,107 @@
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+// Golden-file tests for the `rpn` binary. Every `tests/rpn/NAME.rpn` runs as
+// `rpn NAME.rpn` from that directory, and what it prints has to match
+// `tests/rpn/NAME.out` exactly. To accept new output after changing the
+// calculator on purpose:
+//
+//   UPDATE_GOLDEN=1 cargo test --test rpn
+
+fn cases() -> PathBuf {
+  Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("rpn")
+}
+
+fn rpn(args: &[&str], stdin: Option<&str>) -> Output {
+  let mut child = Command::new(env!("CARGO_BIN_EXE_rpn"))
+    .args(args)
+    .current_dir(cases())
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .expect("couldn't run rpn");
+
+  // Dropping it closes stdin, so `rpn` isn't left waiting.
+  let mut input = child.stdin.take().unwrap();
+  input.write_all(stdin.unwrap_or("").as_bytes()).unwrap();
+  drop(input);
+
+  child.wait_with_output().unwrap()
+}
+
+// Stdout, then stderr and the exit status when there's something to say.
+fn transcript(output: &Output) -> String {
+  let mut transcript = String::from_utf8(output.stdout.clone()).unwrap();
+  if !output.stderr.is_empty() {
+    transcript += "--- stderr\n";
+    transcript += &String::from_utf8(output.stderr.clone()).unwrap();
+  }
+  if !output.status.success() {
+    transcript += &format!("--- exit status {}\n", output.status.code().unwrap());
+  }
+  transcript
+}
+
+#[test]
+fn golden() {
+  let update = env::var_os("UPDATE_GOLDEN").is_some();
+  let mut scripts: Vec<_> = fs::read_dir(cases())
+    .unwrap()
+    .map(|entry| entry.unwrap().path())
+    .filter(|path| path.extension().is_some_and(|extension| extension == "rpn"))
+    .collect();
+  scripts.sort();
+  assert!(!scripts.is_empty());
+
+  let mut failed = Vec::new();
+  for script in scripts {
+    let name = script.file_name().unwrap().to_str().unwrap();
+    let actual = transcript(&rpn(&[name], None));
+    let golden = script.with_extension("out");
+
+    if update {
+      fs::write(&golden, &actual).unwrap();
+      continue;
+    }
+    let expected = fs::read_to_string(&golden).unwrap_or_default();
+    if actual != expected {
+      eprintln!("--- {} expected\n{}--- {} got\n{}", name, expected, name, actual);
+      failed.push(name.to_string());
+    }
+  }
+
+  assert!(failed.is_empty(), "output changed for {:?}; UPDATE_GOLDEN=1 accepts it", failed);
+}
+
+#[test]
+fn reads_stdin() {
+  let script = fs::read_to_string(cases().join("underflow.rpn")).unwrap();
+  let expected = fs::read_to_string(cases().join("underflow.out")).unwrap();
+
+  let output = rpn(&[], Some(&script));
+  assert_eq!(transcript(&output), expected.replace("underflow.rpn", "<stdin>"));
+  // `-` works too, between files.
+  let output = rpn(&["arithmetic.rpn", "-"], Some(&script));
+  assert!(transcript(&output).ends_with(&expected.replace("underflow.rpn", "<stdin>")));
+}
+
+#[test]
+fn files_run_as_one_program() {
+  let output = rpn(&["words.rpn", "-", "leftover.rpn"], Some("5 cube ."));
+  assert!(output.status.success());
+
+  let words = fs::read_to_string(cases().join("words.out")).unwrap();
+  let leftover = fs::read_to_string(cases().join("leftover.out")).unwrap();
+  assert_eq!(String::from_utf8(output.stdout).unwrap(), words + "125\n" + &leftover);
+}
+
+#[test]
+fn missing_file() {
+  let output = rpn(&["arithmetic.rpn", "nowhere.rpn"], None);
+  assert_eq!(output.status.code(), Some(1));
+  assert!(String::from_utf8(output.stderr).unwrap().starts_with("rpn: nowhere.rpn:"));
+}